@@ -0,0 +1,921 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use futures_util::{stream, Stream, StreamExt};
+use hyper::{
+    body::Buf,
+    header::{ALLOW, CONTENT_TYPE, WWW_AUTHENTICATE},
+    Body, Method, Response, StatusCode,
+};
+use log::{error, info, warn};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::{
+    audit_reports::{self, Finding},
+    config::{self, Syntax},
+    package_config::{self, Mode, PackageConfig, Packages},
+    package_requests, pep_503,
+    snapshots::{self, Snapshot},
+    upstream::Upstream,
+    ParsedPackageIndexes,
+};
+
+/// The largest package config the admin API accepts.
+const MAX_POLICY_SIZE: usize = 1024 * 1024;
+
+/// The largest set of package configs the admin API imports at once.
+const MAX_IMPORT_SIZE: usize = 16 * 1024 * 1024;
+
+/// The largest list of packages to snapshot the admin API accepts.
+const MAX_SNAPSHOT_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// The largest audit report the admin API accepts, big enough for safety's whole database.
+const MAX_AUDIT_REPORT_SIZE: usize = 64 * 1024 * 1024;
+
+/// What a package is blocked for when `POST /admin/block/{package}` doesn't say.
+const DEFAULT_BLOCK_REASON: &str = "blocked by an administrator";
+
+/// What a package is rejected for when `POST /admin/requests/{package}/reject` doesn't say.
+const DEFAULT_REJECT_REASON: &str = "rejected by an administrator";
+
+/// How many packages are captured into a snapshot at once.
+const SNAPSHOT_CONCURRENCY: usize = 8;
+
+/// Held while a package config (or a snapshot) is saved and applied,
+/// so that concurrent changes can't apply out of order or undo one another.
+static EDITS: Mutex<()> = Mutex::const_new(());
+
+fn respond(status: StatusCode, message: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+fn json<T: Serialize>(status: StatusCode, value: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(value).unwrap()))
+        .unwrap()
+}
+
+fn method_not_allowed(allow: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header(ALLOW, allow)
+        .body(Body::from("method not allowed"))
+        .unwrap()
+}
+
+/// Whether `authorization`, an `Authorization` header, carries `token`.
+/// Both are hashed before they're compared, so that how long the comparison takes
+/// doesn't give away how much of the token was right.
+pub fn is_authorized(token: &str, authorization: Option<&str>) -> bool {
+    match authorization.and_then(|authorization| authorization.strip_prefix("Bearer ")) {
+        Some(given) => Sha256::digest(given.trim()) == Sha256::digest(token),
+        None => false,
+    }
+}
+
+/// Why a request failed, to tell the client.
+type Failure = (StatusCode, String);
+
+/// Answers requests which don't carry the admin token, turning them away.
+/// Without one configured, there's no admin API at all.
+pub fn unauthorized(token: Option<&str>, authorization: Option<&str>) -> Option<Response<Body>> {
+    match token {
+        None => Some(respond(StatusCode::NOT_FOUND, "not found".to_owned())),
+        Some(token) if is_authorized(token, authorization) => None,
+        Some(_) => Some(
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(WWW_AUTHENTICATE, "Bearer")
+                .body(Body::from("unauthorized"))
+                .unwrap(),
+        ),
+    }
+}
+
+/// Reads a request body of up to `limit` bytes.
+async fn read_body<B: Buf>(
+    mut body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+    limit: usize,
+) -> Result<Vec<u8>, Failure> {
+    let mut bytes = vec![];
+    while let Some(chunk) = body.next().await {
+        let mut chunk = chunk.map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("failed to read the request: {e}"),
+            )
+        })?;
+        if bytes.len() + chunk.remaining() > limit {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("the request can be at most {limit} bytes"),
+            ));
+        }
+        bytes.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    Ok(bytes)
+}
+
+/// Reopens the package config store, so that a change saved to it applies from now on.
+fn apply(upstream: &Upstream) -> Result<(), Failure> {
+    match package_config::open(upstream.config()) {
+        Ok(package_configs) => {
+            upstream.set_package_configs(package_configs);
+            Ok(())
+        }
+        Err(e) => {
+            error!("failed to reload package configs: {e}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("saved, but failed to reload package configs: {e}"),
+            ))
+        }
+    }
+}
+
+/// `GET` lists every package config by normalized package name,
+/// as they're applied, i.e. with the templates they extend resolved.
+pub async fn handle_policies(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    method: Method,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    if method != Method::GET {
+        return method_not_allowed("GET");
+    }
+
+    match upstream.package_configs().packages().await {
+        Ok(packages) => json(
+            StatusCode::OK,
+            &packages.into_iter().collect::<BTreeMap<_, _>>(),
+        ),
+        Err(e) => respond(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// `GET` fetches a package's config, `PUT` creates or replaces it with the JSON in the body,
+/// and `DELETE` removes it.
+/// Changes are saved to the package config store, and apply to every request from then on.
+pub async fn handle_policy<B: Buf>(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    package: String,
+    method: Method,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    let package = match percent_decode_str(&package).decode_utf8() {
+        Ok(package) if pep_503::is_valid_project_name(&package) => pep_503::normalize(&package),
+        _ => return respond(StatusCode::BAD_REQUEST, "invalid package name".to_owned()),
+    };
+
+    let result = match method {
+        Method::GET => get_policy(&upstream, &package).await,
+        Method::PUT => put_policy(&upstream, &package, body).await,
+        Method::DELETE => delete_policy(&upstream, &package).await,
+        _ => return method_not_allowed("GET, PUT, DELETE"),
+    };
+    result.unwrap_or_else(|(status, message)| respond(status, message))
+}
+
+async fn get_policy(upstream: &Upstream, package: &str) -> Result<Response<Body>, Failure> {
+    let packages = upstream
+        .package_configs()
+        .packages()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    match packages.get(package) {
+        Some(config) => Ok(json(StatusCode::OK, config)),
+        None => Err((StatusCode::NOT_FOUND, format!("{package} has no config"))),
+    }
+}
+
+/// Reads a package config out of a request body, along with it as it's applied.
+fn parse_policy(
+    upstream: &Upstream,
+    body: &[u8],
+) -> Result<(PackageConfig, PackageConfig), Failure> {
+    let config = serde_json::from_slice::<PackageConfig>(body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid config: {e}")))?;
+    let resolved = config
+        .validate()
+        .and_then(|()| {
+            config
+                .clone()
+                .resolve(&upstream.config().package_config_templates)
+        })
+        .map_err(|(key, e)| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("invalid config: {key}: {e}"),
+            )
+        })?;
+    Ok((config, resolved))
+}
+
+async fn put_policy<B: Buf>(
+    upstream: &Upstream,
+    package: &str,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Result<Response<Body>, Failure> {
+    let body = read_body(body, MAX_POLICY_SIZE).await?;
+    let (config, resolved) = parse_policy(upstream, &body)?;
+
+    let _edit = EDITS.lock().await;
+    let existed = package_config::save(upstream.config(), package, &config)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    apply(upstream)?;
+    info!(
+        "PUT /admin/policies/{package}: {} its config",
+        if existed { "replaced" } else { "created" }
+    );
+    let status = if existed {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok(json(status, &resolved))
+}
+
+async fn delete_policy(upstream: &Upstream, package: &str) -> Result<Response<Body>, Failure> {
+    let _edit = EDITS.lock().await;
+    let existed = package_config::remove(upstream.config(), package)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if !existed {
+        return Err((StatusCode::NOT_FOUND, format!("{package} has no config")));
+    }
+    apply(upstream)?;
+    info!("DELETE /admin/policies/{package}: removed its config");
+    Ok(respond(StatusCode::NO_CONTENT, String::new()))
+}
+
+/// `GET` renders every package config as one document, shaped like a `package_config_file`,
+/// in JSON, or in TOML or YAML if `Accept` asks for either.
+/// They're exported as they're applied, so that they don't depend on any templates.
+pub async fn handle_export(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    accept: Option<String>,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    info!("GET /admin/policies/export");
+
+    let syntax = accept
+        .as_deref()
+        .and_then(|accept| accept.split(',').find_map(Syntax::from_media_type))
+        .unwrap_or(Syntax::Json);
+    let rendered = upstream
+        .package_configs()
+        .packages()
+        .await
+        .and_then(|packages| {
+            let packages = Packages {
+                packages: packages.into_iter().collect(),
+            };
+            config::render(&packages, syntax)
+        });
+    match rendered {
+        Ok(rendered) => Response::builder()
+            .header(CONTENT_TYPE, syntax.media_type())
+            .body(Body::from(rendered))
+            .unwrap(),
+        Err(e) => respond(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// `POST` replaces every package config with the ones in the body,
+/// a document like [`handle_export`]'s in whichever syntax `Content-Type` says (JSON by default).
+/// Nothing changes unless every one of them is valid.
+pub async fn handle_import<B: Buf>(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    content_type: Option<String>,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    import(&upstream, content_type.as_deref(), body)
+        .await
+        .unwrap_or_else(|(status, message)| respond(status, message))
+}
+
+async fn import<B: Buf>(
+    upstream: &Upstream,
+    content_type: Option<&str>,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Result<Response<Body>, Failure> {
+    let syntax = match content_type {
+        Some(content_type) => Syntax::from_media_type(content_type).ok_or_else(|| {
+            (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("can't import {content_type}"),
+            )
+        })?,
+        None => Syntax::Json,
+    };
+    let body = read_body(body, MAX_IMPORT_SIZE).await?;
+    let body = std::str::from_utf8(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid configs: {e}")))?;
+    let packages = config::parse_as::<Packages>(body, syntax)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid configs: {e}")))?;
+
+    let templates = &upstream.config().package_config_templates;
+    let mut normalized = HashSet::new();
+    let mut errors = vec![];
+    for (package, config) in packages.packages.iter() {
+        if !pep_503::is_valid_project_name(package) {
+            errors.push(format!("invalid package name: {package}"));
+            continue;
+        }
+        if !normalized.insert(pep_503::normalize(package)) {
+            errors.push(format!("{package} has more than one config"));
+        }
+        let resolved = config
+            .validate()
+            .and_then(|()| config.clone().resolve(templates));
+        if let Err((key, e)) = resolved {
+            errors.push(format!("invalid config: packages.{package}.{key}: {e}"));
+        }
+    }
+    if !errors.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, errors.join("\n")));
+    }
+
+    let _edit = EDITS.lock().await;
+    let old = upstream
+        .package_configs()
+        .packages()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    package_config::save_all(upstream.config(), packages)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    apply(upstream)?;
+    let new = upstream
+        .package_configs()
+        .packages()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let changes = package_config::describe_changes(&old, &new)
+        .unwrap_or_else(|| "no package configs changed".to_owned());
+    info!("POST /admin/policies/import: {changes}");
+    Ok(respond(StatusCode::OK, changes))
+}
+
+/// Where snapshots are kept, or why there can't be any.
+fn snapshot_dir(upstream: &Upstream) -> Result<PathBuf, Failure> {
+    upstream.config().snapshot_dir.clone().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "there are no snapshots without a snapshot_dir".to_owned(),
+        )
+    })
+}
+
+/// A snapshot, without the releases it keeps.
+#[derive(Serialize)]
+struct SnapshotSummary {
+    #[serde(with = "humantime_serde")]
+    taken_at: SystemTime,
+    packages: Vec<String>,
+}
+
+impl From<&Snapshot> for SnapshotSummary {
+    fn from(snapshot: &Snapshot) -> Self {
+        Self {
+            taken_at: snapshot.taken_at,
+            packages: snapshot.packages.keys().cloned().collect(),
+        }
+    }
+}
+
+/// `GET` lists every snapshot by name, with when it was taken and which packages it captured.
+pub async fn handle_snapshots(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    method: Method,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    if method != Method::GET {
+        return method_not_allowed("GET");
+    }
+
+    let listed = snapshot_dir(&upstream)
+        .and_then(|dir| snapshots::list(&dir).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e)));
+    match listed {
+        Ok(snapshots) => json(
+            StatusCode::OK,
+            &snapshots
+                .iter()
+                .map(|(name, snapshot)| (name, SnapshotSummary::from(snapshot)))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+        Err((status, message)) => respond(status, message),
+    }
+}
+
+/// The body of a `PUT /admin/snapshots/{name}`.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SnapshotRequest {
+    /// Packages to keep exactly as they're served right now.
+    /// Every other package is served as of when the snapshot was taken.
+    packages: Vec<String>,
+}
+
+/// `GET` fetches a snapshot, `PUT` takes one, capturing the packages listed in the body
+/// (e.g. `{"packages": ["numpy"]}`, or nothing to capture none), and `DELETE` removes one.
+/// Snapshots can't be replaced, only removed and taken again.
+pub async fn handle_snapshot<B: Buf>(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    parsed: Arc<ParsedPackageIndexes>,
+    name: String,
+    method: Method,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    if !snapshots::is_valid_name(&name) {
+        return respond(StatusCode::BAD_REQUEST, "invalid snapshot name".to_owned());
+    }
+
+    let result = match method {
+        Method::GET => get_snapshot(&upstream, &name),
+        Method::PUT => put_snapshot(&upstream, &parsed, &name, body).await,
+        Method::DELETE => delete_snapshot(&upstream, &name),
+        _ => return method_not_allowed("GET, PUT, DELETE"),
+    };
+    result.unwrap_or_else(|(status, message)| respond(status, message))
+}
+
+fn get_snapshot(upstream: &Upstream, name: &str) -> Result<Response<Body>, Failure> {
+    let dir = snapshot_dir(upstream)?;
+    match snapshots::load(&dir, name).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))? {
+        Some(snapshot) => Ok(json(StatusCode::OK, &snapshot)),
+        None => Err((StatusCode::NOT_FOUND, format!("no snapshot called {name}"))),
+    }
+}
+
+async fn put_snapshot<B: Buf>(
+    upstream: &Upstream,
+    parsed: &ParsedPackageIndexes,
+    name: &str,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Result<Response<Body>, Failure> {
+    let dir = snapshot_dir(upstream)?;
+    let body = read_body(body, MAX_SNAPSHOT_REQUEST_SIZE).await?;
+    let request = if body.is_empty() {
+        SnapshotRequest::default()
+    } else {
+        serde_json::from_slice::<SnapshotRequest>(&body)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid request: {e}")))?
+    };
+    let mut packages = BTreeSet::new();
+    for package in request.packages {
+        if !pep_503::is_valid_project_name(&package) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("invalid package name: {package}"),
+            ));
+        }
+        packages.insert(pep_503::normalize(&package));
+    }
+    let exists = || {
+        (
+            StatusCode::CONFLICT,
+            format!("there's already a snapshot called {name}"),
+        )
+    };
+    // before capturing anything, which can take a while
+    if snapshots::load(&dir, name)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .is_some()
+    {
+        return Err(exists());
+    }
+
+    let taken_at = SystemTime::now();
+    let captured = stream::iter(packages)
+        .map(|package| async move {
+            let releases = snapshots::capture(upstream, parsed, &package).await;
+            (package, releases)
+        })
+        .buffer_unordered(SNAPSHOT_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    let mut snapshot = Snapshot {
+        taken_at,
+        packages: BTreeMap::new(),
+    };
+    let mut errors = vec![];
+    for (package, releases) in captured {
+        match releases {
+            Ok(releases) => {
+                snapshot.packages.insert(package, releases);
+            }
+            Err(e) => errors.push(format!("{package}: {e}")),
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort();
+        return Err((StatusCode::BAD_GATEWAY, errors.join("\n")));
+    }
+
+    let _edit = EDITS.lock().await;
+    if !snapshots::save(&dir, name, &snapshot)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+    {
+        return Err(exists());
+    }
+    info!(
+        "PUT /admin/snapshots/{name}: took it, capturing {} packages",
+        snapshot.packages.len()
+    );
+    Ok(json(StatusCode::CREATED, &SnapshotSummary::from(&snapshot)))
+}
+
+fn delete_snapshot(upstream: &Upstream, name: &str) -> Result<Response<Body>, Failure> {
+    let dir = snapshot_dir(upstream)?;
+    if !snapshots::remove(&dir, name).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))? {
+        return Err((StatusCode::NOT_FOUND, format!("no snapshot called {name}")));
+    }
+    info!("DELETE /admin/snapshots/{name}: removed it");
+    Ok(respond(StatusCode::NO_CONTENT, String::new()))
+}
+
+fn audit_report_dir(upstream: &Upstream) -> Result<PathBuf, Failure> {
+    upstream.config().audit_report_dir.clone().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "there are no audit reports without an audit_report_dir".to_owned(),
+        )
+    })
+}
+
+/// The advisories a report found, by package.
+fn summarize(findings: Vec<Finding>) -> BTreeMap<String, BTreeSet<String>> {
+    let mut summary = BTreeMap::<String, BTreeSet<String>>::new();
+    for finding in findings {
+        summary
+            .entry(finding.package)
+            .or_default()
+            .insert(finding.id);
+    }
+    summary
+}
+
+/// `GET` lists every audit report by name, with the advisories it found, by package.
+pub async fn handle_audit_reports(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    method: Method,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    if method != Method::GET {
+        return method_not_allowed("GET");
+    }
+
+    let summaries = audit_report_dir(&upstream).and_then(|dir| {
+        let reports =
+            audit_reports::list(&dir).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        reports
+            .into_iter()
+            .map(|(name, report)| {
+                let findings = audit_reports::parse(&report)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{name}: {e}")))?;
+                Ok((name, summarize(findings)))
+            })
+            .collect::<Result<BTreeMap<_, _>, Failure>>()
+    });
+    match summaries {
+        Ok(summaries) => json(StatusCode::OK, &summaries),
+        Err((status, message)) => respond(status, message),
+    }
+}
+
+/// `PUT` saves the pip-audit or safety report in the body as `name`, replacing any by that name,
+/// and `DELETE` removes one.
+/// Either applies right away, and the versions in the reports are denied until they're removed.
+pub async fn handle_audit_report<B: Buf>(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    name: String,
+    method: Method,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    if !audit_reports::is_valid_name(&name) {
+        return respond(StatusCode::BAD_REQUEST, "invalid report name".to_owned());
+    }
+
+    let result = match method {
+        Method::PUT => put_audit_report(&upstream, &name, body).await,
+        Method::DELETE => delete_audit_report(&upstream, &name).await,
+        _ => return method_not_allowed("PUT, DELETE"),
+    };
+    result.unwrap_or_else(|(status, message)| respond(status, message))
+}
+
+async fn put_audit_report<B: Buf>(
+    upstream: &Upstream,
+    name: &str,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Result<Response<Body>, Failure> {
+    let dir = audit_report_dir(upstream)?;
+    let body = read_body(body, MAX_AUDIT_REPORT_SIZE).await?;
+    let findings = audit_reports::parse(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid report: {e}")))?;
+
+    let _edit = EDITS.lock().await;
+    let existed = audit_reports::save(&dir, name, &body)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    apply(upstream)?;
+    info!(
+        "PUT /admin/audit-reports/{name}: {} it, with {} findings",
+        if existed { "replaced" } else { "saved" },
+        findings.len()
+    );
+    let status = if existed {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok(json(status, &summarize(findings)))
+}
+
+async fn delete_audit_report(upstream: &Upstream, name: &str) -> Result<Response<Body>, Failure> {
+    let dir = audit_report_dir(upstream)?;
+    let _edit = EDITS.lock().await;
+    let existed =
+        audit_reports::remove(&dir, name).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if !existed {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("no audit report called {name}"),
+        ));
+    }
+    apply(upstream)?;
+    info!("DELETE /admin/audit-reports/{name}: removed it");
+    Ok(respond(StatusCode::NO_CONTENT, String::new()))
+}
+
+/// Reads a reason out of a plain text request body, or `default` if it's empty.
+async fn read_reason<B: Buf>(
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+    default: &str,
+) -> Result<String, Failure> {
+    let body = read_body(body, MAX_POLICY_SIZE).await?;
+    let reason = String::from_utf8(body).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            "the reason has to be plain text".to_owned(),
+        )
+    })?;
+    Ok(match reason.trim() {
+        "" => default.to_owned(),
+        reason => reason.to_owned(),
+    })
+}
+
+/// `POST` blocks a package right away, for the reason in the body (as plain text),
+/// e.g. during a supply chain incident, and purges its artifacts from the artifact cache.
+/// The block is saved into its config as it's stored, or a new one if it has none,
+/// and is enforced even if it was in audit mode.
+pub async fn handle_block<B: Buf>(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    package: String,
+    method: Method,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    let package = match percent_decode_str(&package).decode_utf8() {
+        Ok(package) if pep_503::is_valid_project_name(&package) => pep_503::normalize(&package),
+        _ => return respond(StatusCode::BAD_REQUEST, "invalid package name".to_owned()),
+    };
+    if method != Method::POST {
+        return method_not_allowed("POST");
+    }
+
+    block(&upstream, &package, body)
+        .await
+        .unwrap_or_else(|(status, message)| respond(status, message))
+}
+
+async fn block<B: Buf>(
+    upstream: &Upstream,
+    package: &str,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Result<Response<Body>, Failure> {
+    let reason = read_reason(body, DEFAULT_BLOCK_REASON).await?;
+
+    let _edit = EDITS.lock().await;
+    // not as it's applied, which would bake its templates, lockfile pins and so on into it
+    let stored = package_config::stored(upstream.config(), package)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let config = PackageConfig {
+        blocked: Some(reason.clone()),
+        mode: Some(Mode::Enforce),
+        ..stored.unwrap_or_default()
+    };
+    package_config::save(upstream.config(), package, &config)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    apply(upstream)?;
+    let purged = upstream.purge_artifacts(package);
+    warn!(
+        "POST /admin/block/{package}: blocked it ({reason}), and purged {purged} cached artifacts"
+    );
+    Ok(json(StatusCode::OK, &config))
+}
+
+fn package_request_dir(upstream: &Upstream) -> Result<PathBuf, Failure> {
+    upstream
+        .config()
+        .package_request_dir
+        .clone()
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "there are no package requests without a package_request_dir".to_owned(),
+            )
+        })
+}
+
+/// `GET` lists every package requested while it wasn't on the allowlist, by normalized name,
+/// with who asked for it and when, and why it was rejected, if it was.
+pub async fn handle_package_requests(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    method: Method,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    if method != Method::GET {
+        return method_not_allowed("GET");
+    }
+
+    let listed = package_request_dir(&upstream).and_then(|dir| {
+        package_requests::list(&dir).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+    });
+    match listed {
+        Ok(requests) => json(StatusCode::OK, &requests),
+        Err((status, message)) => respond(status, message),
+    }
+}
+
+/// `POST` to `approve` adds a requested package to the allowlist, with the config in the body
+/// (or an empty one, unless it already has one), and `POST` to `reject` rejects it,
+/// for the reason in the body (as plain text).
+/// Rejected packages stay in the queue, so that they can still be approved later.
+pub async fn handle_package_request<B: Buf>(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    package: String,
+    action: String,
+    method: Method,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Response<Body> {
+    if let Some(res) = unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    let package = match percent_decode_str(&package).decode_utf8() {
+        Ok(package) if pep_503::is_valid_project_name(&package) => pep_503::normalize(&package),
+        _ => return respond(StatusCode::BAD_REQUEST, "invalid package name".to_owned()),
+    };
+    if !matches!(action.as_str(), "approve" | "reject") {
+        return respond(StatusCode::NOT_FOUND, "not found".to_owned());
+    }
+    if method != Method::POST {
+        return method_not_allowed("POST");
+    }
+
+    let result = match action.as_str() {
+        "approve" => approve(&upstream, &package, body).await,
+        _ => reject(&upstream, &package, body).await,
+    };
+    result.unwrap_or_else(|(status, message)| respond(status, message))
+}
+
+async fn approve<B: Buf>(
+    upstream: &Upstream,
+    package: &str,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Result<Response<Body>, Failure> {
+    let dir = package_request_dir(upstream)?;
+    let body = read_body(body, MAX_POLICY_SIZE).await?;
+    let config = if body.is_empty() {
+        None
+    } else {
+        Some(parse_policy(upstream, &body)?.0)
+    };
+
+    let _edit = EDITS.lock().await;
+    let requests = package_requests::load(&dir, package)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("{package} hasn't been requested"),
+            )
+        })?;
+    let configured = upstream
+        .package_configs()
+        .has(package)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if config.is_some() || !configured {
+        package_config::save(upstream.config(), package, &config.unwrap_or_default())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        apply(upstream)?;
+    }
+    package_requests::remove(&dir, package).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    info!(
+        "POST /admin/requests/{package}/approve: added it to the allowlist ({} requests)",
+        requests.requests.len()
+    );
+
+    let packages = upstream
+        .package_configs()
+        .packages()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(json(
+        StatusCode::OK,
+        &packages.get(package).cloned().unwrap_or_default(),
+    ))
+}
+
+async fn reject<B: Buf>(
+    upstream: &Upstream,
+    package: &str,
+    body: impl Stream<Item = Result<B, warp::Error>> + Unpin,
+) -> Result<Response<Body>, Failure> {
+    let dir = package_request_dir(upstream)?;
+    let reason = read_reason(body, DEFAULT_REJECT_REASON).await?;
+
+    let _edit = EDITS.lock().await;
+    let requests = package_requests::reject(&dir, package, &reason)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("{package} hasn't been requested"),
+            )
+        })?;
+    info!("POST /admin/requests/{package}/reject: rejected it ({reason})");
+    Ok(json(StatusCode::OK, &requests))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_authorized() {
+        assert!(is_authorized("hunter2", Some("Bearer hunter2")));
+        assert!(!is_authorized("hunter2", Some("Bearer hunter3")));
+        assert!(!is_authorized("hunter2", Some("Basic hunter2")));
+        assert!(!is_authorized("hunter2", None));
+    }
+}
@@ -0,0 +1,934 @@
+// reference: https://en.wikipedia.org/wiki/Cache_replacement_policies#LRU
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::stream;
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+    Body, HeaderMap, Response, StatusCode,
+};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{fs::File, io::AsyncReadExt, io::AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    cache::{decode_headers, encode_headers, CacheStatus, EvictionReason, Evictions},
+    lease::{self, Lease},
+    lru::Lru,
+    s3::{self, S3Store},
+};
+
+/// How often a download underway elsewhere is checked for.
+const LEASE_POLL: Duration = Duration::from_millis(250);
+/// How often a download being followed is checked for more of its file.
+const FOLLOW_POLL: Duration = Duration::from_millis(50);
+
+/// Downloaded artifacts (wheels and sdists), kept on disk up to `max_size` bytes in total.
+/// Once full, the least recently served artifacts are evicted first.
+///
+/// Every artifact is stored alongside the SHA-256 of its contents,
+/// and is checked against it before it's served, so that a file
+/// which was damaged on disk is downloaded again instead of handed to pip.
+///
+/// With a `remote` object store, the disk becomes a hot layer in front of it:
+/// artifacts are uploaded there once they've been downloaded,
+/// and fetched from there (rather than upstream) when they aren't on disk.
+pub struct ArtifactCache {
+    dir: PathBuf,
+    max_size: u64,
+    remote: Option<S3Store>,
+    lease_timeout: Duration,
+    lru: Mutex<Lru<String, Cached>>,
+    /// Numbers partially downloaded files, so that concurrent downloads don't collide.
+    downloads: AtomicU64,
+    pub evictions: Evictions,
+}
+
+/// What's kept in memory about each artifact on disk.
+#[derive(Clone, Copy)]
+struct Cached {
+    size: u64,
+    fetched_at: SystemTime,
+}
+
+/// What the holder of a download's lease publishes for others to follow it with.
+#[derive(Deserialize, Serialize)]
+struct LeaseInfo {
+    /// File name of the partial download, in the cache directory.
+    partial: String,
+    headers: Vec<(String, String)>,
+    size: Option<u64>,
+}
+
+pub enum Claim {
+    Served(Response<Body>),
+    /// Nobody has it, so it's up to the caller to download it (with the lease, if there is one).
+    Download(Option<Lease>),
+}
+
+#[derive(Deserialize, Serialize)]
+struct Metadata {
+    key: String,
+    sha256: String,
+    size: u64,
+    headers: Vec<(String, String)>,
+    fetched_at: SystemTime,
+}
+
+impl ArtifactCache {
+    /// Opens the cache in `dir`, picking up whatever was cached there before.
+    /// Since last use isn't persisted, artifacts from a previous run
+    /// are treated as if they were last used when they were downloaded.
+    ///
+    /// Several instances can share one directory: downloads are coordinated through
+    /// lease files, which are considered abandoned if not renewed within `lease_timeout`.
+    pub fn open(
+        dir: PathBuf,
+        max_size: u64,
+        remote: Option<S3Store>,
+        lease_timeout: Duration,
+    ) -> Self {
+        let mut cached = Vec::new();
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("json") => {}
+                Some("partial") => {
+                    // left over from a download which was interrupted by a restart,
+                    // unless it's another instance's download which is still going
+                    if !lease::is_held(&path, lease_timeout) {
+                        let _ = fs::remove_file(&path);
+                    }
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let metadata = fs::read(&path)
+                .ok()
+                .and_then(|metadata| serde_json::from_slice::<Metadata>(&metadata).ok());
+            match metadata {
+                Some(metadata) if path.with_extension("").exists() => cached.push(metadata),
+                _ => {
+                    warn!("ignoring corrupt cache entry {}", path.display());
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+        cached.sort_by_key(|metadata| metadata.fetched_at);
+
+        let mut lru = Lru::new(max_size);
+        let mut evicted = Vec::new();
+        for metadata in cached {
+            let cached = Cached {
+                size: metadata.size,
+                fetched_at: metadata.fetched_at,
+            };
+            evicted.extend(lru.insert(metadata.key, cached, metadata.size));
+        }
+        let cache = Self {
+            dir,
+            max_size,
+            remote,
+            lease_timeout,
+            lru: Mutex::new(lru),
+            downloads: AtomicU64::new(0),
+            evictions: Evictions::default(),
+        };
+        cache.evict(evicted, EvictionReason::Size);
+        cache
+    }
+
+    /// Serves a cached artifact, if there's an intact copy of it.
+    pub async fn get(self: &Arc<Self>, key: &str) -> Option<Response<Body>> {
+        if let Some(res) = self.get_local(key).await {
+            return Some(res);
+        }
+        self.get_remote(key, &mut None).await
+    }
+
+    /// Serves `key` from the cache if it's there, or from another download of it
+    /// which is already underway, from this instance or any other sharing the cache directory.
+    /// Otherwise hands back a lease to download it with, so that everyone else can follow along.
+    pub async fn claim(self: &Arc<Self>, key: &str) -> Claim {
+        let lease_path = self.path(key).with_extension("lease");
+        loop {
+            if let Some(res) = self.get_local(key).await {
+                return Claim::Served(res);
+            }
+            match Lease::acquire(&lease_path, self.lease_timeout) {
+                Ok(Some(lease)) => {
+                    let mut lease = Some(lease);
+                    if let Some(res) = self.get_local(key).await {
+                        // it landed between looking for it and taking the lease
+                        return Claim::Served(res);
+                    }
+                    if let Some(res) = self.get_remote(key, &mut lease).await {
+                        return Claim::Served(res);
+                    }
+                    return Claim::Download(lease);
+                }
+                Ok(None) => {
+                    if let Some(res) = self.follow(&lease_path) {
+                        return Claim::Served(res);
+                    }
+                }
+                Err(e) => {
+                    warn!("failed to lease cached artifact {key}: {e}");
+                    return Claim::Download(None);
+                }
+            }
+            tokio::time::sleep(LEASE_POLL).await;
+        }
+    }
+
+    /// Streams the partial file of someone else's download, as it's being written.
+    /// Only works once they've published it, and only if we know how big it's going to be.
+    fn follow(&self, lease_path: &Path) -> Option<Response<Body>> {
+        let info = fs::read(lease_path)
+            .ok()
+            .and_then(|info| serde_json::from_slice::<LeaseInfo>(&info).ok())?;
+        let size = info.size?;
+        // opened rather than looked up by name from here on,
+        // since it's renamed into place once it's done
+        let file = File::from_std(fs::File::open(self.dir.join(&info.partial)).ok()?);
+
+        let lease_path = lease_path.to_owned();
+        let lease_timeout = self.lease_timeout;
+        let body = stream::unfold(Some((file, 0, false)), move |state| {
+            let lease_path = lease_path.clone();
+            async move {
+                let (mut file, mut read, mut released) = state?;
+                if read == size {
+                    return None;
+                }
+                let mut buf = vec![0; 64 * 1024];
+                loop {
+                    match file.read(&mut buf).await {
+                        Ok(0) if released => {
+                            let e = std::io::Error::new(
+                                std::io::ErrorKind::UnexpectedEof,
+                                "download was abandoned",
+                            );
+                            return Some((Err(e), None));
+                        }
+                        Ok(0) => {
+                            // read once more after the lease is gone,
+                            // in case the last chunk was written just before it went
+                            released = !lease::is_held(&lease_path, lease_timeout);
+                            if !released {
+                                tokio::time::sleep(FOLLOW_POLL).await;
+                            }
+                        }
+                        Ok(n) => {
+                            read += n as u64;
+                            let chunk = Bytes::copy_from_slice(&buf[..n]);
+                            return Some((Ok(chunk), Some((file, read, released))));
+                        }
+                        Err(e) => return Some((Err(e), None)),
+                    }
+                }
+            }
+        });
+
+        let mut res = Response::new(Body::wrap_stream(body));
+        *res.headers_mut() = decode_headers(&info.headers).unwrap_or_default();
+        res.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(size));
+        Some(res)
+    }
+
+    /// Serves an intact copy of `key` from disk.
+    /// Artifacts which aren't in our LRU may still have been downloaded by another instance.
+    async fn get_local(self: &Arc<Self>, key: &str) -> Option<Response<Body>> {
+        if !self.lru.lock().unwrap().contains_key(key) && !self.adopt(key) {
+            return None;
+        }
+
+        let path = self.path(key);
+        let metadata = tokio::fs::read(path.with_extension("json"))
+            .await
+            .ok()
+            .and_then(|metadata| serde_json::from_slice::<Metadata>(&metadata).ok());
+        let metadata = if let Some(metadata) = metadata {
+            metadata
+        } else {
+            warn!("missing metadata for cached artifact {key}");
+            self.remove(key);
+            return None;
+        };
+        match sha256_file(&path).await {
+            Ok(sha256) if sha256 == metadata.sha256 => {}
+            Ok(sha256) => {
+                warn!(
+                    "cached artifact {key} is corrupt (sha256 {sha256}, expected {})",
+                    metadata.sha256
+                );
+                self.remove(key);
+                return None;
+            }
+            Err(e) => {
+                warn!("failed to read cached artifact {key}: {e}");
+                self.remove(key);
+                return None;
+            }
+        }
+        let file = File::open(&path).await.ok()?;
+        self.lru.lock().unwrap().get(key);
+
+        let mut res = Response::new(Body::wrap_stream(ReaderStream::new(file)));
+        *res.headers_mut() = decode_headers(&metadata.headers).unwrap_or_default();
+        res.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(metadata.size));
+        CacheStatus::Hit.set_headers(
+            res.headers_mut(),
+            metadata.fetched_at.elapsed().unwrap_or_default(),
+        );
+        Some(res)
+    }
+
+    /// Adds an artifact which is on disk, but which we didn't download, to our LRU.
+    fn adopt(&self, key: &str) -> bool {
+        let path = self.path(key);
+        let metadata = fs::read(path.with_extension("json"))
+            .ok()
+            .and_then(|metadata| serde_json::from_slice::<Metadata>(&metadata).ok());
+        let metadata = match metadata {
+            Some(metadata) if metadata.key == key && path.exists() => metadata,
+            _ => return false,
+        };
+        let cached = Cached {
+            size: metadata.size,
+            fetched_at: metadata.fetched_at,
+        };
+        let evicted = self
+            .lru
+            .lock()
+            .unwrap()
+            .insert(metadata.key, cached, metadata.size);
+        self.evict(evicted, EvictionReason::Size);
+        true
+    }
+
+    /// Streams an artifact from the object store, keeping a copy on disk on the way through.
+    /// Takes the `lease` to download it with, if there is one, once it's found.
+    async fn get_remote(
+        self: &Arc<Self>,
+        key: &str,
+        lease: &mut Option<Lease>,
+    ) -> Option<Response<Body>> {
+        let res = match self.remote.as_ref()?.get(key).await {
+            Ok(res) => res?,
+            Err(e) => {
+                warn!("failed to fetch cached artifact {key}: {e}");
+                return None;
+            }
+        };
+        let metadata = |name: &str| {
+            res.headers()
+                .get(s3::metadata_header(name))
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        let sha256 = if let Some(sha256) = metadata("sha256") {
+            sha256
+        } else {
+            warn!("cached artifact {key} has no sha256, ignoring it");
+            return None;
+        };
+        let age = metadata("fetched-at")
+            .and_then(|fetched_at| fetched_at.parse::<u64>().ok())
+            .and_then(|fetched_at| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH + Duration::from_secs(fetched_at))
+                    .ok()
+            })
+            .unwrap_or_default();
+
+        let (parts, body) = res.into_parts();
+        let mut res = Response::new(body);
+        for name in [CONTENT_TYPE, CONTENT_LENGTH] {
+            if let Some(value) = parts.headers.get(&name) {
+                res.headers_mut().insert(name, value.clone());
+            }
+        }
+        CacheStatus::Hit.set_headers(res.headers_mut(), age);
+        Some(self.store(key, res, Some(sha256), lease.take()))
+    }
+
+    /// Passes a full upstream response for `key` through,
+    /// writing it to the cache as it goes by.
+    /// The artifact is only added to the cache once its body has been read in full.
+    ///
+    /// `lease` is the one from [`ArtifactCache::claim`], which is held until the download is done.
+    pub fn tee(
+        self: &Arc<Self>,
+        key: &str,
+        res: Response<Body>,
+        lease: Option<Lease>,
+    ) -> Response<Body> {
+        self.store(key, res, None, lease)
+    }
+
+    /// Writes a response to disk as it's read.
+    /// Artifacts coming from the object store have the `sha256` they were uploaded with,
+    /// and are only kept if they still match it. Everything else gets uploaded there.
+    fn store(
+        self: &Arc<Self>,
+        key: &str,
+        res: Response<Body>,
+        sha256: Option<String>,
+        lease: Option<Lease>,
+    ) -> Response<Body> {
+        if res.status() != StatusCode::OK {
+            return res;
+        }
+        let expected_size = res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if expected_size.is_some_and(|size| size > self.max_size) {
+            return res;
+        }
+
+        let (parts, body) = res.into_parts();
+        let download = Download {
+            cache: self.clone(),
+            key: key.to_owned(),
+            headers: encode_headers(&parts.headers),
+            partial: self.path(key).with_extension(format!(
+                "{}.partial",
+                self.downloads.fetch_add(1, Ordering::SeqCst)
+            )),
+            file: None,
+            failed: false,
+            hasher: Sha256::new(),
+            size: 0,
+            expected_size,
+            expected_sha256: sha256,
+            lease,
+        };
+        let body = stream::unfold(Some((download, body)), |state| async move {
+            let (mut download, mut body) = state?;
+            match body.data().await {
+                Some(Ok(chunk)) => {
+                    download.write(&chunk).await;
+                    Some((Ok::<Bytes, hyper::Error>(chunk), Some((download, body))))
+                }
+                Some(Err(e)) => Some((Err(e), None)),
+                None => {
+                    download.finish().await;
+                    None
+                }
+            }
+        });
+        Response::from_parts(parts, Body::wrap_stream(body))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:x}", Sha256::digest(key)))
+    }
+
+    fn remove(&self, key: &str) {
+        self.lru.lock().unwrap().remove(key);
+        remove_files(&self.path(key));
+    }
+
+    fn evict(&self, evicted: Vec<(String, Cached)>, reason: EvictionReason) {
+        for (key, cached) in evicted {
+            debug!("evicting cached artifact {key}");
+            remove_files(&self.path(key.as_str()));
+            self.evictions.record(reason, cached.size);
+        }
+    }
+
+    /// Removes every artifact whose key `matches`, returning how many there were.
+    /// Like [`ArtifactCache::collect_garbage`], copies in the object store are left alone.
+    pub fn purge(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let keys = self
+            .lru
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| matches(key))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<String>>();
+        for key in keys.iter() {
+            debug!("purging cached artifact {key}");
+            self.remove(key);
+        }
+        keys.len()
+    }
+
+    /// Evicts artifacts downloaded more than `max_age` ago.
+    /// The cache never grows past `max_size`, so there's nothing else to collect.
+    /// Copies in the object store are left alone; use the bucket's lifecycle rules for those.
+    pub fn collect_garbage(&self, max_age: Duration) {
+        let expired = {
+            let mut lru = self.lru.lock().unwrap();
+            let keys = lru
+                .iter()
+                .filter(|(_, cached)| cached.fetched_at.elapsed().unwrap_or_default() > max_age)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<String>>();
+            keys.into_iter()
+                .filter_map(|key| {
+                    let cached = lru.remove(&key)?;
+                    Some((key, cached))
+                })
+                .collect()
+        };
+        self.evict(expired, EvictionReason::Age);
+    }
+}
+
+fn remove_files(path: &Path) {
+    let _ = fs::remove_file(path.with_extension("json"));
+    let _ = fs::remove_file(path);
+}
+
+async fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// An artifact on its way into the cache.
+/// If it's dropped before it's finished, e.g. because the client went away, it's thrown out.
+struct Download {
+    cache: Arc<ArtifactCache>,
+    key: String,
+    headers: Vec<(String, String)>,
+    partial: PathBuf,
+    file: Option<File>,
+    failed: bool,
+    hasher: Sha256,
+    size: u64,
+    expected_size: Option<u64>,
+    expected_sha256: Option<String>,
+    /// Dropped along with the download, whether it finished or not,
+    /// which is what tells everyone following it that it's over.
+    lease: Option<Lease>,
+}
+
+impl Download {
+    async fn write(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+        if self.failed {
+            return;
+        }
+
+        if self.file.is_none() {
+            let file = async {
+                tokio::fs::create_dir_all(&self.cache.dir).await?;
+                File::create(&self.partial).await
+            };
+            match file.await {
+                Ok(file) => {
+                    self.file = Some(file);
+                    self.publish();
+                }
+                Err(e) => {
+                    warn!("failed to cache artifact {}: {e}", self.key);
+                    self.failed = true;
+                    return;
+                }
+            }
+        }
+        if let Err(e) = self.file.as_mut().unwrap().write_all(chunk).await {
+            warn!("failed to cache artifact {}: {e}", self.key);
+            self.failed = true;
+        }
+        if let Some(lease) = &mut self.lease {
+            lease.renew();
+        }
+    }
+
+    /// Tells everyone waiting on our lease where to follow the download from.
+    fn publish(&mut self) {
+        let lease = if let Some(lease) = &mut self.lease {
+            lease
+        } else {
+            return;
+        };
+        let info = LeaseInfo {
+            partial: self
+                .partial
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            headers: self.headers.clone(),
+            size: self.expected_size,
+        };
+        if let Err(e) = lease.publish(&serde_json::to_vec(&info).unwrap()) {
+            warn!("failed to publish download of {}: {e}", self.key);
+        }
+    }
+
+    async fn finish(&mut self) {
+        if self.failed
+            || self.size > self.cache.max_size
+            || self.expected_size.is_some_and(|size| size != self.size)
+        {
+            return;
+        }
+        if self.file.is_none() {
+            // empty artifacts are never written to, but they're still artifacts
+            self.write(&[]).await;
+        }
+
+        let sha256 = format!("{:x}", self.hasher.clone().finalize());
+        if let Some(expected_sha256) = &self.expected_sha256 {
+            if &sha256 != expected_sha256 {
+                warn!(
+                    "cached artifact {} is corrupt (sha256 {sha256}, expected {expected_sha256})",
+                    self.key
+                );
+                return;
+            }
+        }
+        let cached = Cached {
+            size: self.size,
+            fetched_at: SystemTime::now(),
+        };
+        let metadata = Metadata {
+            key: self.key.clone(),
+            sha256,
+            size: self.size,
+            headers: self.headers.clone(),
+            fetched_at: cached.fetched_at,
+        };
+        let path = self.cache.path(&self.key);
+        let result = async {
+            let mut file = self.file.take().unwrap();
+            file.flush().await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&self.partial, &path).await?;
+            tokio::fs::write(
+                path.with_extension("json"),
+                serde_json::to_vec(&metadata).unwrap(),
+            )
+            .await
+        };
+        if let Err(e) = result.await {
+            warn!("failed to cache artifact {}: {e}", self.key);
+            remove_files(&path);
+            return;
+        }
+
+        let evicted = self
+            .cache
+            .lru
+            .lock()
+            .unwrap()
+            .insert(self.key.clone(), cached, self.size);
+        self.cache.evict(evicted, EvictionReason::Size);
+
+        if self.expected_sha256.is_none() {
+            if let Some(remote) = self.cache.remote.clone() {
+                tokio::spawn(upload(remote, path, metadata));
+            }
+        }
+    }
+}
+
+/// Copies a freshly downloaded artifact into the object store.
+async fn upload(remote: S3Store, path: PathBuf, metadata: Metadata) {
+    let mut headers = decode_headers(&metadata.headers)
+        .unwrap_or_default()
+        .get(CONTENT_TYPE)
+        .map(|content_type| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, content_type.clone());
+            headers
+        })
+        .unwrap_or_default();
+    headers.insert(
+        s3::metadata_header("sha256"),
+        HeaderValue::from_str(&metadata.sha256).unwrap(),
+    );
+    let fetched_at = metadata
+        .fetched_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    headers.insert(
+        s3::metadata_header("fetched-at"),
+        HeaderValue::from(fetched_at),
+    );
+
+    let file = match File::open(&path).await {
+        Ok(file) => file,
+        Err(e) => {
+            // e.g. it was evicted again already
+            warn!("failed to upload cached artifact {}: {e}", metadata.key);
+            return;
+        }
+    };
+    let body = Body::wrap_stream(ReaderStream::new(file));
+    if let Err(e) = remote
+        .put(&metadata.key, headers, metadata.size, body)
+        .await
+    {
+        warn!("failed to upload cached artifact {}: {e}", metadata.key);
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.partial);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use std::collections::HashMap;
+
+    use warp::{path::Tail, Filter};
+
+    use super::*;
+    use crate::{cache::X_CACHE, s3::S3Config};
+
+    const LEASE_TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn make_cache_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pyproxide-artifacts-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    async fn download(cache: &Arc<ArtifactCache>, key: &str, contents: &'static [u8]) {
+        let res = Response::builder()
+            .header(CONTENT_LENGTH, contents.len())
+            .body(Body::from(contents))
+            .unwrap();
+        let body = hyper::body::to_bytes(cache.tee(key, res, None).into_body())
+            .await
+            .unwrap();
+        assert_eq!(&body[..], contents);
+    }
+
+    #[tokio::test]
+    async fn test_serves_cached_artifacts() {
+        let dir = make_cache_dir("serve");
+        let cache = Arc::new(ArtifactCache::open(dir.clone(), 1024, None, LEASE_TIMEOUT));
+        assert!(cache.get("numpy-1.0.tar.gz").await.is_none());
+        download(&cache, "numpy-1.0.tar.gz", b"numpy").await;
+
+        // including after a restart
+        let cache = Arc::new(ArtifactCache::open(dir.clone(), 1024, None, LEASE_TIMEOUT));
+        let res = cache.get("numpy-1.0.tar.gz").await.unwrap();
+        assert_eq!(res.headers()[X_CACHE], "HIT");
+        assert_eq!(res.headers()[CONTENT_LENGTH], "5");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"numpy");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let dir = make_cache_dir("evict");
+        let cache = Arc::new(ArtifactCache::open(dir.clone(), 10, None, LEASE_TIMEOUT));
+        download(&cache, "numpy-1.0.tar.gz", b"numpy").await;
+        download(&cache, "scipy-1.0.tar.gz", b"scipy").await;
+        cache.get("numpy-1.0.tar.gz").await.unwrap();
+        download(&cache, "torch-1.0.tar.gz", b"torch").await;
+
+        assert!(cache.get("numpy-1.0.tar.gz").await.is_some());
+        assert!(cache.get("scipy-1.0.tar.gz").await.is_none());
+        assert!(cache.get("torch-1.0.tar.gz").await.is_some());
+        assert_eq!(cache.evictions.get(EvictionReason::Size), (1, 5));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_collects_expired_artifacts() {
+        let dir = make_cache_dir("gc");
+        let cache = Arc::new(ArtifactCache::open(dir.clone(), 1024, None, LEASE_TIMEOUT));
+        download(&cache, "numpy-1.0.tar.gz", b"numpy").await;
+
+        cache.collect_garbage(Duration::from_secs(60));
+        assert!(cache.get("numpy-1.0.tar.gz").await.is_some());
+
+        cache.collect_garbage(Duration::ZERO);
+        assert!(cache.get("numpy-1.0.tar.gz").await.is_none());
+        assert!(!cache.path("numpy-1.0.tar.gz").exists());
+        assert_eq!(cache.evictions.get(EvictionReason::Age), (1, 5));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_purges_artifacts() {
+        let dir = make_cache_dir("purge");
+        let cache = Arc::new(ArtifactCache::open(dir.clone(), 1024, None, LEASE_TIMEOUT));
+        download(&cache, "numpy-1.0.tar.gz", b"numpy").await;
+        download(&cache, "scipy-1.0.tar.gz", b"scipy").await;
+
+        assert_eq!(cache.purge(|key| key.starts_with("numpy-")), 1);
+        assert!(cache.get("numpy-1.0.tar.gz").await.is_none());
+        assert!(!cache.path("numpy-1.0.tar.gz").exists());
+        assert!(cache.get("scipy-1.0.tar.gz").await.is_some());
+        assert_eq!(cache.purge(|key| key.starts_with("numpy-")), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_artifacts_are_not_served() {
+        let dir = make_cache_dir("corrupt");
+        let cache = Arc::new(ArtifactCache::open(dir.clone(), 1024, None, LEASE_TIMEOUT));
+        download(&cache, "numpy-1.0.tar.gz", b"numpy").await;
+        fs::write(cache.path("numpy-1.0.tar.gz"), b"nunpy").unwrap();
+
+        assert!(cache.get("numpy-1.0.tar.gz").await.is_none());
+        assert!(!cache.path("numpy-1.0.tar.gz").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Stands in for an S3 bucket, keeping objects and their metadata in memory.
+    fn serve_bucket() -> S3Store {
+        type Objects = Arc<Mutex<HashMap<String, (HeaderMap, Bytes)>>>;
+        let objects = Objects::default();
+        let put = {
+            let objects = objects.clone();
+            warp::put()
+                .and(warp::path::tail())
+                .and(warp::header::headers_cloned())
+                .and(warp::body::bytes())
+                .map(move |key: Tail, headers: HeaderMap, body: Bytes| {
+                    objects
+                        .lock()
+                        .unwrap()
+                        .insert(key.as_str().to_owned(), (headers, body));
+                    StatusCode::OK
+                })
+        };
+        let get = warp::get().and(warp::path::tail()).map(move |key: Tail| {
+            match objects.lock().unwrap().get(key.as_str()) {
+                Some((headers, body)) => {
+                    let mut res = Response::new(Body::from(body.clone()));
+                    for (name, value) in headers.iter() {
+                        if name.as_str().starts_with("x-amz-meta-") {
+                            res.headers_mut().insert(name, value.clone());
+                        }
+                    }
+                    res
+                }
+                None => Response::builder().status(404).body(Body::empty()).unwrap(),
+            }
+        });
+        let (addr, server) = warp::serve(put.or(get)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        S3Store::new(S3Config {
+            endpoint: format!("http://{addr}"),
+            region: "us-east-1".to_owned(),
+            bucket: "artifacts".to_owned(),
+            prefix: "".to_owned(),
+            path_style: true,
+            access_key_id: "".to_owned(),
+            secret_access_key: "".to_owned(),
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serves_artifacts_from_the_object_store() {
+        let bucket = serve_bucket();
+        let dir = make_cache_dir("remote");
+        let cache = Arc::new(ArtifactCache::open(
+            dir.join("a"),
+            1024,
+            Some(bucket.clone()),
+            LEASE_TIMEOUT,
+        ));
+        download(&cache, "numpy-1.0.tar.gz", b"numpy").await;
+        // uploads happen in the background
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // e.g. another instance, or this one after its disk went away
+        let cache = Arc::new(ArtifactCache::open(
+            dir.join("b"),
+            1024,
+            Some(bucket),
+            LEASE_TIMEOUT,
+        ));
+        let res = cache.get("numpy-1.0.tar.gz").await.unwrap();
+        assert_eq!(res.headers()[X_CACHE], "HIT");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"numpy");
+        assert!(cache.path("numpy-1.0.tar.gz").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_follows_downloads_from_other_instances() {
+        let dir = make_cache_dir("follow");
+        let a = Arc::new(ArtifactCache::open(dir.clone(), 1024, None, LEASE_TIMEOUT));
+        let b = Arc::new(ArtifactCache::open(dir.clone(), 1024, None, LEASE_TIMEOUT));
+
+        let lease = match a.claim("numpy-1.0.tar.gz").await {
+            Claim::Download(Some(lease)) => lease,
+            _ => panic!("expected to download numpy"),
+        };
+        let (mut sender, body) = Body::channel();
+        let res = Response::builder()
+            .header(CONTENT_LENGTH, 10)
+            .body(body)
+            .unwrap();
+        let a_body = tokio::spawn(hyper::body::to_bytes(
+            a.tee("numpy-1.0.tar.gz", res, Some(lease)).into_body(),
+        ));
+        sender.send_data(Bytes::from("numpy")).await.unwrap();
+
+        let res = match b.claim("numpy-1.0.tar.gz").await {
+            Claim::Served(res) => res,
+            Claim::Download(_) => panic!("expected to follow a's download"),
+        };
+        assert_eq!(res.headers()[CONTENT_LENGTH], "10");
+        let b_body = tokio::spawn(hyper::body::to_bytes(res.into_body()));
+        sender.send_data(Bytes::from("-1.0!")).await.unwrap();
+        drop(sender);
+
+        assert_eq!(&a_body.await.unwrap().unwrap()[..], b"numpy-1.0!");
+        assert_eq!(&b_body.await.unwrap().unwrap()[..], b"numpy-1.0!");
+        // and it's b's to serve from now on, too
+        assert!(matches!(
+            b.claim("numpy-1.0.tar.gz").await,
+            Claim::Served(_)
+        ));
+        assert!(b.lru.lock().unwrap().contains_key("numpy-1.0.tar.gz"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{package_config, pep_440::SpecifierSet, pep_503};
+
+/// `pip-audit -f json`.
+#[derive(Deserialize)]
+struct PipAudit {
+    dependencies: Vec<PipAuditDependency>,
+}
+
+#[derive(Deserialize)]
+struct PipAuditDependency {
+    name: String,
+    /// Left out of dependencies pip-audit skipped.
+    version: Option<String>,
+    #[serde(default)]
+    vulns: Vec<PipAuditVuln>,
+}
+
+#[derive(Deserialize)]
+struct PipAuditVuln {
+    id: String,
+}
+
+/// `safety check --json`.
+#[derive(Deserialize)]
+struct SafetyCheck {
+    vulnerabilities: Vec<SafetyVulnerability>,
+}
+
+#[derive(Deserialize)]
+struct SafetyVulnerability {
+    package_name: String,
+    analyzed_version: String,
+    vulnerability_id: String,
+}
+
+/// An advisory in safety's vulnerability database (`insecure_full.json`).
+#[derive(Deserialize)]
+struct SafetyAdvisory {
+    id: String,
+    specs: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Report {
+    PipAudit(PipAudit),
+    /// What pip-audit wrote before it wrapped its dependencies in an object.
+    PipAuditLegacy(Vec<PipAuditDependency>),
+    SafetyCheck(SafetyCheck),
+    /// What `safety check --json` wrote before 2.0: name, spec, version, advisory and id.
+    SafetyCheckLegacy(Vec<(String, String, String, String, String)>),
+    /// By package name, along with a `$meta` entry.
+    SafetyDb(HashMap<String, serde_json::Value>),
+}
+
+/// A known vulnerability in some of a package's versions, as a report found it.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Finding {
+    /// By its normalized name.
+    pub package: String,
+    pub versions: SpecifierSet,
+    /// The advisory, e.g. `PYSEC-2023-74`.
+    pub id: String,
+}
+
+fn exact(package: &str, version: &str, id: String) -> Result<Finding, String> {
+    Ok(Finding {
+        package: pep_503::normalize(package),
+        versions: SpecifierSet::parse_strict(&format!("=={version}"))
+            .map_err(|e| format!("{package}: {e}"))?,
+        id,
+    })
+}
+
+/// Reads the findings out of a pip-audit or safety report, or a safety database export.
+pub fn parse(json: &[u8]) -> Result<Vec<Finding>, String> {
+    let report = serde_json::from_slice::<Report>(json)
+        .map_err(|_| "not a pip-audit or safety report".to_owned())?;
+
+    let mut findings = vec![];
+    match report {
+        Report::PipAudit(PipAudit { dependencies }) | Report::PipAuditLegacy(dependencies) => {
+            for dependency in dependencies {
+                let version = match &dependency.version {
+                    Some(version) => version,
+                    None => continue,
+                };
+                for vuln in dependency.vulns {
+                    findings.push(exact(&dependency.name, version, vuln.id)?);
+                }
+            }
+        }
+        Report::SafetyCheck(SafetyCheck { vulnerabilities }) => {
+            for vulnerability in vulnerabilities {
+                findings.push(exact(
+                    &vulnerability.package_name,
+                    &vulnerability.analyzed_version,
+                    vulnerability.vulnerability_id,
+                )?);
+            }
+        }
+        Report::SafetyCheckLegacy(vulnerabilities) => {
+            for (package, _, version, _, id) in vulnerabilities {
+                findings.push(exact(&package, &version, id)?);
+            }
+        }
+        Report::SafetyDb(packages) => {
+            for (package, advisories) in packages {
+                if package.starts_with('$') {
+                    continue;
+                }
+                let advisories = serde_json::from_value::<Vec<SafetyAdvisory>>(advisories)
+                    .map_err(|e| format!("{package}: {e}"))?;
+                for advisory in advisories {
+                    for spec in advisory.specs {
+                        findings.push(Finding {
+                            package: pep_503::normalize(&package),
+                            versions: SpecifierSet::parse_strict(&spec)
+                                .map_err(|e| format!("{package}: {e}"))?,
+                            id: advisory.id.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(findings)
+}
+
+/// Whether `name` can name a report, since it ends up in a file name.
+pub fn is_valid_name(name: &str) -> bool {
+    pep_503::is_valid_project_name(name)
+}
+
+fn path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.json"))
+}
+
+/// Every report in `dir`, by name, as it was saved.
+pub fn list(dir: &Path) -> Result<BTreeMap<String, Vec<u8>>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(format!("failed to read {}: {e}", dir.display())),
+    };
+
+    let mut reports = BTreeMap::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("failed to read {}: {e}", dir.display()))?;
+        let file_name = entry.file_name();
+        let name = match file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".json"))
+        {
+            Some(name) if is_valid_name(name) => name.to_owned(),
+            _ => continue,
+        };
+        let path = path(dir, &name);
+        let report =
+            std::fs::read(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        reports.insert(name, report);
+    }
+    Ok(reports)
+}
+
+/// The findings of every report in `dir`.
+pub fn load(dir: &Path) -> Result<Vec<Finding>, String> {
+    let mut findings = vec![];
+    for (name, report) in list(dir)? {
+        let path = path(dir, &name);
+        findings.extend(parse(&report).map_err(|e| format!("{}: {e}", path.display()))?);
+    }
+    Ok(findings)
+}
+
+/// Saves `report` as `name` in `dir`, replacing the one there was, if any.
+/// Returns whether there was.
+pub fn save(dir: &Path, name: &str, report: &[u8]) -> Result<bool, String> {
+    let path = path(dir, name);
+    let existed = path.exists();
+    std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    let report = std::str::from_utf8(report).map_err(|e| e.to_string())?;
+    package_config::write(&path, report)?;
+    Ok(existed)
+}
+
+/// Removes the report called `name` from `dir`, returning whether there was one.
+pub fn remove(dir: &Path, name: &str) -> Result<bool, String> {
+    let path = path(dir, name);
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(format!("failed to remove {}: {e}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn finding(package: &str, versions: &str, id: &str) -> Finding {
+        Finding {
+            package: package.to_owned(),
+            versions: SpecifierSet::from_str(versions).unwrap(),
+            id: id.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let pip_audit = br#"{
+            "dependencies": [
+                {"name": "Requests", "version": "2.25.0", "vulns": [
+                    {"id": "PYSEC-2023-74", "fix_versions": ["2.31.0"], "aliases": [], "description": ""}
+                ]},
+                {"name": "six", "version": "1.16.0", "vulns": []},
+                {"name": "local-thing", "skip_reason": "not on PyPI"}
+            ],
+            "fixes": []
+        }"#;
+        assert_eq!(
+            parse(pip_audit).unwrap(),
+            vec![finding("requests", "==2.25.0", "PYSEC-2023-74")],
+        );
+
+        let safety_check = br#"{
+            "report_meta": {},
+            "vulnerabilities": [
+                {"package_name": "django", "analyzed_version": "3.2.0", "vulnerability_id": "44742", "CVE": "CVE-2022-28346"}
+            ]
+        }"#;
+        assert_eq!(
+            parse(safety_check).unwrap(),
+            vec![finding("django", "==3.2.0", "44742")],
+        );
+
+        let safety_db = br#"{
+            "$meta": {"advisory": "...", "timestamp": 1700000000},
+            "django": [{"advisory": "...", "cve": "CVE-2022-28346", "id": "pyup.io-44742", "specs": ["<2.2.28", ">=3.0a1,<3.2.13"], "v": "<2.2.28,>=3.0a1,<3.2.13"}]
+        }"#;
+        assert_eq!(
+            parse(safety_db).unwrap(),
+            vec![
+                finding("django", "<2.2.28", "pyup.io-44742"),
+                finding("django", ">=3.0a1,<3.2.13", "pyup.io-44742"),
+            ],
+        );
+
+        assert_eq!(
+            parse(b"[1, 2]"),
+            Err("not a pip-audit or safety report".to_owned())
+        );
+        assert!(parse(br#"{"django": {"specs": "<2"}}"#).is_err());
+    }
+}
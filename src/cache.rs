@@ -0,0 +1,391 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use hyper::{
+    body::Bytes,
+    header::{
+        HeaderName, HeaderValue, AGE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        WARNING,
+    },
+    Body, HeaderMap, Response, StatusCode,
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{cache_backend::CacheBackend, lru::Lru, upstream::UpstreamUrl};
+
+/// Non-standard, but widely understood by CDNs and the humans debugging them.
+pub const X_CACHE: &str = "x-cache";
+
+/// How a response was put together, reported to clients in `X-Cache`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CacheStatus {
+    /// Served from a copy which was still fresh, without asking upstream.
+    Hit,
+    /// Fetched from upstream in full.
+    Miss,
+    /// Served from a copy which upstream just confirmed is current.
+    Revalidated,
+    /// Served from an expired copy, because upstream couldn't be reached to refresh it.
+    Stale,
+}
+
+impl ToString for CacheStatus {
+    fn to_string(&self) -> String {
+        match self {
+            CacheStatus::Hit => "HIT".to_string(),
+            CacheStatus::Miss => "MISS".to_string(),
+            CacheStatus::Revalidated => "REVALIDATED".to_string(),
+            CacheStatus::Stale => "STALE".to_string(),
+        }
+    }
+}
+
+impl CacheStatus {
+    /// Sets `X-Cache`, plus `Age` with how long ago the response was fetched from upstream.
+    /// Stale responses also get a `Warning`, for clients which surface those.
+    pub fn set_headers(&self, headers: &mut HeaderMap, age: Duration) {
+        headers.insert(X_CACHE, HeaderValue::from_str(&self.to_string()).unwrap());
+        headers.insert(AGE, HeaderValue::from(age.as_secs()));
+        if *self == CacheStatus::Stale {
+            // reference: https://www.rfc-editor.org/rfc/rfc7234#section-5.5.2
+            headers.insert(
+                WARNING,
+                HeaderValue::from_static("111 - \"Revalidation Failed\""),
+            );
+        }
+    }
+}
+
+/// An upstream response held in memory, so that it can be handed to more than one client
+/// and kept around to serve (or revalidate) later.
+#[derive(Clone, Debug)]
+pub struct BufferedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub upstream_url: Option<UpstreamUrl>,
+    pub body: Bytes,
+    pub cache_status: CacheStatus,
+    /// When upstream last confirmed this is what it'd send.
+    pub fetched_at: SystemTime,
+}
+
+impl BufferedResponse {
+    pub async fn read(res: Response<Body>) -> Result<Self, hyper::Error> {
+        let (parts, body) = res.into_parts();
+        Ok(Self {
+            status: parts.status,
+            headers: parts.headers,
+            upstream_url: parts.extensions.get::<UpstreamUrl>().cloned(),
+            body: hyper::body::to_bytes(body).await?,
+            cache_status: CacheStatus::Miss,
+            fetched_at: SystemTime::now(),
+        })
+    }
+
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed().unwrap_or_default()
+    }
+
+    /// The conditional request headers to revalidate this response with.
+    pub fn validators(&self) -> HeaderMap {
+        let mut validators = HeaderMap::new();
+        if let Some(etag) = self.headers.get(ETAG) {
+            validators.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = self.headers.get(LAST_MODIFIED) {
+            validators.insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+        validators
+    }
+
+    pub fn into_response(self) -> Response<Body> {
+        let age = self.age();
+        let mut res = Response::new(Body::from(self.body));
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers;
+        self.cache_status.set_headers(res.headers_mut(), age);
+        if let Some(upstream_url) = self.upstream_url {
+            res.extensions_mut().insert(upstream_url);
+        }
+        res
+    }
+
+    /// Serializes the response as a line of JSON metadata followed by the raw body.
+    fn encode(&self) -> Vec<u8> {
+        let metadata = Metadata {
+            status: self.status.as_u16(),
+            headers: encode_headers(&self.headers),
+            upstream_url: self.upstream_url.as_ref().map(|url| url.0.clone()),
+            fetched_at: self.fetched_at,
+        };
+
+        let mut encoded = serde_json::to_vec(&metadata).unwrap();
+        encoded.push(b'\n');
+        encoded.extend_from_slice(&self.body);
+        encoded
+    }
+
+    fn decode(encoded: &[u8]) -> Option<Self> {
+        let newline = encoded.iter().position(|byte| *byte == b'\n')?;
+        let metadata = serde_json::from_slice::<Metadata>(&encoded[..newline]).ok()?;
+
+        Some(Self {
+            status: StatusCode::from_u16(metadata.status).ok()?,
+            headers: decode_headers(&metadata.headers)?,
+            upstream_url: metadata.upstream_url.map(UpstreamUrl),
+            body: Bytes::copy_from_slice(&encoded[newline + 1..]),
+            cache_status: CacheStatus::Miss,
+            fetched_at: metadata.fetched_at,
+        })
+    }
+}
+
+/// Headers in a form that can be written to disk. Values which aren't valid UTF-8 are dropped.
+pub fn encode_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            Some((name.as_str().to_owned(), value.to_str().ok()?.to_owned()))
+        })
+        .collect()
+}
+
+pub fn decode_headers(encoded: &[(String, String)]) -> Option<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in encoded.iter() {
+        headers.append(
+            HeaderName::from_bytes(name.as_bytes()).ok()?,
+            HeaderValue::from_str(value).ok()?,
+        );
+    }
+    Some(headers)
+}
+
+#[derive(Deserialize, Serialize)]
+struct Metadata {
+    status: u16,
+    headers: Vec<(String, String)>,
+    upstream_url: Option<String>,
+    fetched_at: SystemTime,
+}
+
+/// Upstream index pages we've fetched, by upstream URL.
+/// Kept in memory, and in a [`CacheBackend`] (if there is one)
+/// so that they survive restarts, or are shared with other instances.
+pub struct PageCache {
+    backend: Option<Box<dyn CacheBackend>>,
+    /// How long the backend needs to hold on to pages for.
+    ttl: Duration,
+    /// The most recently used pages, weighed by the size of their bodies.
+    memory: Mutex<Lru<String, BufferedResponse>>,
+    pub evictions: Evictions,
+    pub memory_evictions: Evictions,
+}
+
+impl PageCache {
+    pub fn new(backend: Option<Box<dyn CacheBackend>>, ttl: Duration, memory_size: u64) -> Self {
+        Self {
+            backend,
+            ttl,
+            memory: Mutex::new(Lru::new(memory_size)),
+            evictions: Evictions::default(),
+            memory_evictions: Evictions::default(),
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<BufferedResponse> {
+        if let Some(page) = self.memory.lock().unwrap().get(key) {
+            return Some(page.clone());
+        }
+
+        let encoded = self.backend.as_ref()?.get(key).await?;
+        let page = if let Some(page) = BufferedResponse::decode(&encoded) {
+            page
+        } else {
+            warn!("ignoring corrupt cache entry for {key}");
+            return None;
+        };
+        self.remember(key, &page);
+        Some(page)
+    }
+
+    pub async fn put(&self, key: &str, page: &BufferedResponse) {
+        self.remember(key, page);
+
+        if let Some(backend) = &self.backend {
+            backend.put(key, page.encode(), self.ttl).await;
+        }
+    }
+
+    fn remember(&self, key: &str, page: &BufferedResponse) {
+        let evicted = self.memory.lock().unwrap().insert(
+            key.to_owned(),
+            page.clone(),
+            page.body.len() as u64,
+        );
+        for (_, page) in evicted {
+            self.memory_evictions
+                .record(EvictionReason::Size, page.body.len() as u64);
+        }
+    }
+
+    /// Throws out pages fetched more than `max_age` ago,
+    /// and shrinks the backend down to `max_size` bytes if it's grown past it.
+    pub async fn collect_garbage(&self, max_size: u64, max_age: Duration) {
+        {
+            let mut memory = self.memory.lock().unwrap();
+            let expired = memory
+                .iter()
+                .filter(|(_, page)| page.age() > max_age)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<String>>();
+            for key in expired {
+                if let Some(page) = memory.remove(&key) {
+                    self.memory_evictions
+                        .record(EvictionReason::Age, page.body.len() as u64);
+                }
+            }
+        }
+
+        if let Some(backend) = &self.backend {
+            backend
+                .collect_garbage(max_size, max_age, &self.evictions)
+                .await;
+        }
+    }
+}
+
+/// Why a cache entry was thrown out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EvictionReason {
+    /// To make room, because the cache was full.
+    Size,
+    /// Because it was older than the oldest entries the cache may keep.
+    Age,
+}
+
+impl ToString for EvictionReason {
+    fn to_string(&self) -> String {
+        match self {
+            EvictionReason::Size => "size".to_string(),
+            EvictionReason::Age => "age".to_string(),
+        }
+    }
+}
+
+/// Running totals of what a cache has thrown out, by reason.
+#[derive(Default)]
+pub struct Evictions {
+    by_size: AtomicU64,
+    by_age: AtomicU64,
+    bytes_by_size: AtomicU64,
+    bytes_by_age: AtomicU64,
+}
+
+impl Evictions {
+    pub fn record(&self, reason: EvictionReason, bytes: u64) {
+        let (count, total) = match reason {
+            EvictionReason::Size => (&self.by_size, &self.bytes_by_size),
+            EvictionReason::Age => (&self.by_age, &self.bytes_by_age),
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+        total.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// (entries, bytes) evicted for `reason` so far.
+    pub fn get(&self, reason: EvictionReason) -> (u64, u64) {
+        let (count, total) = match reason {
+            EvictionReason::Size => (&self.by_size, &self.bytes_by_size),
+            EvictionReason::Age => (&self.by_age, &self.bytes_by_age),
+        };
+        (count.load(Ordering::Relaxed), total.load(Ordering::Relaxed))
+    }
+}
+
+/// Renders eviction counts for each named cache in Prometheus' text format.
+pub fn render_evictions(caches: &[(&str, &Evictions)]) -> String {
+    let mut out = String::new();
+    for (metric, help, bytes) in [
+        (
+            "pyproxide_cache_evictions_total",
+            "Entries evicted from a cache.",
+            false,
+        ),
+        (
+            "pyproxide_cache_evicted_bytes_total",
+            "Bytes evicted from a cache.",
+            true,
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {metric} {help}");
+        let _ = writeln!(out, "# TYPE {metric} counter");
+        for (cache, evictions) in caches {
+            for reason in [EvictionReason::Size, EvictionReason::Age] {
+                let (count, total) = evictions.get(reason);
+                let _ = writeln!(
+                    out,
+                    "{metric}{{cache=\"{cache}\",reason=\"{}\"}} {}",
+                    reason.to_string(),
+                    if bytes { total } else { count }
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::cache_backend::CacheBackendConfig;
+
+    #[test]
+    fn test_set_headers() {
+        let mut headers = HeaderMap::new();
+        CacheStatus::Revalidated.set_headers(&mut headers, Duration::from_millis(61_500));
+        assert_eq!(headers[X_CACHE], "REVALIDATED");
+        assert_eq!(headers[AGE], "61");
+    }
+
+    #[tokio::test]
+    async fn test_page_cache_survives_restarts() {
+        let dir = std::env::temp_dir().join(format!("pyproxide-cache-{}", std::process::id()));
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        let page = BufferedResponse {
+            status: StatusCode::OK,
+            headers,
+            upstream_url: Some(UpstreamUrl("https://pypi.org/simple/numpy/".to_owned())),
+            body: Bytes::from_static(b"<a href=\"numpy-1.0.tar.gz\">numpy-1.0.tar.gz</a>\n"),
+            cache_status: CacheStatus::Miss,
+            fetched_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        };
+        let backend = || CacheBackendConfig::Disk.backend(Some(&dir)).unwrap();
+        PageCache::new(backend(), Duration::ZERO, 1024)
+            .put("https://pypi.org/simple/numpy/", &page)
+            .await;
+
+        let cache = PageCache::new(backend(), Duration::ZERO, 1024);
+        let cached = cache.get("https://pypi.org/simple/numpy/").await.unwrap();
+        assert_eq!(cached.status, page.status);
+        assert_eq!(cached.headers, page.headers);
+        assert_eq!(
+            cached.upstream_url.unwrap().0,
+            "https://pypi.org/simple/numpy/"
+        );
+        assert_eq!(cached.body, page.body);
+        assert_eq!(cached.fetched_at, page.fetched_at);
+        assert!(cache.get("https://pypi.org/simple/scipy/").await.is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use log::warn;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+use crate::cache::{EvictionReason, Evictions};
+
+/// Where cached index pages are kept, beyond each instance's own memory.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CacheBackendConfig {
+    /// Files under `cache_dir`, if there is one.
+    #[default]
+    Disk,
+    /// A Redis server, so that every instance behind a load balancer shares one cache.
+    Redis { url: String },
+}
+
+impl CacheBackendConfig {
+    pub fn backend(
+        &self,
+        cache_dir: Option<&Path>,
+    ) -> Result<Option<Box<dyn CacheBackend>>, String> {
+        match self {
+            CacheBackendConfig::Disk => Ok(cache_dir.map(|dir| {
+                Box::new(DiskBackend {
+                    dir: dir.join("index"),
+                }) as Box<dyn CacheBackend>
+            })),
+            CacheBackendConfig::Redis { url } => Ok(Some(Box::new(RedisBackend::new(url)?))),
+        }
+    }
+}
+
+/// Storage for serialized cache entries.
+///
+/// Backends are best effort: failures are logged and treated as misses,
+/// since the cache going away shouldn't take the proxy down with it.
+#[async_trait]
+pub trait CacheBackend: Send + Sync {
+    async fn get(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Stores `value` under `key`, for at least `ttl` if the backend expires entries.
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration);
+
+    /// Deletes entries older than `max_age`, then the oldest of the rest
+    /// until what's left fits in `max_size` bytes, counting them in `evictions`.
+    ///
+    /// Does nothing by default, for backends which expire and bound their entries themselves.
+    async fn collect_garbage(&self, _max_size: u64, _max_age: Duration, _evictions: &Evictions) {}
+}
+
+pub struct DiskBackend {
+    dir: PathBuf,
+}
+
+impl DiskBackend {
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:x}", Sha256::digest(key)))
+    }
+}
+
+#[async_trait]
+impl CacheBackend for DiskBackend {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        tokio::fs::read(self.path(key)).await.ok()
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, _ttl: Duration) {
+        let path = self.path(key);
+        // written to the side and moved into place, so readers never see half an entry
+        let partial = path.with_extension("partial");
+        let result = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(&partial, value).await?;
+            tokio::fs::rename(&partial, &path).await
+        };
+        if let Err(e) = result.await {
+            warn!("failed to write cache entry {}: {e}", path.display());
+        }
+    }
+
+    async fn collect_garbage(&self, max_size: u64, max_age: Duration, evictions: &Evictions) {
+        let mut dir = if let Ok(dir) = tokio::fs::read_dir(&self.dir).await {
+            dir
+        } else {
+            return;
+        };
+        let mut entries = Vec::new();
+        while let Ok(Some(entry)) = dir.next_entry().await {
+            if let Ok(metadata) = entry.metadata().await {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                entries.push((entry.path(), metadata.len(), modified));
+            }
+        }
+
+        // oldest first, so everything past max_age comes before anything evicted for space
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        let mut size = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+        for (path, len, modified) in entries {
+            let reason = if modified.elapsed().unwrap_or_default() > max_age {
+                EvictionReason::Age
+            } else if size > max_size {
+                EvictionReason::Size
+            } else {
+                break;
+            };
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {
+                    size -= len;
+                    evictions.record(reason, len);
+                }
+                Err(e) => warn!("failed to evict cache entry {}: {e}", path.display()),
+            }
+        }
+    }
+}
+
+/// Entries are written with a TTL for Redis to expire,
+/// and its `maxmemory` policy bounds how much it holds,
+/// so there's no garbage for us to collect.
+pub struct RedisBackend {
+    client: redis::Client,
+    /// Connected on first use, and reconnected by the manager whenever the connection drops.
+    connection: OnceCell<ConnectionManager>,
+}
+
+impl RedisBackend {
+    pub fn new(url: &str) -> Result<Self, String> {
+        Ok(Self {
+            client: redis::Client::open(url)
+                .map_err(|e| format!("invalid redis url {url}: {e}"))?,
+            connection: OnceCell::new(),
+        })
+    }
+
+    async fn connection(&self) -> Option<ConnectionManager> {
+        let connection = self
+            .connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await;
+        match connection {
+            Ok(connection) => Some(connection.clone()),
+            Err(e) => {
+                warn!("failed to connect to redis: {e}");
+                None
+            }
+        }
+    }
+
+    fn key(key: &str) -> String {
+        format!("pyproxide:index:{:x}", Sha256::digest(key))
+    }
+}
+
+#[async_trait]
+impl CacheBackend for RedisBackend {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut connection = self.connection().await?;
+        match connection
+            .get::<String, Option<Vec<u8>>>(Self::key(key))
+            .await
+        {
+            Ok(value) => value,
+            Err(e) => {
+                warn!("failed to read {key} from redis: {e}");
+                None
+            }
+        }
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) {
+        let mut connection = if let Some(connection) = self.connection().await {
+            connection
+        } else {
+            return;
+        };
+        let result = connection
+            .set_ex::<String, Vec<u8>, ()>(Self::key(key), value, ttl.as_secs().max(1))
+            .await;
+        if let Err(e) = result {
+            warn!("failed to write {key} to redis: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disk_garbage_collection() {
+        let dir = std::env::temp_dir().join(format!("pyproxide-disk-gc-{}", std::process::id()));
+        let backend = DiskBackend { dir: dir.clone() };
+        let evictions = Evictions::default();
+        for key in ["numpy", "scipy", "torch"] {
+            backend
+                .put(key, b"0123456789".to_vec(), Duration::ZERO)
+                .await;
+            // so that they're ordered by mtime
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        backend
+            .collect_garbage(20, Duration::from_secs(60), &evictions)
+            .await;
+        assert_eq!(backend.get("numpy").await, None);
+        assert_eq!(backend.get("scipy").await, Some(b"0123456789".to_vec()));
+        assert_eq!(evictions.get(EvictionReason::Size), (1, 10));
+
+        backend
+            .collect_garbage(20, Duration::ZERO, &evictions)
+            .await;
+        assert_eq!(backend.get("scipy").await, None);
+        assert_eq!(backend.get("torch").await, None);
+        assert_eq!(evictions.get(EvictionReason::Age), (2, 20));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+
+use crate::{config::Config, upstream::IndexConfig};
+
+/// A PyPI proxy which filters what pip gets to see.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// TOML file to read settings from. Flags take precedence over it.
+    #[arg(long, short, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+    /// Address to serve on.
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<SocketAddr>,
+    /// Port to serve on, overriding the one in `--listen`.
+    #[arg(long, short)]
+    pub port: Option<u16>,
+    /// Simple index to serve packages from, in place of PyPI.
+    #[arg(long, value_name = "URL")]
+    pub upstream: Option<String>,
+    /// Directory of per-package configs, named `{package}.json`.
+    #[arg(long, value_name = "DIR", global = true)]
+    pub config_dir: Option<PathBuf>,
+    /// A single file with every package's config, instead of a directory of them.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "config_dir",
+        global = true
+    )]
+    pub package_config_file: Option<PathBuf>,
+    /// Where index pages and artifacts are cached, so that they survive restarts.
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+    /// One of off, error, warn, info (the default), debug or trace.
+    #[arg(long, value_name = "LEVEL", value_parser = parse_log_level)]
+    pub log_level: Option<LevelFilter>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Checks every setting and package config, without serving anything,
+    /// printing each problem and exiting non-zero if there are any.
+    CheckConfig,
+    /// Shows whether each of a package's files would be served, and which rules decide it.
+    Explain {
+        package: String,
+        /// Filenames of the package's releases, e.g. `numpy-1.26.4-cp311-cp311-win_amd64.whl`.
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// The requires-python the files are published with, to check against `target_pythons`.
+        #[arg(long)]
+        requires_python: Option<String>,
+    },
+}
+
+fn parse_log_level(level: &str) -> Result<LevelFilter, String> {
+    level
+        .parse()
+        .map_err(|_| format!("unknown log level `{level}`"))
+}
+
+impl Cli {
+    /// Overrides whatever was configured with the flags that were passed.
+    pub fn apply(&self, config: &mut Config) {
+        let (server, upstream) = (&mut config.server, &mut config.upstream);
+        if let Some(listen) = self.listen {
+            server.listen = listen;
+        }
+        if let Some(port) = self.port {
+            server.listen.set_port(port);
+        }
+        if let Some(url) = &self.upstream {
+            upstream.indexes = vec![IndexConfig {
+                name: "upstream".to_owned(),
+                urls: vec![url.clone()],
+                credentials: None,
+                credential_helper: None,
+            }];
+        }
+        if let Some(config_dir) = &self.config_dir {
+            upstream.package_config_dir = config_dir.clone();
+            upstream.package_config_file = None;
+        }
+        if let Some(package_config_file) = &self.package_config_file {
+            upstream.package_config_file = Some(package_config_file.clone());
+        }
+        if let Some(cache_dir) = &self.cache_dir {
+            upstream.cache_dir = Some(cache_dir.clone());
+        }
+        if let Some(level) = self.log_level {
+            config.log.level = level;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let cli = Cli::parse_from([
+            "pyproxide",
+            "--listen",
+            "0.0.0.0:80",
+            "-p",
+            "3141",
+            "--upstream",
+            "https://pypi.example.com/simple",
+            "--log-level",
+            "debug",
+        ]);
+        let mut config = Config::default();
+        cli.apply(&mut config);
+        assert_eq!(config.server.listen, "0.0.0.0:3141".parse().unwrap());
+        assert_eq!(
+            config.upstream.indexes[0].urls,
+            vec!["https://pypi.example.com/simple"]
+        );
+        assert_eq!(config.upstream.cache_dir, None);
+        assert_eq!(config.log.level, LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_check_config() {
+        let cli = Cli::parse_from(["pyproxide", "check-config", "--config-dir", "policies"]);
+        assert!(matches!(cli.command, Some(Command::CheckConfig)));
+        assert_eq!(cli.config_dir, Some(PathBuf::from("policies")));
+
+        let cli = Cli::parse_from(["pyproxide", "explain", "numpy", "numpy-1.26.4.tar.gz"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Explain { package, files, .. })
+                if package == "numpy" && files == vec!["numpy-1.26.4.tar.gz"]
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+// reference: https://httpwg.org/specs/rfc9110.html#field.content-encoding
+
+use std::io;
+
+use async_compression::tokio::bufread::{
+    BrotliDecoder, BrotliEncoder, GzipDecoder, GzipEncoder, ZlibDecoder, ZlibEncoder,
+};
+use futures_util::TryStreamExt;
+use hyper::{
+    body::Bytes,
+    header::{HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, VARY},
+    Body, Response, StatusCode,
+};
+use tokio::io::AsyncReadExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// The `Accept-Encoding` we send upstream.
+/// Every coding listed here must be understood by [`decompress_response`].
+pub const UPSTREAM_ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    Brotli,
+}
+
+impl Encoding {
+    pub fn from_header(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            "br" => Some(Encoding::Brotli),
+            _ => None,
+        }
+    }
+
+    /// Picks the encoding to serve a client with, given its `Accept-Encoding`.
+    /// Ties on quality value are broken in favor of better compression ratios.
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let mut best: Option<(Encoding, f32)> = None;
+        for coding in accept_encoding.split(',') {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map(|q| q.parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            if quality <= 0.0 {
+                continue;
+            }
+
+            let encoding = if name == "*" {
+                Encoding::Gzip
+            } else if let Some(encoding) = Encoding::from_header(name) {
+                encoding
+            } else {
+                continue;
+            };
+
+            let is_better = match best {
+                None => true,
+                Some((best_encoding, best_quality)) => {
+                    quality > best_quality
+                        || (quality == best_quality && encoding.rank() > best_encoding.rank())
+                }
+            };
+            if is_better {
+                best = Some((encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Encoding::Deflate => 0,
+            Encoding::Gzip => 1,
+            Encoding::Brotli => 2,
+        }
+    }
+}
+
+impl ToString for Encoding {
+    fn to_string(&self) -> String {
+        match self {
+            Encoding::Gzip => "gzip".to_string(),
+            Encoding::Deflate => "deflate".to_string(),
+            Encoding::Brotli => "br".to_string(),
+        }
+    }
+}
+
+/// Transparently decodes a compressed upstream response,
+/// so that everything downstream of `Upstream::forward` only ever sees identity bodies.
+///
+/// Responses with an unknown (or no) `Content-Encoding` are returned untouched,
+/// as are partial responses, since a slice of an encoded body can't be decoded.
+pub fn decompress_response(res: Response<Body>) -> Response<Body> {
+    if res.status() == StatusCode::PARTIAL_CONTENT {
+        return res;
+    }
+
+    let encoding = res
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Encoding::from_header);
+    let encoding = if let Some(encoding) = encoding {
+        encoding
+    } else {
+        return res;
+    };
+
+    let (mut parts, body) = res.into_parts();
+    parts.headers.remove(CONTENT_ENCODING);
+    parts.headers.remove(CONTENT_LENGTH);
+
+    let reader = StreamReader::new(body.map_err(io::Error::other));
+    let body = match encoding {
+        Encoding::Gzip => Body::wrap_stream(ReaderStream::new(GzipDecoder::new(reader))),
+        Encoding::Deflate => Body::wrap_stream(ReaderStream::new(ZlibDecoder::new(reader))),
+        Encoding::Brotli => Body::wrap_stream(ReaderStream::new(BrotliDecoder::new(reader))),
+    };
+
+    Response::from_parts(parts, body)
+}
+
+/// Compresses a response we generated ourselves (i.e. a rendered index page)
+/// with whichever encoding the client prefers.
+///
+/// The page is compressed in full, so that it can always be sent with an exact `Content-Length`.
+/// `Vary: Accept-Encoding` is always set, even when the body is sent as-is,
+/// so that shared caches don't hand a compressed page to a client which can't read it.
+pub async fn compress_response(
+    accept_encoding: Option<&str>,
+    res: Response<Bytes>,
+) -> Response<Bytes> {
+    let (mut parts, body) = res.into_parts();
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+    let encoding = if parts.headers.contains_key(CONTENT_ENCODING) {
+        None
+    } else {
+        accept_encoding.and_then(Encoding::negotiate)
+    };
+    let body = match encoding {
+        Some(encoding) => {
+            parts.headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_str(&encoding.to_string()).unwrap(),
+            );
+            Bytes::from(compress(encoding, &body).await)
+        }
+        None => body,
+    };
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+
+    Response::from_parts(parts, body)
+}
+
+async fn compress(encoding: Encoding, body: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let result = match encoding {
+        Encoding::Gzip => GzipEncoder::new(body).read_to_end(&mut compressed).await,
+        Encoding::Deflate => ZlibEncoder::new(body).read_to_end(&mut compressed).await,
+        Encoding::Brotli => BrotliEncoder::new(body).read_to_end(&mut compressed).await,
+    };
+    // reading from memory can't fail
+    result.unwrap();
+    compressed
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_decompress_gzip_response() {
+        let mut compressed = Vec::new();
+        GzipEncoder::new(&b"<a href=\"/simple/numpy/\">numpy</a>"[..])
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+
+        let res = Response::builder()
+            .header(CONTENT_ENCODING, "gzip")
+            .header(CONTENT_LENGTH, compressed.len())
+            .body(Body::from(compressed))
+            .unwrap();
+        let res = decompress_response(res);
+
+        assert_eq!(res.headers().get(CONTENT_ENCODING), None);
+        assert_eq!(res.headers().get(CONTENT_LENGTH), None);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"<a href=\"/simple/numpy/\">numpy</a>");
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            Encoding::negotiate("gzip, deflate, br"),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(
+            Encoding::negotiate("gzip;q=1.0, br;q=0.5"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            Encoding::negotiate("br;q=0, deflate"),
+            Some(Encoding::Deflate)
+        );
+        assert_eq!(Encoding::negotiate("identity"), None);
+        assert_eq!(Encoding::negotiate("*"), Some(Encoding::Gzip));
+    }
+
+    #[tokio::test]
+    async fn test_compress_response_round_trip() {
+        let page = "<a href=\"/simple/numpy/\">numpy</a>".repeat(100);
+        let res = Response::builder().body(Bytes::from(page.clone())).unwrap();
+
+        let res = compress_response(Some("gzip"), res).await;
+        let content_length = res.headers()[CONTENT_LENGTH].to_str().unwrap().to_owned();
+        assert_eq!(
+            res.headers().get(CONTENT_ENCODING),
+            Some(&HeaderValue::from_static("gzip")),
+        );
+        assert_eq!(
+            res.headers().get(VARY),
+            Some(&HeaderValue::from_static("accept-encoding")),
+        );
+
+        let (parts, body) = res.into_parts();
+        assert_eq!(content_length, body.len().to_string());
+
+        let res = decompress_response(Response::from_parts(parts, Body::from(body)));
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(String::from_utf8(body.to_vec()).unwrap(), page);
+    }
+}
@@ -0,0 +1,146 @@
+// reference: https://httpwg.org/specs/rfc9110.html#conditional.requests
+
+use std::time::SystemTime;
+
+use hyper::{
+    body::Bytes,
+    header::{
+        HeaderValue, CACHE_CONTROL, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        VARY,
+    },
+    Body, HeaderMap, Response, StatusCode,
+};
+use sha2::{Digest, Sha256};
+
+use crate::header_str;
+
+/// The validators a client sent along with its request.
+#[derive(Debug, Default)]
+pub struct Conditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+}
+
+impl Conditions {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            if_none_match: header_str(headers, IF_NONE_MATCH),
+            if_modified_since: header_str(headers, IF_MODIFIED_SINCE)
+                .and_then(|date| httpdate::parse_http_date(&date).ok()),
+        }
+    }
+
+    /// Whether the client's copy is still the one we'd send.
+    /// `If-Modified-Since` is only considered when there's no `If-None-Match`.
+    fn is_fresh(&self, etag: &str, last_modified: Option<SystemTime>) -> bool {
+        if let Some(if_none_match) = &self.if_none_match {
+            // weak comparison, which is what If-None-Match calls for
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag);
+        }
+
+        match (self.if_modified_since, last_modified) {
+            (Some(if_modified_since), Some(last_modified)) => last_modified <= if_modified_since,
+            _ => false,
+        }
+    }
+}
+
+/// A strong validator for a rendered page,
+/// which changes whenever the page (or its encoding) does.
+pub fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    format!("\"{:x}\"", digest)
+}
+
+/// Tags a page we rendered with an `ETag`,
+/// and answers with a `304 Not Modified` instead if the client already has it.
+pub fn respond(conditions: &Conditions, res: Response<Bytes>) -> Response<Body> {
+    let (mut parts, body) = res.into_parts();
+    let etag = etag(&body);
+    parts
+        .headers
+        .insert(ETAG, HeaderValue::from_str(&etag).unwrap());
+
+    let last_modified = header_str(&parts.headers, LAST_MODIFIED)
+        .and_then(|date| httpdate::parse_http_date(&date).ok());
+    if !conditions.is_fresh(&etag, last_modified) {
+        return Response::from_parts(parts, Body::from(body));
+    }
+
+    let mut not_modified = Response::new(Body::empty());
+    *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+    for name in [CACHE_CONTROL, ETAG, EXPIRES, LAST_MODIFIED, VARY] {
+        for value in parts.headers.get_all(&name) {
+            not_modified.headers_mut().append(&name, value.clone());
+        }
+    }
+    not_modified
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn make_response(body: &'static str, last_modified: Option<SystemTime>) -> Response<Bytes> {
+        let mut res = Response::new(Bytes::from(body));
+        if let Some(last_modified) = last_modified {
+            res.headers_mut().insert(
+                LAST_MODIFIED,
+                HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)).unwrap(),
+            );
+        }
+        res
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let page = "<a href=\"/simple/numpy/\">numpy</a>";
+        let etag = etag(page.as_bytes());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            IF_NONE_MATCH,
+            HeaderValue::from_str(&format!("\"stale\", W/{etag}")).unwrap(),
+        );
+        let res = respond(
+            &Conditions::from_headers(&headers),
+            make_response(page, None),
+        );
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get(ETAG).unwrap(), &etag);
+
+        let res = respond(
+            &Conditions::from_headers(&headers),
+            make_response("<a href=\"/simple/scipy/\">scipy</a>", None),
+        );
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)).unwrap(),
+        );
+        let conditions = Conditions::from_headers(&headers);
+
+        let res = respond(&conditions, make_response("page", Some(last_modified)));
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        let newer = last_modified + Duration::from_secs(60);
+        let res = respond(&conditions, make_response("page", Some(newer)));
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = respond(&Conditions::default(), make_response("page", None));
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
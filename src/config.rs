@@ -0,0 +1,258 @@
+use std::path::{Path, PathBuf};
+
+use log::LevelFilter;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{server::ServerConfig, upstream::UpstreamConfig};
+
+/// Everything the proxy can be configured with, as read from a TOML file like:
+///
+/// ```toml
+/// [server]
+/// listen = "0.0.0.0:8080"
+/// tls = { cert = "cert.pem", key = "key.pem" }
+///
+/// [upstream]
+/// cache_dir = "/var/cache/pyproxide"
+/// package_config_dir = "policies"
+/// index_ttl = "10m"
+///
+/// [[upstream.indexes]]
+/// name = "pypi"
+/// urls = ["https://pypi.org/simple"]
+///
+/// # for package configs to build on, with `"extends": ["stable"]`
+/// [upstream.package_config_templates.stable]
+/// version_limits = [">=1"]
+///
+/// [log]
+/// level = "info"
+/// ```
+///
+/// Anything left out keeps its default.
+/// The same settings can be written in YAML instead, in a `.yaml` or `.yml` file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub upstream: UpstreamConfig,
+    pub log: LogConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: LevelFilter,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file at `path`.
+    /// Relative paths in it are relative to the directory the file is in,
+    /// and so is the default `package_config_dir`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let mut config = parse::<Self>(path, &contents, Syntax::Toml)?;
+        config.resolve_paths(path.parent().unwrap_or_else(|| Path::new("")));
+        Ok(config)
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |path: &mut PathBuf| *path = base.join(&*path);
+        if let Some(tls) = &mut self.server.tls {
+            resolve(&mut tls.cert);
+            resolve(&mut tls.key);
+        }
+        if let Some(cache_dir) = &mut self.upstream.cache_dir {
+            resolve(cache_dir);
+        }
+        if let Some(netrc) = &mut self.upstream.netrc {
+            resolve(netrc);
+        }
+        if let Some(gpg_keyring) = &mut self.upstream.gpg_keyring {
+            resolve(gpg_keyring);
+        }
+        if let Some(snapshot_dir) = &mut self.upstream.snapshot_dir {
+            resolve(snapshot_dir);
+        }
+        if let Some(lockfile) = &mut self.upstream.lockfile {
+            resolve(lockfile);
+        }
+        if let Some(audit_report_dir) = &mut self.upstream.audit_report_dir {
+            resolve(audit_report_dir);
+        }
+        if let Some(package_request_dir) = &mut self.upstream.package_request_dir {
+            resolve(package_request_dir);
+        }
+        resolve(&mut self.upstream.package_config_dir);
+        if let Some(package_config_file) = &mut self.upstream.package_config_file {
+            resolve(package_config_file);
+        }
+    }
+}
+
+/// A language config files can be written in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Syntax {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Syntax {
+    /// The syntax a file's extension says it's in, if it says.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Syntax::Json),
+            "toml" => Some(Syntax::Toml),
+            "yaml" | "yml" => Some(Syntax::Yaml),
+            _ => None,
+        }
+    }
+
+    /// The syntax a media type is in, e.g. from a `Content-Type` header, if it's one we know.
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        let media_type = media_type.split(';').next()?.trim().to_ascii_lowercase();
+        match media_type.as_str() {
+            "application/json" => Some(Syntax::Json),
+            "application/toml" => Some(Syntax::Toml),
+            "application/yaml" | "application/x-yaml" | "text/yaml" => Some(Syntax::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn media_type(self) -> &'static str {
+        match self {
+            Syntax::Json => "application/json",
+            Syntax::Toml => "application/toml",
+            Syntax::Yaml => "application/yaml",
+        }
+    }
+}
+
+/// Parses the contents of the config file at `path`,
+/// in the syntax its extension calls for, or `default` if it doesn't have a known one.
+pub fn parse<T: DeserializeOwned>(
+    path: &Path,
+    contents: &str,
+    default: Syntax,
+) -> Result<T, String> {
+    parse_as(contents, Syntax::from_path(path).unwrap_or(default))
+        .map_err(|e| format!("failed to parse {}: {e}", path.display()))
+}
+
+/// Parses `contents` in `syntax`.
+pub fn parse_as<T: DeserializeOwned>(contents: &str, syntax: Syntax) -> Result<T, String> {
+    match syntax {
+        Syntax::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+        Syntax::Toml => toml::from_str(contents).map_err(|e| e.to_string()),
+        Syntax::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+    }
+}
+
+/// Renders `value` in `syntax`, the way [`parse`] would read it back.
+pub fn render<T: Serialize>(value: &T, syntax: Syntax) -> Result<String, String> {
+    let result = match syntax {
+        Syntax::Json => serde_json::to_string_pretty(value)
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string()),
+        Syntax::Toml => toml::to_string(value).map_err(|e| e.to_string()),
+        Syntax::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+    };
+    result.map_err(|e| format!("failed to render config: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut config = parse::<Config>(
+            Path::new("pyproxide.toml"),
+            r#"
+[server]
+listen = "0.0.0.0:3141"
+
+[upstream]
+cache_dir = "cache"
+index_ttl = "5m"
+
+[[upstream.indexes]]
+name = "internal"
+urls = ["https://pypi.internal.example.com/simple"]
+
+[[upstream.routes]]
+pattern = "acme-*"
+index = "internal"
+
+[log]
+level = "debug"
+"#,
+            Syntax::Toml,
+        )
+        .unwrap();
+        config.resolve_paths(Path::new("/etc/pyproxide"));
+
+        assert_eq!(config.server.listen, "0.0.0.0:3141".parse().unwrap());
+        assert_eq!(config.server.max_concurrent_streams, 100);
+        assert_eq!(config.upstream.indexes[0].name, "internal");
+        assert_eq!(config.upstream.routes[0].pattern, "acme-*");
+        assert_eq!(
+            config.upstream.cache_dir,
+            Some(PathBuf::from("/etc/pyproxide/cache"))
+        );
+        assert_eq!(
+            config.upstream.package_config_dir,
+            PathBuf::from("/etc/pyproxide/fixtures")
+        );
+        assert_eq!(config.upstream.index_ttl, Duration::from_secs(300));
+        assert_eq!(config.upstream.max_retries, 3);
+        assert_eq!(config.log.level, LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_parse_unknown_field() {
+        let e = parse::<Config>(
+            Path::new("pyproxide.toml"),
+            "[upstream]\nindex_tll = \"5m\"\n",
+            Syntax::Toml,
+        )
+        .unwrap_err();
+        assert!(e.contains("unknown field `index_tll`"), "{e}");
+        assert!(e.contains("line 2"), "{e}");
+    }
+
+    #[test]
+    fn test_parse_yaml() {
+        let config = parse::<Config>(
+            Path::new("pyproxide.yaml"),
+            r#"
+server:
+  listen: 0.0.0.0:3141
+upstream:
+  index_ttl: 5m
+  indexes:
+    - name: internal
+      urls: [https://pypi.internal.example.com/simple]
+"#,
+            Syntax::Toml,
+        )
+        .unwrap();
+        assert_eq!(config.server.listen, "0.0.0.0:3141".parse().unwrap());
+        assert_eq!(config.upstream.index_ttl, Duration::from_secs(300));
+        assert_eq!(config.upstream.indexes[0].name, "internal");
+    }
+}
@@ -0,0 +1,172 @@
+// reference: https://www.rfc-editor.org/rfc/rfc7239
+
+use std::net::IpAddr;
+
+use hyper::{header::HOST, HeaderMap};
+use ipnet::IpNet;
+
+use crate::header_str;
+
+/// Works out the origin (scheme and host) that a client used to reach us,
+/// from the `Forwarded` or `X-Forwarded-Proto`/`X-Forwarded-Host` headers set by a frontend.
+///
+/// Returns `None` when the request carries no forwarding headers,
+/// in which case links should stay relative to whatever host the client used.
+pub fn external_origin(headers: &HeaderMap) -> Option<String> {
+    let (proto, host) = match header_str(headers, "forwarded".parse().unwrap()) {
+        Some(forwarded) => {
+            let forwarded = parse_forwarded(&forwarded);
+            (forwarded.proto, forwarded.host)
+        }
+        None => (
+            first_value(headers, "x-forwarded-proto"),
+            first_value(headers, "x-forwarded-host"),
+        ),
+    };
+    if proto.is_none() && host.is_none() {
+        return None;
+    }
+
+    let host = host.or_else(|| header_str(headers, HOST))?;
+    let proto = proto.unwrap_or_else(|| "http".to_owned());
+    Some(format!("{}://{}", proto.to_ascii_lowercase(), host))
+}
+
+/// Works out the address of the client a frontend forwarded a request for,
+/// from the `Forwarded` or `X-Forwarded-For` header it set.
+pub fn client(headers: &HeaderMap) -> Option<String> {
+    match header_str(headers, "forwarded".parse().unwrap()) {
+        Some(forwarded) => parse_forwarded(&forwarded).client,
+        None => first_value(headers, "x-forwarded-for"),
+    }
+}
+
+/// Whether forwarding headers from `remote` are to be believed.
+/// Anyone can send them, so they're only honored from the frontends we know about.
+pub fn is_trusted(trusted_proxies: &[IpNet], remote: IpAddr) -> bool {
+    trusted_proxies.iter().any(|net| net.contains(&remote))
+}
+
+/// What the first element of a `Forwarded` header says about the hop that the client connected to.
+#[derive(Default)]
+struct Forwarded {
+    proto: Option<String>,
+    host: Option<String>,
+    /// Its `for`.
+    client: Option<String>,
+}
+
+fn parse_forwarded(forwarded: &str) -> Forwarded {
+    let first = forwarded.split(',').next().unwrap_or_default();
+
+    let mut parsed = Forwarded::default();
+    for pair in first.split(';') {
+        let (key, value) = if let Some(pair) = pair.split_once('=') {
+            pair
+        } else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_owned();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "proto" => parsed.proto = Some(value),
+            "host" => parsed.host = Some(value),
+            "for" => parsed.client = Some(value),
+            _ => {}
+        }
+    }
+    parsed
+}
+
+/// The value set by the outermost frontend in a comma separated `X-Forwarded-*` header.
+fn first_value(headers: &HeaderMap, name: &'static str) -> Option<String> {
+    let value = header_str(headers, name.parse().unwrap())?;
+    let first = value.split(',').next().unwrap_or_default().trim();
+    if first.is_empty() {
+        None
+    } else {
+        Some(first.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn make_headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_external_origin() {
+        assert_eq!(
+            external_origin(&make_headers(&[("host", "10.0.0.5")])),
+            None
+        );
+        assert_eq!(
+            external_origin(&make_headers(&[
+                ("host", "10.0.0.5"),
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-host", "pypi.example.com, 10.0.0.2"),
+            ])),
+            Some("https://pypi.example.com".to_string()),
+        );
+        assert_eq!(
+            external_origin(&make_headers(&[
+                ("host", "pypi.example.com"),
+                ("x-forwarded-proto", "HTTPS"),
+            ])),
+            Some("https://pypi.example.com".to_string()),
+        );
+        assert_eq!(
+            external_origin(&make_headers(&[
+                ("host", "10.0.0.5"),
+                ("x-forwarded-proto", "http"),
+                (
+                    "forwarded",
+                    r#"for=192.0.2.60;proto=https;host="pypi.example.com:8443", for=10.0.0.2"#
+                ),
+            ])),
+            Some("https://pypi.example.com:8443".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_client() {
+        assert_eq!(client(&make_headers(&[("host", "10.0.0.5")])), None);
+        assert_eq!(
+            client(&make_headers(&[(
+                "x-forwarded-for",
+                "192.0.2.60, 10.0.0.2"
+            )])),
+            Some("192.0.2.60".to_string()),
+        );
+        assert_eq!(
+            client(&make_headers(&[
+                ("x-forwarded-for", "10.0.0.2"),
+                (
+                    "forwarded",
+                    r#"for="[2001:db8::1]:4711";proto=https, for=10.0.0.2"#
+                ),
+            ])),
+            Some("[2001:db8::1]:4711".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_is_trusted() {
+        let trusted_proxies = vec![
+            "10.0.0.0/8".parse::<IpNet>().unwrap(),
+            "::1/128".parse::<IpNet>().unwrap(),
+        ];
+        assert!(is_trusted(&trusted_proxies, "10.1.2.3".parse().unwrap()));
+        assert!(is_trusted(&trusted_proxies, "::1".parse().unwrap()));
+        assert!(!is_trusted(&trusted_proxies, "192.0.2.60".parse().unwrap()));
+        assert!(!is_trusted(&[], "10.1.2.3".parse().unwrap()));
+    }
+}
@@ -0,0 +1,143 @@
+// shell-style wildcards, as used in routing rules and denylists:
+//   `*` matches any run of characters
+//   `?` matches exactly one character
+//   `[abc]` / `[a-z]` / `[!abc]` match one character from (or not from) a set
+
+use std::str::FromStr;
+
+use regex::Regex;
+
+#[derive(Clone, Debug)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+
+impl PartialEq for Glob {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Eq for Glob {}
+
+impl Glob {
+    pub fn matches(&self, s: &str) -> bool {
+        self.regex.is_match(s)
+    }
+
+    /// Whether this pattern contains any wildcards at all,
+    /// i.e. whether it can match more than one string.
+    pub fn is_literal(&self) -> bool {
+        !self.pattern.contains(['*', '?', '['])
+    }
+
+    /// Like `from_str`, but ignoring case, as for license names.
+    pub fn case_insensitive(pattern: &str) -> Result<Self, String> {
+        Self::compile(pattern, true)
+    }
+
+    fn compile(pattern: &str, case_insensitive: bool) -> Result<Self, String> {
+        let mut regex_str = String::from(if case_insensitive { "(?i)^" } else { "^" });
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' => regex_str.push_str(".*"),
+                '?' => regex_str.push('.'),
+                '[' => {
+                    let mut class = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == ']' && !class.is_empty() && class != "!" {
+                            closed = true;
+                            break;
+                        }
+                        class.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("unclosed `[` in glob: `{pattern}`"));
+                    }
+
+                    regex_str.push('[');
+                    let class = match class.strip_prefix('!') {
+                        Some(negated) => {
+                            regex_str.push('^');
+                            negated.to_owned()
+                        }
+                        None => class,
+                    };
+                    for c in class.chars() {
+                        if c == '\\' || c == '[' || c == ']' || c == '^' {
+                            regex_str.push('\\');
+                        }
+                        regex_str.push(c);
+                    }
+                    regex_str.push(']');
+                }
+                other => regex_str.push_str(&regex::escape(&other.to_string())),
+            }
+        }
+        regex_str.push('$');
+
+        let regex = Regex::new(&regex_str).map_err(|e| format!("invalid glob `{pattern}`: {e}"))?;
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            regex,
+        })
+    }
+}
+
+impl ToString for Glob {
+    fn to_string(&self) -> String {
+        self.pattern.clone()
+    }
+}
+
+impl FromStr for Glob {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Self::compile(pattern, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        let glob = Glob::from_str("acme-*").unwrap();
+        assert!(glob.matches("acme-utils"));
+        assert!(!glob.matches("not-acme-utils"));
+
+        let glob = Glob::from_str("numpy-1.22.*-win32.whl").unwrap();
+        assert!(glob.matches("numpy-1.22.3-cp310-cp310-win32.whl"));
+        assert!(!glob.matches("numpy-1.22.3-cp310-cp310-win_amd64.whl"));
+        assert!(!glob.matches("numpy-1x22.3-cp310-cp310-win32.whl"));
+
+        let glob = Glob::from_str("py?-[!a-c]*").unwrap();
+        assert!(glob.matches("py3-d"));
+        assert!(!glob.matches("py3-b"));
+
+        let glob = Glob::case_insensitive("*affero*").unwrap();
+        assert!(glob.matches("GNU Affero General Public License v3"));
+        assert!(!Glob::from_str("*affero*").unwrap().matches("Affero"));
+    }
+
+    #[test]
+    fn test_glob_from_str_errors() {
+        assert_eq!(
+            Glob::from_str("numpy-[1.22"),
+            Err("unclosed `[` in glob: `numpy-[1.22`".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_glob_is_literal() {
+        assert!(Glob::from_str("acme-utils").unwrap().is_literal());
+        assert!(!Glob::from_str("acme-*").unwrap().is_literal());
+    }
+}
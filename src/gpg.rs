@@ -0,0 +1,81 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tokio::process::Command;
+
+/// Tells apart the scratch files of checks running at the same time.
+static SCRATCH_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A file in the temp dir that's removed once it's dropped.
+struct ScratchFile(PathBuf);
+
+impl ScratchFile {
+    async fn write(suffix: &str, contents: &[u8]) -> Result<Self, String> {
+        let id = SCRATCH_ID.fetch_add(1, Ordering::SeqCst);
+        let path =
+            std::env::temp_dir().join(format!("pyproxide-{}-{id}.{suffix}", std::process::id()));
+        tokio::fs::write(&path, contents)
+            .await
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Checks that `signature` is a detached signature of `artifact`
+/// made by one of the keys in `keyring`, using `gpgv`.
+pub async fn verify(keyring: &Path, artifact: &[u8], signature: &[u8]) -> Result<(), String> {
+    let artifact = ScratchFile::write("data", artifact).await?;
+    let signature = ScratchFile::write("asc", signature).await?;
+
+    // gpgv looks keyrings named without a slash up in its home directory
+    let keyring = std::path::absolute(keyring)
+        .map_err(|e| format!("failed to resolve {}: {e}", keyring.display()))?;
+    let output = Command::new("gpgv")
+        .arg("--keyring")
+        .arg(&keyring)
+        .arg(&signature.0)
+        .arg(&artifact.0)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run gpgv: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "bad GPG signature ({}): {}",
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_rejects_garbage() {
+        let keyring = std::env::temp_dir().join("pyproxide-test-missing.kbx");
+        let e = verify(&keyring, b"artifact", b"not a signature")
+            .await
+            .unwrap_err();
+        assert!(e.contains("gpgv"), "{e}");
+
+        // and leaves nothing behind
+        let prefix = format!("pyproxide-{}-", std::process::id());
+        let leftovers = std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
@@ -0,0 +1,122 @@
+// reference: https://www.rfc-editor.org/rfc/rfc7230#section-6.1
+
+use hyper::{
+    header::{HeaderName, CONNECTION, TE, TRAILER, TRANSFER_ENCODING, UPGRADE},
+    HeaderMap,
+};
+use serde::Deserialize;
+
+/// Headers which only describe a single connection,
+/// and so must never be passed on by a proxy.
+const HOP_BY_HOP: &[&str] = &[
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+];
+
+/// Removes every hop-by-hop header from `headers`,
+/// including any which the sender listed in `Connection`.
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<HeaderName>>();
+    for name in listed {
+        headers.remove(name);
+    }
+
+    for name in [CONNECTION, TE, TRAILER, TRANSFER_ENCODING, UPGRADE] {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+}
+
+/// Decides which end-to-end headers are passed through the proxy.
+/// Hop-by-hop headers are always dropped, regardless of what's configured here.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct HeaderFilter {
+    /// When set, only these headers are forwarded.
+    pub allow: Option<Vec<String>>,
+    /// Never forwarded, even if they're also allowed.
+    pub deny: Vec<String>,
+}
+
+impl HeaderFilter {
+    pub fn allows(&self, name: &HeaderName) -> bool {
+        let matches = |names: &[String]| {
+            names
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(name.as_str()))
+        };
+        let allowed = self.allow.as_deref().map(matches).unwrap_or(true);
+        allowed && !matches(&self.deny)
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        strip_hop_by_hop(headers);
+
+        let denied = headers
+            .keys()
+            .filter(|name| !self.allows(name))
+            .cloned()
+            .collect::<Vec<HeaderName>>();
+        for name in denied {
+            headers.remove(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::{CONTENT_TYPE, USER_AGENT};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_strip_hop_by_hop() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, "keep-alive, x-session".parse().unwrap());
+        headers.insert("keep-alive", "timeout=5".parse().unwrap());
+        headers.insert("x-session", "abc".parse().unwrap());
+        headers.insert(TRANSFER_ENCODING, "chunked".parse().unwrap());
+        headers.insert("proxy-authorization", "Basic Zm9v".parse().unwrap());
+        headers.insert(USER_AGENT, "pip/23.0".parse().unwrap());
+        headers.insert(CONTENT_TYPE, "text/html".parse().unwrap());
+
+        strip_hop_by_hop(&mut headers);
+
+        let mut names = headers.keys().map(HeaderName::as_str).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["content-type", "user-agent"]);
+    }
+
+    #[test]
+    fn test_header_filter() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, "close".parse().unwrap());
+        headers.insert(USER_AGENT, "pip/23.0".parse().unwrap());
+        headers.insert(CONTENT_TYPE, "text/html".parse().unwrap());
+        headers.insert("x-forwarded-for", "10.0.0.1".parse().unwrap());
+
+        let filter = HeaderFilter {
+            allow: Some(vec![
+                "User-Agent".to_string(),
+                "X-Forwarded-For".to_string(),
+            ]),
+            deny: vec!["x-forwarded-for".to_string()],
+        };
+        filter.apply(&mut headers);
+
+        let names = headers.keys().map(HeaderName::as_str).collect::<Vec<_>>();
+        assert_eq!(names, vec!["user-agent"]);
+        assert!(HeaderFilter::default().allows(&CONNECTION));
+    }
+}
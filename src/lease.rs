@@ -0,0 +1,113 @@
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::warn;
+
+/// An exclusive claim on some work, shared with every process which can see `path`,
+/// e.g. other instances with the same cache directory mounted.
+///
+/// Held until it's dropped. Holders must [`Lease::renew`] it more often than its timeout,
+/// or it's considered abandoned (e.g. because its holder crashed) and can be taken over.
+pub struct Lease {
+    path: PathBuf,
+    timeout: Duration,
+    renewed_at: Instant,
+}
+
+impl Lease {
+    /// Takes the lease at `path`, or returns `None` if someone else is holding it.
+    pub fn acquire(path: &Path, timeout: Duration) -> io::Result<Option<Lease>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        for _ in 0..2 {
+            match File::options().write(true).create_new(true).open(path) {
+                Ok(_) => {
+                    return Ok(Some(Lease {
+                        path: path.to_owned(),
+                        timeout,
+                        renewed_at: Instant::now(),
+                    }))
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+            if is_held(path, timeout) {
+                return Ok(None);
+            }
+            // moved out of the way first, so that only one of everyone
+            // who noticed it was abandoned gets to throw it out
+            let abandoned = path.with_extension(format!("abandoned-{}", std::process::id()));
+            if fs::rename(path, &abandoned).is_ok() {
+                let _ = fs::remove_file(&abandoned);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Keeps the lease from being taken over. Cheap enough to call on every chunk of work.
+    pub fn renew(&mut self) {
+        if self.renewed_at.elapsed() < self.timeout / 3 {
+            return;
+        }
+        self.renewed_at = Instant::now();
+        let result = File::options()
+            .write(true)
+            .open(&self.path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = result {
+            warn!("failed to renew lease {}: {e}", self.path.display());
+        }
+    }
+
+    /// Replaces the lease's contents, for whoever is waiting on it to read.
+    pub fn publish(&mut self, contents: &[u8]) -> io::Result<()> {
+        let partial = self.path.with_extension("lease-partial");
+        fs::write(&partial, contents)?;
+        fs::rename(&partial, &self.path)?;
+        self.renewed_at = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether anyone is holding the lease at `path`, and has renewed it within `timeout`.
+pub fn is_held(path: &Path, timeout: Duration) -> bool {
+    match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified.elapsed().unwrap_or_default() < timeout,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease() {
+        let path = std::env::temp_dir().join(format!("pyproxide-lease-{}", std::process::id()));
+        let timeout = Duration::from_secs(60);
+
+        let lease = Lease::acquire(&path, timeout).unwrap().unwrap();
+        assert!(is_held(&path, timeout));
+        assert!(Lease::acquire(&path, timeout).unwrap().is_none());
+        drop(lease);
+        assert!(!is_held(&path, timeout));
+
+        // nobody renewed it in time
+        let abandoned = Lease::acquire(&path, Duration::ZERO).unwrap().unwrap();
+        let lease = Lease::acquire(&path, Duration::ZERO).unwrap().unwrap();
+        std::mem::forget(abandoned);
+        drop(lease);
+        assert!(!path.exists());
+    }
+}
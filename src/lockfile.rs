@@ -0,0 +1,100 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::{pep_440::SpecifierSet, pep_503, requirements};
+
+#[derive(Deserialize)]
+struct PoetryLock {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+}
+
+/// The versions a compiled lockfile pins its packages to, by normalized name.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Lockfile(HashMap<String, SpecifierSet>);
+
+impl Lockfile {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        Self::parse(path, &contents).map_err(|e| format!("failed to parse {}: {e}", path.display()))
+    }
+
+    /// Reads a `poetry.lock` (or anything else ending in `.lock`) as one,
+    /// and anything else as a requirements or constraints file,
+    /// e.g. as compiled by `pip-compile` or `uv pip compile`.
+    pub fn parse(path: &Path, contents: &str) -> Result<Self, String> {
+        let mut pins = HashMap::<String, SpecifierSet>::new();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "lock")
+        {
+            let lock = toml::from_str::<PoetryLock>(contents).map_err(|e| e.to_string())?;
+            for package in lock.package {
+                let specifier_set = SpecifierSet::parse_strict(&format!("=={}", package.version))
+                    .map_err(|e| format!("package `{}`: {e}", package.name))?;
+                pins.entry(pep_503::normalize(&package.name))
+                    .or_default()
+                    .extend(specifier_set);
+            }
+        } else {
+            for requirement in requirements::parse(contents)? {
+                pins.entry(pep_503::normalize(&requirement.name))
+                    .or_default()
+                    .extend(requirement.specifier_set);
+            }
+        }
+        Ok(Self(pins))
+    }
+
+    /// What each package in the lockfile is pinned to, by normalized name.
+    pub fn pins(&self) -> &HashMap<String, SpecifierSet> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let lockfile = Lockfile::parse(
+            Path::new("requirements.txt"),
+            "# compiled\nNumPy==1.26.4\nsix==1.16.0 \\\n    --hash=sha256:abc123\n",
+        )
+        .unwrap();
+        assert_eq!(lockfile.pins()["numpy"].to_string(), "==1.26.4");
+        assert_eq!(lockfile.pins()["six"].to_string(), "==1.16.0");
+        assert!(!lockfile.pins().contains_key("requests"));
+
+        let lockfile = Lockfile::parse(
+            Path::new("poetry.lock"),
+            r#"
+[[package]]
+name = "Foo_Bar"
+version = "2.0.1"
+description = "..."
+optional = false
+python-versions = ">=3.8"
+files = []
+
+[metadata]
+lock-version = "2.0"
+"#,
+        )
+        .unwrap();
+        assert_eq!(lockfile.pins()["foo-bar"].to_string(), "==2.0.1");
+
+        assert!(Lockfile::parse(Path::new("poetry.lock"), "[[package]]\nname = 1\n").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+// reference: https://en.wikipedia.org/wiki/Cache_replacement_policies#LRU
+
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// A least recently used cache, bounded by the total weight of what's in it
+/// (e.g. a count of entries, when everything weighs 1, or bytes on disk).
+pub struct Lru<K, V> {
+    capacity: u64,
+    entries: HashMap<K, Entry<V>>,
+    /// Keys by last use, oldest first.
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+    weight: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    weight: u64,
+    last_used: u64,
+}
+
+impl<K: Clone + Eq + Hash, V> Lru<K, V> {
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            weight: 0,
+        }
+    }
+
+    pub fn contains_key<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.entries.contains_key(key)
+    }
+
+    /// Looks up `key`, marking it as the most recently used entry.
+    pub fn get<Q: Eq + Hash + ?Sized>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        let owned_key = self.entries.get_key_value(key)?.0.clone();
+        let entry = self.entries.get_mut(key)?;
+        self.clock += 1;
+        self.recency.remove(&entry.last_used);
+        entry.last_used = self.clock;
+        self.recency.insert(self.clock, owned_key);
+        Some(&entry.value)
+    }
+
+    /// Adds (or replaces) an entry, then evicts the least recently used entries
+    /// until everything fits again. Returns the entries which were evicted,
+    /// which can include `key` itself if it weighs more than the whole cache.
+    pub fn insert(&mut self, key: K, value: V, weight: u64) -> Vec<(K, V)> {
+        self.remove::<K>(&key);
+        self.clock += 1;
+        self.weight += weight;
+        self.recency.insert(self.clock, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                weight,
+                last_used: self.clock,
+            },
+        );
+
+        let mut evicted = Vec::new();
+        while self.weight > self.capacity {
+            let key = if let Some((_, key)) = self.recency.iter().next() {
+                key.clone()
+            } else {
+                break;
+            };
+            let value = self.remove::<K>(&key).unwrap();
+            evicted.push((key, value));
+        }
+        evicted
+    }
+
+    /// Every entry, without marking any of them as used.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, entry)| (key, &entry.value))
+    }
+
+    pub fn remove<Q: Eq + Hash + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let entry = self.entries.remove(key)?;
+        self.weight -= entry.weight;
+        self.recency.remove(&entry.last_used);
+        Some(entry.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut lru = Lru::new(3);
+        assert_eq!(lru.insert("numpy", 1, 1), vec![]);
+        assert_eq!(lru.insert("scipy", 2, 1), vec![]);
+        assert_eq!(lru.get(&"numpy"), Some(&1));
+        assert_eq!(lru.insert("torch", 3, 2), vec![("scipy", 2)]);
+        assert_eq!(lru.get(&"scipy"), None);
+        assert_eq!(
+            lru.insert("jax", 4, 4),
+            vec![("numpy", 1), ("torch", 3), ("jax", 4)]
+        );
+        assert!(!lru.contains_key(&"jax"));
+    }
+}
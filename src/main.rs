@@ -1,202 +1,1460 @@
-use std::{collections::HashSet, error, path::Path, str::FromStr};
+// the pep_* modules deliberately implement `ToString` directly
+// rather than going through `Display`
+#![allow(clippy::to_string_trait_impl)]
 
-use hyper::{body::HttpBody, Body, Client, Request, Response};
-use hyper_tls::HttpsConnector;
-use log::{info, log, Level, Metadata, Record};
-use serde::{Deserialize, Serialize};
-use tokio::join;
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
+    time::{Instant, SystemTime},
+};
+
+use clap::Parser;
+use futures_util::future::join_all;
+use hyper::{
+    header::{
+        HeaderName, HeaderValue, ACCEPT, ACCEPT_ENCODING, ACCEPT_RANGES, ALLOW, AUTHORIZATION,
+        CACHE_CONTROL, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LOCATION, USER_AGENT,
+        VARY,
+    },
+    Body, Response, StatusCode,
+};
+use log::{debug, error, info, warn, Metadata, Record};
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha256};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    time::MissedTickBehavior,
+};
+use url::Url;
 use warp::{
     hyper::{body::Bytes, HeaderMap, Method},
-    Filter,
+    path::{FullPath, Tail},
+    reject::MethodNotAllowed,
+    Filter, Rejection,
 };
 
 use crate::{
-    pep_427::WheelInfo,
-    pep_440::{SpecifierSet, Version},
+    cache::CacheStatus,
+    cli::{Cli, Command},
+    config::Config,
+    lru::Lru,
+    osv::{Vulnerabilities, VulnerabilityException},
+    package_config::{Filtered, PackageConfig, RequireSha256},
+    package_requests::{PackageRequest, Request},
+    pep_440::Version,
+    pep_691::Format,
+    pypi_json::UploadTimes,
+    server::RemoteAddr,
+    snapshots::Snapshot,
+    upstream::{Index, Upstream, UpstreamUrl},
 };
 
+mod admin;
+mod artifact_cache;
+mod audit_reports;
+mod cache;
+mod cache_backend;
+mod cli;
+mod compression;
+mod conditional;
+mod config;
+mod connector;
+mod credentials;
+mod forwarded;
+mod glob;
+mod gpg;
+mod headers;
+mod lease;
+mod lockfile;
+mod lru;
+mod osv;
+mod package_config;
+mod package_requests;
+mod pep_425;
 mod pep_427;
 mod pep_440;
 mod pep_503;
+mod pep_600;
+mod pep_656;
+mod pep_691;
+mod popularity;
+mod pypi_json;
+mod requirements;
+mod s3;
+mod server;
+mod single_flight;
+mod snapshots;
+mod typosquat;
+mod upstream;
+mod warm;
+
+fn not_found() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from("not found"))
+        .unwrap()
+}
+
+/// Answers for a package which is blocked by its config.
+fn blocked(package: &str, reason: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(format!("{package} is blocked: {reason}")))
+        .unwrap()
+}
+
+/// Turns away a package which isn't on the allowlist, saying why.
+/// With a `request` for it, and a `package_request_dir` to record it in,
+/// who asked for it is recorded there, for an admin to approve or reject.
+async fn unlisted(upstream: &Upstream, package: &str, request: Option<Request>) -> Response<Body> {
+    let refused = format!(
+        "{package} isn't on the allowlist: only packages with a config of their own are served"
+    );
+    let message = match (&upstream.config().package_request_dir, request) {
+        (Some(dir), Some(request)) => {
+            match record(dir.clone(), package.to_owned(), request).await {
+                Ok(Some(PackageRequest {
+                    rejected: Some(reason),
+                    ..
+                })) => format!("{package} isn't on the allowlist, and was rejected: {reason}"),
+                Ok(Some(_)) => format!("{refused}; it's been requested, for an admin to approve"),
+                Ok(None) => {
+                    warn!("not recording a request for {package}: too many packages have been requested");
+                    refused
+                }
+                Err(e) => {
+                    error!("failed to record a request for {package}: {e}");
+                    refused
+                }
+            }
+        }
+        _ => refused,
+    };
+    info!("{message}");
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+/// Records `request` for `package` off the async runtime, since it's all file I/O.
+async fn record(
+    dir: PathBuf,
+    package: String,
+    request: Request,
+) -> Result<Option<PackageRequest>, String> {
+    tokio::task::spawn_blocking(move || package_requests::record(&dir, &package, request))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn bad_request(message: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+fn header_str(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
 
-// TODO: figure out pattern to differentiate between
-// actionable errors (e.g. failed to parse version)
-// vs. unactionable errors (e.g. file doesn't exist)
+/// An index page as fetched from an upstream.
+struct Page {
+    /// Where the page was fetched from, for resolving relative links.
+    url: Url,
+    content_type: Option<String>,
+    etag: Option<String>,
+    body: Bytes,
+}
+
+impl Page {
+    /// The page's markup, if it's an HTML page we're able to read.
+    /// Pages without a `Content-Type` are assumed to be HTML, like pip does.
+    fn html(&self) -> Option<&str> {
+        let is_html = match &self.content_type {
+            None => true,
+            Some(content_type) => {
+                let mime = content_type.split(';').next().unwrap_or_default().trim();
+                mime.eq_ignore_ascii_case("text/html")
+                    || mime.eq_ignore_ascii_case("application/xhtml+xml")
+                    || mime.eq_ignore_ascii_case("application/vnd.pypi.simple.v1+html")
+            }
+        };
+        if !is_html {
+            return None;
+        }
+        std::str::from_utf8(&self.body).ok()
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct PackageConfig {
-    release_denylist: Vec<String>,
-    version_limits: String,
+    /// Identifies this version of the page: upstream's `ETag` when it sent one,
+    /// otherwise a hash of the page itself, which is still far cheaper than parsing it.
+    fn fingerprint(&self) -> String {
+        let version = match &self.etag {
+            Some(etag) => etag.clone(),
+            None => format!("{:x}", Sha256::digest(&self.body)),
+        };
+        format!("{} {version}", self.url)
+    }
 }
 
-impl PackageConfig {
-    async fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn error::Error>> {
-        Ok(serde_json::from_str(
-            &tokio::fs::read_to_string(path).await?,
-        )?)
+/// The largest requirements file `/-/warm` accepts.
+const MAX_REQUIREMENTS_SIZE: u64 = 1024 * 1024;
+
+/// How many parsed and filtered package indexes are kept in memory.
+const PARSED_PACKAGE_INDEXES: u64 = 1024;
+
+/// Asks for package indexes as they were at a point in time, e.g. `2024-06-01T00:00:00Z`,
+/// and says which point in time they were served as of.
+const X_PYPROXIDE_SNAPSHOT: &str = "x-pyproxide-snapshot";
+
+/// Asks for a package index unfiltered, with every file which would have been filtered out
+/// marked with why, e.g. to try a config out on it. Only admins may.
+const X_PYPROXIDE_DRY_RUN: &str = "x-pyproxide-dry-run";
+
+/// Says how many of a package's files were filtered out, and by which settings,
+/// e.g. `3 filtered out: version_limits 2, yanked 1`.
+const X_PYPROXIDE_FILTERED: &str = "x-pyproxide-filtered";
+
+/// Who a package index is served to.
+#[derive(Clone)]
+struct Client {
+    /// Their address, or the one a trusted frontend forwarded the request for.
+    address: Option<String>,
+    /// Whether they carry the admin token.
+    is_admin: bool,
+}
+
+/// Package indexes which have already been parsed and filtered,
+/// keyed by the package, the version of every page they were built from,
+/// and the configuration they were filtered with.
+/// Links are stored before they're rewritten to point at the proxy,
+/// since that depends on how each client reached us.
+type ParsedPackageIndexes = Mutex<Lru<String, Arc<pep_503::PackageIndex>>>;
+
+/// Hands the highest priority page to the client as we got it,
+/// for when we can't make sense of any of them.
+fn pass_through(mut res: Response<Body>, pages: Vec<Page>) -> Response<Body> {
+    if let Some(page) = pages.into_iter().next() {
+        res.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(page.body.len()));
+        *res.body_mut() = Body::from(page.body);
     }
+    res
 }
 
-async fn forward_upstream<S: AsRef<str>>(
-    uri: S,
-    method: Method,
+/// Swaps an upstream response's body for a page we rendered from it,
+/// dropping the headers which described the upstream's version of the body.
+fn rendered(res: Response<Body>, format: Format, body: String) -> Response<Bytes> {
+    let (mut parts, _) = res.into_parts();
+    for name in [CONTENT_LENGTH, CONTENT_RANGE, ACCEPT_RANGES, ETAG] {
+        parts.headers.remove(name);
+    }
+    parts.headers.remove("content-md5");
+    parts.headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("accept"));
+    Response::from_parts(parts, Bytes::from(body))
+}
+
+fn not_acceptable() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_ACCEPTABLE)
+        .header(VARY, "accept")
+        .body(Body::from(format!(
+            "can only serve {}, {}, or {}",
+            pep_691::JSON_CONTENT_TYPE,
+            pep_691::HTML_CONTENT_TYPE,
+            pep_691::LEGACY_HTML_CONTENT_TYPE,
+        )))
+        .unwrap()
+}
+
+/// Fetches `path` from each of `indexes` concurrently.
+///
+/// Returns every successful response's page in priority order,
+/// alongside the response whose head should be passed on to the client:
+/// the highest priority success, or the highest priority failure if nothing succeeded.
+async fn fetch_from_indexes(
+    upstream: &Upstream,
+    indexes: &[&Index],
+    path: &str,
     headers: HeaderMap,
     body: Bytes,
-) -> Response<String> {
-    // TODO: Make it so you can parse partial input here
-    if method != "GET" {
-        return Response::builder()
-            .status(400)
-            .body("can only forward GET requests for now".to_owned())
-            .unwrap();
+) -> (Response<Body>, Vec<Page>) {
+    let responses = join_all(
+        indexes
+            .iter()
+            .map(|index| upstream.forward_index(index, path, headers.clone(), body.clone())),
+    )
+    .await;
+
+    let mut head = None;
+    let mut first_failure = None;
+    let mut pages = vec![];
+    for mut res in responses.into_iter() {
+        if res.status().is_success() {
+            let url = res.extensions().get::<UpstreamUrl>().unwrap().0.clone();
+            let body = match hyper::body::to_bytes(res.body_mut()).await {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("failed to read {url}: {e}");
+                    continue;
+                }
+            };
+            pages.push(Page {
+                url: Url::parse(&url).unwrap(),
+                content_type: header_str(res.headers(), CONTENT_TYPE),
+                etag: header_str(res.headers(), ETAG),
+                body,
+            });
+            if head.is_none() {
+                head = Some(res);
+            }
+        } else if first_failure.is_none() {
+            first_failure = Some(res);
+        }
     }
 
-    let mut request = Request::builder().method(Method::GET).uri(uri.as_ref());
-    for (header, value) in headers.into_iter() {
-        let header = if let Some(header) = header {
-            header
+    match (head, first_failure) {
+        (Some(head), _) => (head, pages),
+        (None, Some(first_failure)) => (first_failure, pages),
+        (None, None) => (not_found(), pages),
+    }
+}
+
+/// Rewrites a link to an artifact hosted upstream
+/// so that the download flows through `/packages/...` on the proxy instead.
+fn proxy_artifact_uri(files_url: &str, base: &str, uri: &str) -> String {
+    match uri.strip_prefix(files_url) {
+        Some(path) if path.starts_with("/packages/") => format!("{base}{path}"),
+        _ => uri.to_owned(),
+    }
+}
+
+/// The merged root index, which is far too big to fetch and parse on every request.
+/// It's refreshed in the background every `root_index_refresh`,
+/// and served from here in the meantime.
+#[derive(Clone)]
+struct CachedRootIndex {
+    root_index: Arc<pep_503::RootIndex>,
+    /// Versions of the pages it was built from, to skip reparsing them when they haven't changed.
+    fingerprints: Vec<String>,
+    fetched_at: Instant,
+}
+
+type RootIndexCache = RwLock<Option<CachedRootIndex>>;
+
+/// Merges the root index pages we were able to read, or returns `None` if there weren't any.
+fn parse_root_index(pages: &[Page]) -> Option<pep_503::RootIndex> {
+    if !pages.iter().any(|page| page.html().is_some()) {
+        return None;
+    }
+
+    let mut root_index = pep_503::RootIndex::default();
+    for page in pages.iter() {
+        let html = if let Some(html) = page.html() {
+            html
         } else {
+            warn!("{}: not an HTML page, leaving it out", page.url);
             continue;
         };
+        root_index.merge(pep_503::RootIndex::from_str(html).unwrap());
+    }
+    Some(root_index)
+}
 
-        if header == "host" || header == "accept-encoding" {
-            // host -> makes cURL commands fail
-            // accept-encoding -> makes us get binary data back
-            continue;
-        }
+/// Stores a freshly fetched root index, reusing the one we already have if none of its pages changed.
+fn cache_root_index(cache: &RootIndexCache, pages: &[Page]) -> Option<Arc<pep_503::RootIndex>> {
+    let fingerprints = pages.iter().map(Page::fingerprint).collect::<Vec<String>>();
+    let unchanged = cache
+        .read()
+        .unwrap()
+        .as_ref()
+        .filter(|cached| cached.fingerprints == fingerprints)
+        .map(|cached| cached.root_index.clone());
+    let root_index = match unchanged {
+        Some(root_index) => root_index,
+        None => Arc::new(parse_root_index(pages)?),
+    };
+
+    *cache.write().unwrap() = Some(CachedRootIndex {
+        root_index: root_index.clone(),
+        fingerprints,
+        fetched_at: Instant::now(),
+    });
+    Some(root_index)
+}
 
-        request = request.header(header, value);
+/// Keeps the cached root index up to date, starting right away
+/// so that it's ready by the time the first client asks for it.
+async fn refresh_root_index(upstream: Arc<Upstream>, cache: Arc<RootIndexCache>) {
+    let mut interval = tokio::time::interval(upstream.config().root_index_refresh);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let indexes = upstream.indexes().iter().collect::<Vec<&Index>>();
+        let (res, pages) =
+            fetch_from_indexes(&upstream, &indexes, "", HeaderMap::new(), Bytes::new()).await;
+        if cache_root_index(&cache, &pages).is_none() {
+            warn!(
+                "failed to refresh the root index ({}), serving the last one we got",
+                res.status()
+            );
+        }
     }
-    let request = request.body(Body::from(body)).unwrap();
+}
 
-    // TODO: make the request of this request flow prettier
-    let https = HttpsConnector::new();
-    let client = Client::builder().build(https);
-    let mut res = client
-        .request(request)
-        .await
-        .expect("failed to make HTTP request");
+/// Keeps the most requested packages' index pages fresh in the cache,
+/// so that requests for them never have to wait on upstream.
+async fn refresh_hot_packages(upstream: Arc<Upstream>) {
+    let mut interval = tokio::time::interval(upstream.config().hot_package_refresh);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // the first tick is immediate, when nothing's been requested yet
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        upstream.refresh_hot_packages().await;
+    }
+}
 
-    let mut response = Vec::<u8>::new();
-    while let Some(Ok(chunk)) = res.body_mut().data().await {
-        response.extend(chunk);
+/// Reads audit reports again every `audit_report_refresh`, along with package configs,
+/// so that reports regenerated in place are picked up.
+async fn refresh_audit_reports(upstream: Arc<Upstream>) {
+    let mut interval = tokio::time::interval(upstream.config().audit_report_refresh);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // the first tick is immediate, when they've only just been read
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match package_config::open(upstream.config()) {
+            Ok(package_configs) => upstream.set_package_configs(package_configs),
+            Err(e) => error!("keeping the current audit reports, since rereading them (or package configs) failed: {e}"),
+        }
     }
-    let response_str = String::from_utf8(response).unwrap();
+}
 
-    let mut our_res = Response::builder().status(res.status());
-    for (header, value) in res.headers() {
-        our_res = our_res.header(header, value);
+/// Keeps the caches within their configured size and age limits.
+async fn collect_garbage(upstream: Arc<Upstream>) {
+    let mut interval = tokio::time::interval(upstream.config().gc_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        upstream.collect_garbage().await;
     }
-    our_res.body(response_str).unwrap()
 }
 
-async fn handle_root_index(method: Method, headers: HeaderMap, body: Bytes) -> Response<String> {
+async fn handle_root_index(
+    upstream: Arc<Upstream>,
+    cache: Arc<RootIndexCache>,
+    base: String,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
     info!("{} /simple/", method);
 
-    // TODO: this is REALLY slow right now. optimize!
-    let mut res = forward_upstream("https://pypi.org/simple/", method, headers, body).await;
-    let root_index = pep_503::RootIndex::from_str(res.body()).unwrap();
+    let format = if let Some(format) = Format::negotiate(header_str(&headers, ACCEPT).as_deref()) {
+        format
+    } else {
+        return not_acceptable();
+    };
+    let accept_encoding = header_str(&headers, ACCEPT_ENCODING);
+    let conditions = conditional::Conditions::from_headers(&headers);
 
-    let body = root_index.to_string();
-    res.headers_mut().remove("content-length");
-    (*res.body_mut()) = body;
+    let cached = cache.read().unwrap().clone();
+    let (res, root_index) = match cached {
+        Some(cached) => {
+            let mut res = Response::new(Body::empty());
+            CacheStatus::Hit.set_headers(res.headers_mut(), cached.fetched_at.elapsed());
+            (res, cached.root_index)
+        }
+        // only until the first background refresh finishes
+        None => {
+            let indexes = upstream.indexes().iter().collect::<Vec<&Index>>();
+            let (res, pages) = fetch_from_indexes(&upstream, &indexes, "", headers, body).await;
+            if pages.is_empty() {
+                return res;
+            }
+            match cache_root_index(&cache, &pages) {
+                Some(root_index) => (res, root_index),
+                None => return pass_through(res, pages),
+            }
+        }
+    };
 
-    res
+    let blocked = upstream.blocked_packages().await;
+    let allowed = upstream.allowed_packages().await;
+    let root_index = if blocked.is_empty() && allowed.is_none() {
+        root_index
+    } else {
+        Arc::new(pep_503::RootIndex {
+            packages: root_index
+                .packages
+                .iter()
+                .filter(|package| {
+                    let package = pep_503::normalize(package);
+                    !blocked.contains(&package)
+                        && allowed
+                            .as_ref()
+                            .is_none_or(|allowed| allowed.contains(&package))
+                })
+                .cloned()
+                .collect(),
+        })
+    };
+
+    let body = match format {
+        Format::Json => pep_691::render_root_index(&root_index),
+        Format::Html | Format::LegacyHtml => root_index.render(&base),
+    };
+    let res = rendered(res, format, body);
+    let res = compression::compress_response(accept_encoding.as_deref(), res).await;
+    conditional::respond(&conditions, res)
+}
+
+/// Parses the pages a package index is made up of, merges them,
+/// and filters the result according to the package's configuration.
+fn build_package_index(
+    upstream: &Upstream,
+    pages: &[Page],
+    upload_times: Option<&UploadTimes>,
+    vulnerabilities: Option<&Vulnerabilities>,
+    licenses: Option<&[String]>,
+    package_config: Option<PackageConfig>,
+    dry_run: bool,
+) -> pep_503::PackageIndex {
+    let mut package_index = pep_503::PackageIndex::default();
+    for page in pages.iter() {
+        let html = if let Some(html) = page.html() {
+            html
+        } else {
+            warn!("{}: not an HTML page, leaving it out", page.url);
+            continue;
+        };
+        let mut page_index = pep_503::PackageIndex::from_str(html).unwrap();
+        page_index.resolve_uris(&page.url);
+        package_index.merge(page_index);
+    }
+    if let Some(upload_times) = upload_times {
+        for release in package_index.releases.iter_mut() {
+            release.upload_time = upload_times.get(&release.name);
+        }
+    }
+    if let Some(vulnerabilities) = vulnerabilities {
+        for release in package_index.releases.iter_mut() {
+            if let Some(version) = release.version() {
+                release.vulnerabilities = vulnerabilities.affecting(&version);
+            }
+        }
+    }
+    if let Some(licenses) = licenses {
+        for release in package_index.releases.iter_mut() {
+            release.licenses = licenses.to_vec();
+        }
+    }
+
+    if let Some(package_config) = package_config {
+        let audit = package_config.is_audit();
+        let yank = package_config.filtered == Some(Filtered::Yank);
+        let mut results = package_config
+            .check_releases(&package_index.releases)
+            .into_iter();
+        let mut filtered = BTreeMap::<String, usize>::new();
+        package_index.releases.retain_mut(|release| {
+            let (rule, reason) = match results.next().unwrap() {
+                Ok(_) => return true,
+                Err((_, reason)) if audit && !dry_run => {
+                    info!("audit: would not serve {}: {reason}", release.name);
+                    upstream.record_audit_finding();
+                    return true;
+                }
+                Err(rejected) => rejected,
+            };
+            *filtered.entry(rule.to_owned()).or_default() += 1;
+            if dry_run {
+                release.filtered = Some(reason);
+                true
+            } else if yank {
+                debug!("yanking {}: {reason}", release.name);
+                release.yanked = Some(format!("blocked by policy: {reason}"));
+                true
+            } else {
+                debug!("not serving {}: {reason}", release.name);
+                false
+            }
+        });
+        package_index.filtered = filtered;
+    }
+    package_index
+}
+
+/// Builds (or reuses) the filtered package index for `package` from its pages,
+/// or in a `dry_run`, the unfiltered one, marking what would have been filtered out.
+async fn filtered_package_index(
+    upstream: &Upstream,
+    parsed: &ParsedPackageIndexes,
+    package: &str,
+    pages: &[Page],
+    package_config: Option<PackageConfig>,
+    dry_run: bool,
+) -> Arc<pep_503::PackageIndex> {
+    let upload_cutoff = package_config
+        .as_ref()
+        .and_then(|config| config.upload_cutoff());
+    let upload_times = match upload_cutoff {
+        // without them, nothing can be shown to have been uploaded early enough to serve
+        Some(_) => Some(upstream.upload_times(package).await.unwrap_or_else(|e| {
+            warn!("{package}: {e}");
+            UploadTimes::default()
+        })),
+        None => None,
+    };
+    let licenses = match &package_config {
+        // without them, nothing can be shown to be under an allowed license
+        Some(config) if config.checks_license() => {
+            Some(upstream.licenses(package).await.unwrap_or_else(|e| {
+                warn!("{package}: {e}");
+                vec![]
+            }))
+        }
+        _ => None,
+    };
+    let exceptions = upstream
+        .config()
+        .vulnerability_exceptions
+        .iter()
+        .filter(|exception| exception.is_for(package))
+        .collect::<Vec<&VulnerabilityException>>();
+    let vulnerabilities = match package_config
+        .as_ref()
+        .and_then(|config| config.vulnerability_threshold())
+    {
+        // an outage of the vulnerability database shouldn't take every install down with it
+        Some(_) => match upstream.vulnerabilities(package).await {
+            Ok(mut vulnerabilities) => {
+                for exception in exceptions
+                    .iter()
+                    .filter(|exception| !exception.is_expired())
+                {
+                    if let Ok(version) = Version::from_str(&exception.version) {
+                        vulnerabilities.except(&exception.advisory, version);
+                    }
+                }
+                Some(vulnerabilities)
+            }
+            Err(e) => {
+                warn!("{package}: not checking for known vulnerabilities: {e}");
+                None
+            }
+        },
+        None => None,
+    };
+
+    let key = {
+        let mut hasher = Sha256::new();
+        hasher.update(package);
+        for page in pages.iter() {
+            hasher.update(b"\n");
+            hasher.update(page.fingerprint());
+        }
+        hasher.update(b"\n");
+        hasher.update(serde_json::to_vec(&package_config).unwrap());
+        // which files were uploaded early enough to serve changes as time passes, not with the pages
+        if let (Some(upload_times), Some(upload_cutoff)) = (&upload_times, upload_cutoff) {
+            hasher.update(b"\n");
+            hasher.update(upload_times.count_before(upload_cutoff).to_string());
+        }
+        if let Some(vulnerabilities) = &vulnerabilities {
+            hasher.update(b"\n");
+            hasher.update(vulnerabilities.fingerprint());
+        }
+        if let Some(licenses) = &licenses {
+            hasher.update(b"\n");
+            hasher.update(licenses.join(","));
+        }
+        if dry_run {
+            hasher.update(b"\ndry run");
+        }
+        format!("{:x}", hasher.finalize())
+    };
+    let cached = parsed.lock().unwrap().get(&key).cloned();
+    match cached {
+        Some(filtered) => filtered,
+        None => {
+            // only on a miss, since an expired exception changes the key, so as not to warn on every request
+            for exception in exceptions.iter().filter(|exception| exception.is_expired()) {
+                let affected = Version::from_str(&exception.version)
+                    .ok()
+                    .zip(vulnerabilities.as_ref())
+                    .is_some_and(|(version, vulnerabilities)| {
+                        vulnerabilities
+                            .affecting(&version)
+                            .iter()
+                            .any(|vulnerability| vulnerability.id == exception.advisory)
+                    });
+                if affected {
+                    warn!(
+                        "{package} {}: the exception for {} has expired, so it's hidden again",
+                        exception.version, exception.advisory
+                    );
+                }
+            }
+            let filtered = Arc::new(build_package_index(
+                upstream,
+                pages,
+                upload_times.as_ref(),
+                vulnerabilities.as_ref(),
+                licenses.as_deref(),
+                package_config,
+                dry_run,
+            ));
+            parsed.lock().unwrap().insert(key, filtered.clone(), 1);
+            filtered
+        }
+    }
 }
 
+/// Checks that `package` is served at all, returning its config if it is:
+/// it isn't if it looks like a typosquat, isn't on the allowlist, or is blocked by its config.
+async fn admit(
+    upstream: &Upstream,
+    package: &str,
+    client: Client,
+    headers: &HeaderMap,
+) -> Result<Option<PackageConfig>, Response<Body>> {
+    if let Some(target) = upstream.typosquat_target(package).await {
+        error!(
+            "{package} was requested, which looks like a typosquat of {target}, so it's not served"
+        );
+        return Err(blocked(
+            package,
+            &format!("it looks like a typosquat of {target}, did you mean it?"),
+        ));
+    }
+    if !upstream.is_allowed(package).await {
+        let request = Request {
+            client: client.address,
+            user_agent: header_str(headers, USER_AGENT),
+            at: SystemTime::now(),
+        };
+        return Err(unlisted(upstream, package, Some(request)).await);
+    }
+    let package_config = upstream.package_config(package).await;
+    if let Some(config) = &package_config {
+        if let Some(reason) = &config.blocked {
+            if !config.is_audit() {
+                return Err(blocked(package, reason));
+            }
+            info!("audit: would block {package}: {reason}");
+            upstream.record_audit_finding();
+        }
+    }
+    Ok(package_config)
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_package_index(
+    upstream: Arc<Upstream>,
+    parsed: Arc<ParsedPackageIndexes>,
+    base: String,
+    client: Client,
     package: String,
     method: Method,
     headers: HeaderMap,
     body: Bytes,
-) -> Response<String> {
+) -> Response<Body> {
     info!("{} /simple/{}/", method, package);
 
-    let uri = format!("https://pypi.org/simple/{package}/");
+    // the package name ends up in both filesystem paths and upstream URLs,
+    // so anything which isn't a plain project name is turned away here
+    let package = match percent_decode_str(&package).decode_utf8() {
+        Ok(package) if pep_503::is_valid_project_name(&package) => package.into_owned(),
+        _ => return bad_request("invalid package name"),
+    };
+
+    let format = if let Some(format) = Format::negotiate(header_str(&headers, ACCEPT).as_deref()) {
+        format
+    } else {
+        return not_acceptable();
+    };
+    let snapshot = match header_str(&headers, HeaderName::from_static(X_PYPROXIDE_SNAPSHOT)) {
+        Some(snapshot) => match humantime::parse_rfc3339_weak(&snapshot) {
+            Ok(snapshot) => Some(snapshot),
+            Err(_) => {
+                return bad_request(
+                    "invalid X-PyProxide-Snapshot, it should be like 2024-06-01T00:00:00Z",
+                )
+            }
+        },
+        None => upstream.config().snapshot,
+    };
+    let dry_run = match header_str(&headers, HeaderName::from_static(X_PYPROXIDE_DRY_RUN)) {
+        Some(dry_run) if dry_run.eq_ignore_ascii_case("true") => true,
+        Some(dry_run) if dry_run.eq_ignore_ascii_case("false") => false,
+        Some(_) => return bad_request("invalid X-PyProxide-Dry-Run, it should be true or false"),
+        None => false,
+    };
+    if dry_run && !client.is_admin {
+        return Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("only admins can ask for a dry run"))
+            .unwrap();
+    }
+    let accept_encoding = header_str(&headers, ACCEPT_ENCODING);
+    let conditions = conditional::Conditions::from_headers(&headers);
+    let path = format!("{package}/");
+    let indexes = upstream.indexes_for(&package);
+
+    let mut package_config = match admit(&upstream, &package, client, &headers).await {
+        Ok(package_config) => package_config,
+        Err(res) => return res,
+    };
+    if let Some(snapshot) = snapshot {
+        package_config = Some(package_config.unwrap_or_default().at(snapshot));
+    }
+    if let Some(config) = &package_config {
+        if let Some(policy) = &config.reputation {
+            // it's only a heuristic, so an outage of the JSON API lets everything through
+            match upstream.reputation(&package).await {
+                Ok(reputation) => match policy.check(&reputation) {
+                    Ok(()) => {}
+                    Err(reason) if policy.is_audit() => warn!("{package}: {reason}"),
+                    Err(reason) if config.is_audit() => {
+                        info!("audit: would block {package}: {reason}");
+                        upstream.record_audit_finding();
+                    }
+                    Err(reason) => return blocked(&package, &reason),
+                },
+                Err(e) => warn!("{package}: not checking its reputation: {e}"),
+            }
+        }
+    }
+
+    let (res, pages) = fetch_from_indexes(&upstream, &indexes, &path, headers, body).await;
+    if pages.is_empty() {
+        return res;
+    }
+    upstream.record_request(&package);
+
+    let pass_through_requested = package_config
+        .as_ref()
+        .is_some_and(|config| config.pass_through);
+    if pass_through_requested || !pages.iter().any(|page| page.html().is_some()) {
+        return pass_through(res, pages);
+    }
 
-    let (mut res, package_config) = join!(
-        forward_upstream(&uri, method, headers, body),
-        PackageConfig::load(format!("fixtures/{package}.json"))
+    // why the package isn't served at all when nothing of it is left to serve, if it isn't
+    let unavailable = package_config.as_ref().and_then(|config| {
+        if let Some(pin) = &config.pin {
+            Some(format!(
+                "{package} is pinned to {pin}, which isn't available"
+            ))
+        } else if config.require_sha256 == Some(RequireSha256::Refuse) {
+            Some(format!(
+                "{package} has no releases with a sha256, which its config requires"
+            ))
+        } else {
+            None
+        }
+    });
+    let filtered = filtered_package_index(
+        &upstream,
+        &parsed,
+        &package,
+        &pages,
+        package_config,
+        dry_run,
+    )
+    .await;
+    if let Some(unavailable) = unavailable {
+        if filtered.releases.is_empty() {
+            warn!("{unavailable}");
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from(unavailable))
+                .unwrap();
+        }
+    }
+    let package_index = pep_503::PackageIndex::clone(&filtered);
+    let mut res = render_package_index(
+        &upstream,
+        res,
+        &base,
+        &package,
+        package_index,
+        format,
+        snapshot,
     );
-    let mut package_index = pep_503::PackageIndex::from_str(res.body()).unwrap();
+    if let Some(summary) = filtered.filtered_summary() {
+        res.headers_mut().insert(
+            X_PYPROXIDE_FILTERED,
+            HeaderValue::from_str(&summary).unwrap(),
+        );
+    }
+    if dry_run {
+        res.headers_mut()
+            .insert(X_PYPROXIDE_DRY_RUN, HeaderValue::from_static("true"));
+        res.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    }
+    let res = compression::compress_response(accept_encoding.as_deref(), res).await;
+    conditional::respond(&conditions, res)
+}
 
-    if let Ok(package_config) = package_config {
-        let denylisted_releases = package_config
-            .release_denylist
-            .into_iter()
-            .collect::<HashSet<String>>();
+/// Renders a filtered package index, with links to its files pointing back at us,
+/// saying which point in time it's of if it isn't of now.
+fn render_package_index(
+    upstream: &Upstream,
+    res: Response<Body>,
+    base: &str,
+    package: &str,
+    mut package_index: pep_503::PackageIndex,
+    format: Format,
+    snapshot: Option<SystemTime>,
+) -> Response<Bytes> {
+    for release in package_index.releases.iter_mut() {
+        release.uri = proxy_artifact_uri(&upstream.config().files_url, base, &release.uri);
+    }
 
-        let specifier_set = SpecifierSet::from_str(&package_config.version_limits).unwrap();
+    let body = match format {
+        Format::Json => pep_691::render_package_index(package, &package_index),
+        Format::Html | Format::LegacyHtml => package_index.to_string(),
+    };
+    let mut res = rendered(res, format, body);
+    // anything between us and the client has to tell snapshots (and dry runs) apart
+    res.headers_mut()
+        .append(VARY, HeaderValue::from_static(X_PYPROXIDE_SNAPSHOT));
+    res.headers_mut()
+        .append(VARY, HeaderValue::from_static(X_PYPROXIDE_DRY_RUN));
+    if let Some(snapshot) = snapshot {
+        let snapshot = humantime::format_rfc3339_seconds(snapshot).to_string();
+        res.headers_mut().insert(
+            X_PYPROXIDE_SNAPSHOT,
+            HeaderValue::from_str(&snapshot).unwrap(),
+        );
+    }
+    res
+}
 
-        // TODO: filter this in place to not copy memory around
-        let mut releases = vec![];
-        for release in package_index.releases.into_iter() {
-            if denylisted_releases.contains(&release.name) {
-                // TODO: this should include wildcards,
-                continue;
-            }
+/// Loads the snapshot called `name`, or explains why there isn't one to serve.
+fn load_snapshot(upstream: &Upstream, name: &str) -> Result<Snapshot, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, format!("no snapshot called {name}"));
+    let dir = match &upstream.config().snapshot_dir {
+        Some(dir) if snapshots::is_valid_name(name) => dir,
+        _ => return Err(not_found()),
+    };
+    match snapshots::load(dir, name) {
+        Ok(Some(snapshot)) => Ok(snapshot),
+        Ok(None) => Err(not_found()),
+        Err(e) => {
+            error!("{e}");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, e))
+        }
+    }
+}
 
-            if let Ok(wheel_info) = WheelInfo::from_str(&release.name) {
-                let version = Version::from_str(&wheel_info.version).unwrap();
-                if !specifier_set.contains(&version) {
-                    continue;
+fn snapshot_unavailable((status, message): (StatusCode, String)) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+/// Serves the root index of a named snapshot, linking to its package indexes.
+async fn handle_snapshot_root_index(
+    upstream: Arc<Upstream>,
+    cache: Arc<RootIndexCache>,
+    base: String,
+    name: String,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    if let Err(e) = load_snapshot(&upstream, &name) {
+        return snapshot_unavailable(e);
+    }
+    let base = format!("{base}/snapshots/{name}");
+    handle_root_index(upstream, cache, base, Method::GET, headers, body).await
+}
+
+/// Serves a package index out of a named snapshot:
+/// exactly as it was if the snapshot captured it, and as of when it was taken otherwise.
+/// Either way, packages which aren't served anymore, e.g. since they were blocked, aren't here either.
+#[allow(clippy::too_many_arguments)]
+async fn handle_snapshot_package_index(
+    upstream: Arc<Upstream>,
+    parsed: Arc<ParsedPackageIndexes>,
+    base: String,
+    client: Client,
+    name: String,
+    package: String,
+    mut headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    info!("GET /snapshots/{name}/simple/{package}/");
+
+    let snapshot = match load_snapshot(&upstream, &name) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return snapshot_unavailable(e),
+    };
+    let package = match percent_decode_str(&package).decode_utf8() {
+        Ok(package) if pep_503::is_valid_project_name(&package) => package.into_owned(),
+        _ => return bad_request("invalid package name"),
+    };
+    let taken_at = humantime::format_rfc3339_seconds(snapshot.taken_at).to_string();
+    let releases = match snapshot.packages.get(&pep_503::normalize(&package)) {
+        Some(releases) => releases.clone(),
+        None => {
+            headers.insert(
+                X_PYPROXIDE_SNAPSHOT,
+                HeaderValue::from_str(&taken_at).unwrap(),
+            );
+            return handle_package_index(
+                upstream,
+                parsed,
+                base,
+                client,
+                package,
+                Method::GET,
+                headers,
+                body,
+            )
+            .await;
+        }
+    };
+    // what it captured is only served while the package still is, e.g. not once it's blocked
+    if let Err(res) = admit(&upstream, &package, client, &headers).await {
+        return res;
+    }
+
+    let format = if let Some(format) = Format::negotiate(header_str(&headers, ACCEPT).as_deref()) {
+        format
+    } else {
+        return not_acceptable();
+    };
+    let accept_encoding = header_str(&headers, ACCEPT_ENCODING);
+    let conditions = conditional::Conditions::from_headers(&headers);
+    let res = render_package_index(
+        &upstream,
+        Response::new(Body::empty()),
+        &base,
+        &package,
+        pep_503::PackageIndex {
+            releases,
+            ..pep_503::PackageIndex::default()
+        },
+        format,
+        Some(snapshot.taken_at),
+    );
+    let res = compression::compress_response(accept_encoding.as_deref(), res).await;
+    conditional::respond(&conditions, res)
+}
+
+async fn handle_artifact(
+    upstream: Arc<Upstream>,
+    path: Tail,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    info!("{} /packages/{}", method, path.as_str());
+
+    let filename = path.as_str().rsplit('/').next().unwrap_or_default();
+    let filename = percent_decode_str(filename).decode_utf8_lossy();
+    let package = pep_503::release_package(&filename);
+    let package_config = match &package {
+        Some(package) => upstream.package_config(package).await,
+        None => None,
+    };
+
+    // nor are the files of packages off the allowlist, or of ones which can't be told
+    match &package {
+        Some(package) if !upstream.is_allowed(package).await => {
+            return unlisted(&upstream, package, None).await
+        }
+        None if upstream.config().allowlist => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from(format!(
+                    "can't tell which package {filename} is of, to check it's on the allowlist"
+                )))
+                .unwrap()
+        }
+        _ => {}
+    }
+
+    // a blocked package's files aren't served either, even to anyone who has their links
+    if let (Some(package), Some(config)) = (&package, &package_config) {
+        if let Some(reason) = config.blocked.as_ref().filter(|_| !config.is_audit()) {
+            return blocked(package, reason);
+        }
+    }
+
+    // artifacts of packages which require a GPG signature are only served once it checks out
+    let keyring = upstream.config().gpg_keyring.clone();
+    if let Some(keyring) = keyring.filter(|_| method == Method::GET) {
+        if let Some(package_config) = package_config.filter(|config| config.require_gpg_sig) {
+            match upstream
+                .forward_signed_artifact(path.as_str(), headers.clone(), &keyring)
+                .await
+            {
+                Ok(res) => return res,
+                Err((_, e)) if package_config.is_audit() => {
+                    info!("audit: would not serve {e}");
+                    upstream.record_audit_finding();
+                }
+                Err((status, e)) => {
+                    warn!("{e}");
+                    return Response::builder()
+                        .status(status)
+                        .body(Body::from(e))
+                        .unwrap();
                 }
             }
+        }
+    }
+
+    upstream
+        .forward_artifact(path.as_str(), method, headers, body)
+        .await
+}
+
+/// Pre-fetches the packages in a requirements (or constraints) file into the cache,
+/// e.g. to warm up a fresh proxy before a big CI fan-out. Only admins may.
+async fn handle_warm(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    parsed: Arc<ParsedPackageIndexes>,
+    body: Bytes,
+) -> Response<Body> {
+    if let Some(res) = admin::unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    info!("POST /-/warm");
+
+    let requirements = match std::str::from_utf8(&body)
+        .map_err(|e| e.to_string())
+        .and_then(requirements::parse)
+    {
+        Ok(requirements) => requirements,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e))
+                .unwrap()
+        }
+    };
+    let report = warm::warm(&upstream, &parsed, requirements).await;
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(&report).unwrap()))
+        .unwrap()
+}
+
+/// `GET` reports whether upstream fetches are frozen, `PUT` freezes them and `DELETE` thaws them.
+/// Only admins may.
+async fn handle_freeze(
+    upstream: Arc<Upstream>,
+    token: Option<String>,
+    authorization: Option<String>,
+    method: Method,
+) -> Response<Body> {
+    if let Some(res) = admin::unauthorized(token.as_deref(), authorization.as_deref()) {
+        return res;
+    }
+    if method == Method::PUT || method == Method::DELETE {
+        let frozen = method == Method::PUT;
+        info!("{method} /-/freeze");
+        upstream.set_frozen(frozen);
+    }
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({ "frozen": upstream.is_frozen() }).to_string(),
+        ))
+        .unwrap()
+}
 
-            let sdist_pkg = if release.name.ends_with(".tar.gz") {
-                Some(&release.name[..release.name.len() - ".tar.gz".len()])
-            } else if release.name.ends_with(".zip") {
-                Some(&release.name[..release.name.len() - ".zip".len()])
-            } else if release.name.ends_with(".sdist") {
-                Some(&release.name[..release.name.len() - ".sdist".len()])
+/// Toggles serving from the cache only on `SIGUSR1`, for when the HTTP API is out of reach.
+async fn toggle_freeze_on_signal(upstream: Arc<Upstream>) {
+    let mut signals = match signal(SignalKind::user_defined1()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("failed to listen for SIGUSR1: {e}");
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        let frozen = !upstream.is_frozen();
+        upstream.set_frozen(frozen);
+        warn!(
+            "SIGUSR1: {}",
+            if frozen {
+                "serving cached content only"
             } else {
-                None
-            };
-            if let Some(sdist_pkg) = sdist_pkg {
-                let (_, version_str) = sdist_pkg.split_once('-').unwrap();
-                match Version::from_str(version_str) {
-                    Err(e) => {
-                        log!(
-                            Level::Warn,
-                            "failed to parse version str for `{}`: {}",
-                            sdist_pkg,
-                            e
-                        );
-                        continue;
-                    }
-                    Ok(version) => {
-                        if !specifier_set.contains(&version) {
-                            continue;
-                        }
-                    }
-                }
+                "fetching from upstream again"
             }
+        );
+    }
+}
 
-            if release.name.ends_with(".egg") {
-                // Opinionated choice: we don't care about eggs anymore!
-                // We have a standardized built distribution format in wheels.
-                // If a project only publishes eggs you probably don't want to use it.
-                continue;
-            }
+async fn handle_metrics(upstream: Arc<Upstream>) -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(upstream.metrics()))
+        .unwrap()
+}
 
-            releases.push(release);
+/// Serves what every package's version is limited to as a pip constraints file,
+/// e.g. to resolve like the proxy does while not going through it.
+async fn handle_constraints(upstream: Arc<Upstream>) -> Response<Body> {
+    match upstream.package_configs().effective().await {
+        Ok(packages) => Response::builder()
+            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Body::from(package_config::render_constraints(&packages)))
+            .unwrap(),
+        Err(e) => {
+            error!("{e}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(e))
+                .unwrap()
         }
-        package_index.releases = releases;
+    }
+}
 
-        let body = package_index.to_string();
-        res.headers_mut().remove("content-length");
-        (*res.body_mut()) = body;
+/// Redirects `/simple` and `/simple/{package}` to their canonical trailing-slash form,
+/// like PyPI does, so that relative links on the page resolve the way pip expects.
+async fn redirect_to_trailing_slash(
+    base: String,
+    path: FullPath,
+    query: String,
+) -> Result<Response<Body>, Rejection> {
+    let path = path.as_str();
+    let rest = path.strip_prefix("/simple").unwrap_or_default();
+    let is_index_path = rest.is_empty() || (rest.starts_with('/') && !rest[1..].contains('/'));
+    if path.ends_with('/') || !is_index_path {
+        return Err(warp::reject::not_found());
     }
 
-    // TODO: unconditionally replace the body with the package_index result?
-    res
+    let location = if query.is_empty() {
+        format!("{base}{path}/")
+    } else {
+        format!("{base}{path}/?{query}")
+    };
+    Ok(Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(LOCATION, location)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Turns requests with a method we don't serve into a proper 405,
+/// rather than warp's default of a bare response without `Allow`.
+async fn handle_rejection(rejection: Rejection) -> Result<Response<Body>, Rejection> {
+    if rejection.find::<MethodNotAllowed>().is_none() {
+        return Err(rejection);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header(ALLOW, "GET, HEAD")
+        .body(Body::from("method not allowed"))
+        .unwrap())
+}
+
+/// The config file, if there is one, with the command line's overrides on top.
+fn load_config(cli: &Cli) -> Result<Config, String> {
+    let mut config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    cli.apply(&mut config);
+    Ok(config)
+}
+
+/// Re-reads the config and every package config, for `reason`.
+/// Nothing changes unless all of it is valid.
+///
+/// Only package configs and the log level are picked up this way;
+/// every other setting takes a restart to change.
+async fn reload(upstream: &Upstream, cli: &Cli, reason: &str) {
+    let reloaded = load_config(cli).and_then(|config| {
+        let package_configs = package_config::open(&config.upstream)?;
+        Ok((config, package_configs))
+    });
+    let (config, package_configs) = match reloaded {
+        Ok(reloaded) => reloaded,
+        Err(e) => {
+            error!("{reason}: keeping the current config, since the new one is invalid: {e}");
+            return;
+        }
+    };
+
+    let changes = match (
+        upstream.package_configs().packages().await,
+        package_configs.packages().await,
+    ) {
+        (Ok(old), Ok(new)) => package_config::describe_changes(&old, &new)
+            .unwrap_or_else(|| "no package configs changed".to_owned()),
+        (Err(e), _) | (_, Err(e)) => format!("failed to compare package configs: {e}"),
+    };
+    upstream.set_package_configs(package_configs);
+    log::set_max_level(config.log.level);
+    info!("{reason}: reloaded config ({changes})");
+}
+
+async fn reload_on_signal(upstream: Arc<Upstream>, cli: Arc<Cli>) {
+    let mut signals = match signal(SignalKind::hangup()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("failed to listen for SIGHUP: {e}");
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        reload(&upstream, &cli, "SIGHUP").await;
+    }
+}
+
+async fn reload_on_change(upstream: Arc<Upstream>, cli: Arc<Cli>) {
+    let mut watcher = match package_config::Watcher::new(upstream.config()) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("not reloading package configs when they change: {e}");
+            return;
+        }
+    };
+    loop {
+        let changed = watcher.changed().await;
+        let reason = format!(
+            "{} changed",
+            changed.into_iter().collect::<Vec<String>>().join(", ")
+        );
+        reload(&upstream, &cli, &reason).await;
+    }
+}
+
+/// Loads every config like the server would, printing what's wrong with them.
+/// Returns the exit code.
+async fn check_config(cli: &Cli) -> i32 {
+    let packages = match load_config(cli).and_then(|config| package_config::open(&config.upstream))
+    {
+        Ok(package_configs) => package_configs.packages().await,
+        Err(e) => Err(e),
+    };
+    match packages {
+        Ok(packages) => {
+            println!("ok: {} package configs", packages.len());
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            1
+        }
+    }
+}
+
+/// Prints how `package`'s config decides whether each of `files` is served.
+/// Returns the exit code.
+async fn explain(cli: &Cli, package: &str, files: &[String], requires_python: Option<&str>) -> i32 {
+    let package_config = match load_config(cli).and_then(|config| {
+        package_config::open(&config.upstream).map(|store| (config.upstream.allowlist, store))
+    }) {
+        Ok((allowlist, package_configs)) => match package_configs.has(package).await {
+            Ok(false) if allowlist => {
+                println!(
+                    "{package} isn't on the allowlist, since it has no config of its own, \
+                     so no file is served"
+                );
+                return 0;
+            }
+            Ok(_) => package_configs.get(package).await,
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    let package_config = match package_config {
+        Ok(Some(package_config)) => package_config,
+        Ok(None) => {
+            println!("{package} has no config, so every file is served");
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            return 1;
+        }
+    };
+    let audit = package_config.is_audit();
+    if audit {
+        println!("{package} is in audit mode, so every file is served, whatever its config says");
+    }
+    if let Some(reason) = &package_config.blocked {
+        if !audit {
+            println!("{package} is blocked, so no file is served: {reason}");
+            return 0;
+        }
+        println!("{package} would be blocked: {reason}");
+    }
+    if package_config.pass_through {
+        println!("{package} is passed through, so every file is served");
+        return 0;
+    }
+
+    let releases = files
+        .iter()
+        .map(|file| pep_503::Release {
+            name: file.clone(),
+            uri: file.clone(),
+            has_gpg: false,
+            requires_python: requires_python.map(str::to_owned),
+            yanked: None,
+            upload_time: None,
+            vulnerabilities: vec![],
+            licenses: vec![],
+            filtered: None,
+        })
+        .collect::<Vec<pep_503::Release>>();
+    for (file, result) in files.iter().zip(package_config.check_releases(&releases)) {
+        match result {
+            Ok(rules) => {
+                println!("{file}: served");
+                for rule in rules {
+                    println!("  {rule}");
+                }
+            }
+            Err((_, reason)) if audit => {
+                println!("{file}: served, though it wouldn't be: {reason}")
+            }
+            Err((_, reason)) if package_config.filtered == Some(Filtered::Yank) => {
+                println!("{file}: served as yanked: {reason}")
+            }
+            Err((_, reason)) => println!("{file}: not served: {reason}"),
+        }
+    }
+    0
+}
+
+fn exit_with_error(e: &str) -> ! {
+    eprintln!("error: {e}");
+    std::process::exit(1);
 }
 
 struct SimpleLogger;
 
 impl log::Log for SimpleLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Info
+        metadata.level() <= log::max_level()
     }
 
     fn log(&self, record: &Record) {
@@ -212,25 +1470,959 @@ static LOGGER: SimpleLogger = SimpleLogger;
 
 #[tokio::main]
 async fn main() {
+    let cli = Arc::new(Cli::parse());
+    match &cli.command {
+        Some(Command::CheckConfig) => std::process::exit(check_config(&cli).await),
+        Some(Command::Explain {
+            package,
+            files,
+            requires_python,
+        }) => std::process::exit(explain(&cli, package, files, requires_python.as_deref()).await),
+        None => {}
+    }
+    let config = load_config(&cli).unwrap_or_else(|e| exit_with_error(&e));
     log::set_logger(&LOGGER)
-        .map(|()| log::set_max_level(log::LevelFilter::Info))
+        .map(|()| log::set_max_level(config.log.level))
         .unwrap();
 
+    let upstream = Arc::new(Upstream::new(config.upstream).unwrap_or_else(|e| exit_with_error(&e)));
+    let problems = upstream.preflight().await;
+    if !problems.is_empty() {
+        if upstream.config().require_preflight {
+            exit_with_error(&format!("preflight failed:\n  {}", problems.join("\n  ")));
+        }
+        for problem in problems.iter() {
+            warn!("preflight: {problem}");
+        }
+        warn!("starting degraded, since preflight found problems");
+    }
+    let with_upstream = {
+        let upstream = upstream.clone();
+        warp::any().map(move || upstream.clone())
+    };
+
+    let server_config = Arc::new(config.server);
+    let external_base = {
+        let server_config = server_config.clone();
+        warp::ext::optional::<RemoteAddr>()
+            .and(warp::header::headers_cloned())
+            .map(move |remote: Option<RemoteAddr>, headers: HeaderMap| {
+                server_config.external_base(remote.map(|remote| remote.0), &headers)
+            })
+    };
+    let client = {
+        let server_config = server_config.clone();
+        warp::ext::optional::<RemoteAddr>()
+            .and(warp::header::headers_cloned())
+            .map(
+                move |remote: Option<RemoteAddr>, headers: HeaderMap| Client {
+                    address: server_config.client(remote.map(|remote| remote.0), &headers),
+                    is_admin: server_config.admin_token.as_deref().is_some_and(|token| {
+                        admin::is_authorized(token, header_str(&headers, AUTHORIZATION).as_deref())
+                    }),
+                },
+            )
+    };
+
+    // HEAD is answered like GET; hyper leaves the body off for us
+    let get_or_head = warp::get().or(warp::head()).unify();
     let capture_request = warp::filters::method::method()
         .and(warp::header::headers_cloned())
         .and(warp::filters::body::bytes());
 
-    let root_index = warp::path!("simple")
+    let trailing_slash = external_base
+        .clone()
+        .and(warp::path("simple"))
+        .and(get_or_head)
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and_then(redirect_to_trailing_slash);
+
+    let root_indexes = Arc::new(RwLock::new(None));
+    tokio::spawn(refresh_root_index(upstream.clone(), root_indexes.clone()));
+    let with_root_indexes = warp::any().map(move || root_indexes.clone());
+    let root_index = with_upstream
+        .clone()
+        .and(with_root_indexes.clone())
+        .and(external_base.clone())
+        .and(warp::path!("simple"))
+        .and(get_or_head)
         .and(capture_request)
-        .and(warp::get())
         .then(handle_root_index);
 
-    let package_index = warp::path!("simple" / String)
-        .and(warp::get())
+    let parsed = Arc::new(Mutex::new(Lru::new(PARSED_PACKAGE_INDEXES)));
+    let with_parsed = warp::any().map(move || parsed.clone());
+    tokio::spawn(refresh_hot_packages(upstream.clone()));
+    tokio::spawn(collect_garbage(upstream.clone()));
+    tokio::spawn(toggle_freeze_on_signal(upstream.clone()));
+    if upstream.config().watch_package_configs {
+        tokio::spawn(reload_on_change(upstream.clone(), cli.clone()));
+    }
+    if upstream.config().audit_report_dir.is_some() {
+        tokio::spawn(refresh_audit_reports(upstream.clone()));
+    }
+    tokio::spawn(reload_on_signal(upstream.clone(), cli));
+    let package_index = with_upstream
+        .clone()
+        .and(with_parsed.clone())
+        .and(external_base.clone())
+        .and(client.clone())
+        .and(warp::path!("simple" / String))
+        .and(get_or_head)
         .and(capture_request)
         .then(handle_package_index);
 
-    let router = root_index.or(package_index);
-    println!("Serving 127.0.0.1:8080...");
-    warp::serve(router).run(([127, 0, 0, 1], 8080)).await;
+    let snapshot_root_index = with_upstream
+        .clone()
+        .and(with_root_indexes)
+        .and(external_base.clone())
+        .and(warp::path!("snapshots" / String / "simple"))
+        .and(get_or_head)
+        .and(warp::header::headers_cloned())
+        .and(warp::filters::body::bytes())
+        .then(handle_snapshot_root_index);
+    let snapshot_package_index = with_upstream
+        .clone()
+        .and(with_parsed.clone())
+        .and(external_base)
+        .and(client)
+        .and(warp::path!("snapshots" / String / "simple" / String))
+        .and(get_or_head)
+        .and(warp::header::headers_cloned())
+        .and(warp::filters::body::bytes())
+        .then(handle_snapshot_package_index);
+
+    let admin = {
+        let token = server_config.admin_token.clone();
+        with_upstream
+            .clone()
+            .and(warp::any().map(move || token.clone()))
+            .and(warp::header::optional::<String>("authorization"))
+    };
+    let warm = admin
+        .clone()
+        .and(with_parsed.clone())
+        .and(warp::path!("-" / "warm"))
+        .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_REQUIREMENTS_SIZE))
+        .and(warp::body::bytes())
+        .then(handle_warm);
+
+    let freeze = admin
+        .clone()
+        .and(warp::path!("-" / "freeze"))
+        .and(
+            warp::get()
+                .or(warp::put())
+                .unify()
+                .or(warp::delete())
+                .unify(),
+        )
+        .and(warp::filters::method::method())
+        .then(handle_freeze);
+
+    let metrics = with_upstream
+        .clone()
+        .and(warp::path!("-" / "metrics"))
+        .and(warp::get())
+        .then(handle_metrics);
+
+    let constraints = with_upstream
+        .clone()
+        .and(warp::path!("-" / "constraints.txt"))
+        .and(get_or_head)
+        .then(handle_constraints);
+
+    let policies = admin
+        .clone()
+        .and(warp::path!("admin" / "policies"))
+        .and(warp::filters::method::method())
+        .then(admin::handle_policies);
+    let export = admin
+        .clone()
+        .and(warp::path!("admin" / "policies" / "export"))
+        .and(warp::get())
+        .and(warp::header::optional::<String>("accept"))
+        .then(admin::handle_export);
+    let import = admin
+        .clone()
+        .and(warp::path!("admin" / "policies" / "import"))
+        .and(warp::post())
+        .and(warp::header::optional::<String>("content-type"))
+        .and(warp::body::stream())
+        .then(admin::handle_import);
+    let snapshots = admin
+        .clone()
+        .and(warp::path!("admin" / "snapshots"))
+        .and(warp::filters::method::method())
+        .then(admin::handle_snapshots);
+    let snapshot = admin
+        .clone()
+        .and(with_parsed.clone())
+        .and(warp::path!("admin" / "snapshots" / String))
+        .and(warp::filters::method::method())
+        .and(warp::body::stream())
+        .then(admin::handle_snapshot);
+    let audit_reports = admin
+        .clone()
+        .and(warp::path!("admin" / "audit-reports"))
+        .and(warp::filters::method::method())
+        .then(admin::handle_audit_reports);
+    let audit_report = admin
+        .clone()
+        .and(warp::path!("admin" / "audit-reports" / String))
+        .and(warp::filters::method::method())
+        .and(warp::body::stream())
+        .then(admin::handle_audit_report);
+    let block = admin
+        .clone()
+        .and(warp::path!("admin" / "block" / String))
+        .and(warp::filters::method::method())
+        .and(warp::body::stream())
+        .then(admin::handle_block);
+    let package_requests = admin
+        .clone()
+        .and(warp::path!("admin" / "requests"))
+        .and(warp::filters::method::method())
+        .then(admin::handle_package_requests);
+    let package_request = admin
+        .clone()
+        .and(warp::path!("admin" / "requests" / String / String))
+        .and(warp::filters::method::method())
+        .and(warp::body::stream())
+        .then(admin::handle_package_request);
+    // routed after export and import, which would otherwise be taken for packages
+    let policy = admin
+        .and(warp::path!("admin" / "policies" / String))
+        .and(warp::filters::method::method())
+        .and(warp::body::stream())
+        .then(admin::handle_policy);
+
+    let artifact = with_upstream
+        .and(warp::path("packages"))
+        .and(warp::path::tail())
+        .and(get_or_head)
+        .and(capture_request)
+        .then(handle_artifact);
+
+    let router = trailing_slash
+        .or(root_index)
+        .or(package_index)
+        .or(artifact)
+        .or(warm)
+        .or(metrics)
+        .or(constraints)
+        .or(freeze)
+        .or(policies)
+        .or(export)
+        .or(import)
+        .or(policy)
+        .or(snapshot_root_index)
+        .or(snapshot_package_index)
+        .or(snapshots)
+        .or(snapshot)
+        .or(audit_reports)
+        .or(audit_report)
+        .or(block)
+        .or(package_requests)
+        .or(package_request)
+        .recover(handle_rejection);
+    println!("Serving {}...", server_config.listen);
+    if let Err(e) = server::serve(&server_config, warp::service(router)).await {
+        exit_with_error(&e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        connector::ProxyConfig,
+        upstream::{IndexConfig, UpstreamConfig},
+    };
+
+    const TOKEN: &str = "s3cret";
+
+    fn authorization() -> Option<String> {
+        Some(format!("Bearer {TOKEN}"))
+    }
+
+    fn make_parsed() -> Arc<ParsedPackageIndexes> {
+        Arc::new(Mutex::new(Lru::new(PARSED_PACKAGE_INDEXES)))
+    }
+
+    /// Serves an index with numpy 1.0 and 2.0 on it, returning where.
+    fn serve_numpy_index() -> std::net::SocketAddr {
+        let route = warp::path!("simple" / "numpy").map(|| {
+            warp::reply::html(
+                "<a href=\"numpy-1.0.tar.gz\">numpy-1.0.tar.gz</a>\n\
+                 <a href=\"numpy-2.0.tar.gz\">numpy-2.0.tar.gz</a>",
+            )
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr
+    }
+
+    /// The config of an upstream of a local index with numpy on it, keeping package configs in `dir`.
+    fn make_numpy_config(dir: &std::path::Path) -> UpstreamConfig {
+        UpstreamConfig {
+            indexes: vec![IndexConfig {
+                name: "local".to_owned(),
+                urls: vec![format!("http://{}/simple", serve_numpy_index())],
+                credentials: None,
+                credential_helper: None,
+            }],
+            netrc: None,
+            proxy: ProxyConfig::default(),
+            package_config_dir: dir.to_owned(),
+            ..UpstreamConfig::default()
+        }
+    }
+
+    /// An upstream of a local index with numpy on it, configured by `numpy_config` if there is one.
+    fn make_numpy_upstream(
+        name: &str,
+        numpy_config: Option<&str>,
+        allowlist: bool,
+    ) -> Arc<Upstream> {
+        let dir = std::env::temp_dir().join(format!("pyproxide-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        if let Some(numpy_config) = numpy_config {
+            std::fs::write(dir.join("numpy.json"), numpy_config).unwrap();
+        }
+        let upstream = Upstream::new(UpstreamConfig {
+            allowlist,
+            ..make_numpy_config(&dir)
+        })
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        Arc::new(upstream)
+    }
+
+    /// A request body of `body`.
+    fn body(body: &str) -> impl futures_util::Stream<Item = Result<Bytes, warp::Error>> + Unpin {
+        futures_util::stream::iter([Ok(Bytes::from(body.to_owned()))])
+    }
+
+    /// What `res` answered with.
+    async fn read(res: Response<Body>) -> (StatusCode, String) {
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// Asks `upstream` for numpy's page as `client`, returning what it answered with.
+    async fn get_numpy(
+        upstream: &Arc<Upstream>,
+        client: Client,
+        headers: HeaderMap,
+    ) -> (StatusCode, HeaderMap, String) {
+        let res = handle_package_index(
+            upstream.clone(),
+            make_parsed(),
+            "http://proxy".to_owned(),
+            client,
+            "numpy".to_owned(),
+            Method::GET,
+            headers,
+            Bytes::new(),
+        )
+        .await;
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn anyone() -> Client {
+        Client {
+            address: None,
+            is_admin: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warm() {
+        let upstream = Arc::new(Upstream::new(UpstreamConfig::default()).unwrap());
+        let warm = |token: Option<&str>, authorization: Option<String>| {
+            handle_warm(
+                upstream.clone(),
+                token.map(str::to_owned),
+                authorization,
+                make_parsed(),
+                Bytes::new(),
+            )
+        };
+        // without an admin token, there's nothing there
+        assert_eq!(
+            warm(None, authorization()).await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            warm(Some(TOKEN), None).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            warm(Some(TOKEN), Some("Bearer hunter2".to_owned()))
+                .await
+                .status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            warm(Some(TOKEN), authorization()).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_freeze() {
+        let upstream = Arc::new(Upstream::new(UpstreamConfig::default()).unwrap());
+        let freeze = |token: Option<&str>, authorization: Option<String>, method: Method| {
+            handle_freeze(
+                upstream.clone(),
+                token.map(str::to_owned),
+                authorization,
+                method,
+            )
+        };
+        assert_eq!(
+            freeze(None, authorization(), Method::PUT).await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            freeze(Some(TOKEN), None, Method::PUT).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            freeze(Some(TOKEN), None, Method::GET).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert!(!upstream.is_frozen());
+
+        let res = freeze(Some(TOKEN), authorization(), Method::PUT).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(upstream.is_frozen());
+        let res = freeze(Some(TOKEN), authorization(), Method::DELETE).await;
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, r#"{"frozen":false}"#);
+    }
+
+    #[tokio::test]
+    async fn test_yanked_package_index() {
+        let upstream = make_numpy_upstream(
+            "yank",
+            Some(r#"{"version_limits": "<2", "filtered": "yank"}"#),
+            false,
+        );
+        let (status, headers, body) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            headers[X_PYPROXIDE_FILTERED],
+            "1 filtered out: version_limits 1"
+        );
+        assert!(
+            body.contains("numpy-1.0.tar.gz\">numpy-1.0.tar.gz</a>"),
+            "{body}"
+        );
+        assert!(
+            body.contains(
+                "numpy-2.0.tar.gz\" data-yanked=\"blocked by policy: \
+                 version 2.0 is outside version_limits `<2`\">numpy-2.0.tar.gz</a>"
+            ),
+            "{body}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audited_package_index() {
+        let upstream = make_numpy_upstream(
+            "audit",
+            Some(r#"{"version_limits": "<2", "mode": "audit"}"#),
+            false,
+        );
+        let (status, headers, body) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(status, StatusCode::OK);
+        // nothing is filtered out, only counted as what would have been
+        assert_eq!(headers.get(X_PYPROXIDE_FILTERED), None);
+        assert!(
+            body.contains("numpy-1.0.tar.gz\">numpy-1.0.tar.gz</a>"),
+            "{body}"
+        );
+        assert!(
+            body.contains("numpy-2.0.tar.gz\">numpy-2.0.tar.gz</a>"),
+            "{body}"
+        );
+        assert!(upstream
+            .metrics()
+            .contains("pyproxide_audit_findings_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_unlisted_package_index() {
+        let upstream = make_numpy_upstream("unlisted", None, true);
+        let (status, _, body) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            "numpy isn't on the allowlist: only packages with a config of their own are served"
+        );
+
+        let upstream = make_numpy_upstream("listed", Some("{}"), true);
+        let (status, _, body) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("numpy-2.0.tar.gz"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_package_index() {
+        let upstream = make_numpy_upstream("dry-run", Some(r#"{"version_limits": "<2"}"#), false);
+        let mut headers = HeaderMap::new();
+        headers.insert(X_PYPROXIDE_DRY_RUN, HeaderValue::from_static("true"));
+        let (status, _, body) = get_numpy(&upstream, anyone(), headers.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body, "only admins can ask for a dry run");
+
+        let admin = Client {
+            address: None,
+            is_admin: true,
+        };
+        let (status, headers, body) = get_numpy(&upstream, admin, headers).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[X_PYPROXIDE_DRY_RUN], "true");
+        assert_eq!(headers[CACHE_CONTROL], "no-store");
+        assert_eq!(
+            headers[X_PYPROXIDE_FILTERED],
+            "1 filtered out: version_limits 1"
+        );
+        assert!(
+            body.contains("numpy-1.0.tar.gz\">numpy-1.0.tar.gz</a>"),
+            "{body}"
+        );
+        assert!(
+            body.contains(
+                "numpy-2.0.tar.gz\" data-pyproxide-filtered=\"\
+                 version 2.0 is outside version_limits `<2`\">numpy-2.0.tar.gz</a>"
+            ),
+            "{body}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_policy() {
+        let dir =
+            std::env::temp_dir().join(format!("pyproxide-admin-policy-{}", std::process::id()));
+        let upstream = Arc::new(Upstream::new(make_numpy_config(&dir)).unwrap());
+        let policy = |package: &str, method: Method, config: &str| {
+            admin::handle_policy(
+                upstream.clone(),
+                Some(TOKEN.to_owned()),
+                authorization(),
+                package.to_owned(),
+                method,
+                body(config),
+            )
+        };
+
+        let created = read(policy("NumPy", Method::PUT, r#"{"version_limits": "<2"}"#).await).await;
+        assert_eq!(
+            created,
+            (StatusCode::CREATED, r#"{"version_limits":"<2"}"#.to_owned())
+        );
+        let replaced =
+            read(policy("numpy", Method::PUT, r#"{"version_limits": "<3"}"#).await).await;
+        assert_eq!(replaced.0, StatusCode::OK);
+        // it applies right away
+        let (_, headers, _) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(headers.get(X_PYPROXIDE_FILTERED), None);
+        assert_eq!(
+            read(policy("numpy", Method::GET, "").await).await,
+            (StatusCode::OK, r#"{"version_limits":"<3"}"#.to_owned())
+        );
+
+        assert_eq!(
+            read(policy("numpy", Method::PUT, r#"{"version_limits": "two"}"#).await)
+                .await
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            read(policy("%2E%2E%2Fnumpy", Method::PUT, "{}").await).await,
+            (StatusCode::BAD_REQUEST, "invalid package name".to_owned())
+        );
+        assert!(!dir.parent().unwrap().join("numpy.json").exists());
+
+        assert_eq!(
+            read(policy("numpy", Method::DELETE, "").await).await,
+            (StatusCode::NO_CONTENT, String::new())
+        );
+        assert_eq!(
+            read(policy("numpy", Method::DELETE, "").await).await,
+            (StatusCode::NOT_FOUND, "numpy has no config".to_owned())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_import() {
+        let dir =
+            std::env::temp_dir().join(format!("pyproxide-admin-import-{}", std::process::id()));
+        let upstream = Arc::new(Upstream::new(make_numpy_config(&dir)).unwrap());
+        let import = |configs: &str| {
+            admin::handle_import(
+                upstream.clone(),
+                Some(TOKEN.to_owned()),
+                authorization(),
+                None,
+                body(configs),
+            )
+        };
+        let packages = || async {
+            let res = admin::handle_policies(
+                upstream.clone(),
+                Some(TOKEN.to_owned()),
+                authorization(),
+                Method::GET,
+            )
+            .await;
+            read(res).await.1
+        };
+
+        assert_eq!(
+            read(import(r#"{"packages": {"NumPy": {"version_limits": "<2"}, "six": {}}}"#).await)
+                .await,
+            (StatusCode::OK, "added numpy, six".to_owned())
+        );
+        let imported = packages().await;
+        assert_eq!(imported, r#"{"numpy":{"version_limits":"<2"},"six":{}}"#);
+
+        // nothing changes unless every one of them can be imported
+        assert_eq!(
+            read(import(r#"{"packages": {"../../etc/cron.d/x": {}, "six": {}}}"#).await).await,
+            (
+                StatusCode::BAD_REQUEST,
+                "invalid package name: ../../etc/cron.d/x".to_owned()
+            )
+        );
+        assert!(!dir.join("../../etc/cron.d/x.json").exists());
+        assert_eq!(
+            read(import(r#"{"packages": {"Foo_Bar": {}, "foo-bar": {}}}"#).await).await,
+            (
+                StatusCode::BAD_REQUEST,
+                "foo-bar has more than one config".to_owned()
+            )
+        );
+        assert_eq!(
+            read(import(r#"{"packages": {"six": {}, "numpy": {"version_limits": "two"}}}"#).await)
+                .await
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(packages().await, imported);
+
+        assert_eq!(
+            read(import(r#"{"packages": {"numpy": {}}}"#).await).await,
+            (StatusCode::OK, "changed numpy; removed six".to_owned())
+        );
+        assert!(!dir.join("six.json").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_snapshots() {
+        let dir =
+            std::env::temp_dir().join(format!("pyproxide-admin-snapshots-{}", std::process::id()));
+        let upstream = Arc::new(
+            Upstream::new(UpstreamConfig {
+                snapshot_dir: Some(dir.join("snapshots")),
+                ..make_numpy_config(&dir.join("policies"))
+            })
+            .unwrap(),
+        );
+        let parsed = make_parsed();
+        let snapshot = |name: &str, method: Method, request: &str| {
+            admin::handle_snapshot(
+                upstream.clone(),
+                Some(TOKEN.to_owned()),
+                authorization(),
+                parsed.clone(),
+                name.to_owned(),
+                method,
+                body(request),
+            )
+        };
+
+        let (status, taken) =
+            read(snapshot("lts", Method::PUT, r#"{"packages": ["NumPy"]}"#).await).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(taken.contains(r#""packages":["numpy"]"#), "{taken}");
+        assert_eq!(
+            read(snapshot("lts", Method::PUT, "").await).await,
+            (
+                StatusCode::CONFLICT,
+                "there's already a snapshot called lts".to_owned()
+            )
+        );
+        let (status, captured) = read(snapshot("lts", Method::GET, "").await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(captured.contains("numpy-2.0.tar.gz"), "{captured}");
+        let listed = admin::handle_snapshots(
+            upstream.clone(),
+            Some(TOKEN.to_owned()),
+            authorization(),
+            Method::GET,
+        )
+        .await;
+        let (status, listed) = read(listed).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(listed.starts_with(r#"{"lts":"#), "{listed}");
+
+        assert_eq!(
+            read(snapshot("../lts", Method::PUT, "").await).await,
+            (StatusCode::BAD_REQUEST, "invalid snapshot name".to_owned())
+        );
+        assert_eq!(
+            read(snapshot("next", Method::PUT, r#"{"packages": ["../six"]}"#).await).await,
+            (
+                StatusCode::BAD_REQUEST,
+                "invalid package name: ../six".to_owned()
+            )
+        );
+        assert_eq!(
+            read(snapshot("lts", Method::DELETE, "").await).await,
+            (StatusCode::NO_CONTENT, String::new())
+        );
+        assert_eq!(
+            read(snapshot("lts", Method::GET, "").await).await,
+            (StatusCode::NOT_FOUND, "no snapshot called lts".to_owned())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_audit_reports() {
+        let dir =
+            std::env::temp_dir().join(format!("pyproxide-admin-audit-{}", std::process::id()));
+        let upstream = Arc::new(
+            Upstream::new(UpstreamConfig {
+                audit_report_dir: Some(dir.join("reports")),
+                ..make_numpy_config(&dir.join("policies"))
+            })
+            .unwrap(),
+        );
+        let report = |name: &str, method: Method, report: &str| {
+            admin::handle_audit_report(
+                upstream.clone(),
+                Some(TOKEN.to_owned()),
+                authorization(),
+                name.to_owned(),
+                method,
+                body(report),
+            )
+        };
+        let pip_audit = r#"{"dependencies": [{"name": "NumPy", "version": "2.0", "vulns": [
+            {"id": "PYSEC-2024-1", "fix_versions": [], "aliases": [], "description": ""}
+        ]}], "fixes": []}"#;
+
+        assert_eq!(
+            read(report("ci", Method::PUT, pip_audit).await).await,
+            (
+                StatusCode::CREATED,
+                r#"{"numpy":["PYSEC-2024-1"]}"#.to_owned()
+            )
+        );
+        assert_eq!(
+            read(report("ci", Method::PUT, pip_audit).await).await.0,
+            StatusCode::OK
+        );
+        // it applies right away
+        let (_, headers, body) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(
+            headers[X_PYPROXIDE_FILTERED],
+            "1 filtered out: release_denylist 1"
+        );
+        assert!(!body.contains("numpy-2.0.tar.gz"), "{body}");
+        let listed = admin::handle_audit_reports(
+            upstream.clone(),
+            Some(TOKEN.to_owned()),
+            authorization(),
+            Method::GET,
+        )
+        .await;
+        assert_eq!(
+            read(listed).await,
+            (
+                StatusCode::OK,
+                r#"{"ci":{"numpy":["PYSEC-2024-1"]}}"#.to_owned()
+            )
+        );
+
+        assert_eq!(
+            read(report("../ci", Method::PUT, pip_audit).await).await,
+            (StatusCode::BAD_REQUEST, "invalid report name".to_owned())
+        );
+        assert_eq!(
+            read(report("nightly", Method::PUT, "not json").await)
+                .await
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            read(report("ci", Method::DELETE, "").await).await,
+            (StatusCode::NO_CONTENT, String::new())
+        );
+        assert_eq!(
+            read(report("ci", Method::DELETE, "").await).await,
+            (
+                StatusCode::NOT_FOUND,
+                "no audit report called ci".to_owned()
+            )
+        );
+        let (_, headers, _) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(headers.get(X_PYPROXIDE_FILTERED), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_package_requests() {
+        let dir =
+            std::env::temp_dir().join(format!("pyproxide-admin-requests-{}", std::process::id()));
+        let upstream = Arc::new(
+            Upstream::new(UpstreamConfig {
+                allowlist: true,
+                package_request_dir: Some(dir.join("requests")),
+                ..make_numpy_config(&dir.join("policies"))
+            })
+            .unwrap(),
+        );
+        let request = |package: &str, action: &str, reason: &str| {
+            admin::handle_package_request(
+                upstream.clone(),
+                Some(TOKEN.to_owned()),
+                authorization(),
+                package.to_owned(),
+                action.to_owned(),
+                Method::POST,
+                body(reason),
+            )
+        };
+
+        assert_eq!(
+            read(request("numpy", "approve", "").await).await,
+            (
+                StatusCode::NOT_FOUND,
+                "numpy hasn't been requested".to_owned()
+            )
+        );
+        let (status, _, body) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(
+            body.ends_with("it's been requested, for an admin to approve"),
+            "{body}"
+        );
+
+        let (status, rejected) = read(request("NumPy", "reject", "use our fork").await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            rejected.contains(r#""rejected":"use our fork""#),
+            "{rejected}"
+        );
+        let (status, _, body) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            "numpy isn't on the allowlist, and was rejected: use our fork"
+        );
+
+        assert_eq!(
+            read(request("numpy", "approve", r#"{"version_limits": "<2"}"#).await).await,
+            (StatusCode::OK, r#"{"version_limits":"<2"}"#.to_owned())
+        );
+        let (status, _, body) = get_numpy(&upstream, anyone(), HeaderMap::new()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!body.contains("numpy-2.0.tar.gz"), "{body}");
+        let listed = admin::handle_package_requests(
+            upstream.clone(),
+            Some(TOKEN.to_owned()),
+            authorization(),
+            Method::GET,
+        )
+        .await;
+        assert_eq!(read(listed).await, (StatusCode::OK, "{}".to_owned()));
+
+        assert_eq!(
+            read(request("%2E%2E%2Fnumpy", "reject", "").await).await,
+            (StatusCode::BAD_REQUEST, "invalid package name".to_owned())
+        );
+        assert_eq!(
+            read(request("numpy", "ignore", "").await).await.0,
+            StatusCode::NOT_FOUND
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_of_blocked_package() {
+        let dir =
+            std::env::temp_dir().join(format!("pyproxide-snapshot-blocked-{}", std::process::id()));
+        let upstream = Arc::new(
+            Upstream::new(UpstreamConfig {
+                package_config_dir: dir.join("policies"),
+                snapshot_dir: Some(dir.join("snapshots")),
+                ..UpstreamConfig::default()
+            })
+            .unwrap(),
+        );
+        let captured =
+            pep_503::PackageIndex::from_str("<a href=\"six-1.16.0.tar.gz\">six-1.16.0.tar.gz</a>")
+                .unwrap();
+        let snapshot = Snapshot {
+            taken_at: SystemTime::now(),
+            packages: BTreeMap::from([("six".to_owned(), captured.releases)]),
+        };
+        snapshots::save(&dir.join("snapshots"), "lts", &snapshot).unwrap();
+        let get = || {
+            handle_snapshot_package_index(
+                upstream.clone(),
+                make_parsed(),
+                "http://proxy".to_owned(),
+                Client {
+                    address: None,
+                    is_admin: false,
+                },
+                "lts".to_owned(),
+                "six".to_owned(),
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+        };
+        let res = get().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(std::str::from_utf8(&body)
+            .unwrap()
+            .contains("six-1.16.0.tar.gz"));
+
+        let reason = futures_util::stream::iter([Ok::<_, warp::Error>(Bytes::from("malware"))]);
+        let res = admin::handle_block(
+            upstream.clone(),
+            Some(TOKEN.to_owned()),
+            authorization(),
+            "six".to_owned(),
+            Method::POST,
+            reason,
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = get().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "six is blocked: malware");
+    }
 }
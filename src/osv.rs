@@ -0,0 +1,393 @@
+// reference: https://ossf.github.io/osv-schema/
+
+use std::{cmp::Ordering, collections::HashMap, str::FromStr, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{pep_440::Version, pep_503};
+
+/// How severe a vulnerability is, as GitHub's advisories rate them.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    #[serde(alias = "medium")]
+    Moderate,
+    High,
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "moderate" | "medium" => Ok(Self::Moderate),
+            "high" => Ok(Self::High),
+            "critical" => Ok(Self::Critical),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToString for Severity {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Low => "low",
+            Self::Moderate => "moderate",
+            Self::High => "high",
+            Self::Critical => "critical",
+        }
+        .to_owned()
+    }
+}
+
+/// A known vulnerability in a release.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Vulnerability {
+    /// e.g. `GHSA-xxxx-xxxx-xxxx` or `PYSEC-2024-1`.
+    pub id: String,
+    /// `None` when neither it nor any of its aliases says.
+    pub severity: Option<Severity>,
+}
+
+/// A known vulnerability that's been risk-accepted in a version of a package,
+/// so that it doesn't keep that version from being served, until it expires.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VulnerabilityException {
+    pub package: String,
+    pub version: String,
+    /// The advisory's id, as OSV or the audit report gives it, e.g. `GHSA-xxxx-xxxx-xxxx`.
+    pub advisory: String,
+    /// e.g. `2024-12-31T00:00:00Z`.
+    #[serde(with = "humantime_serde")]
+    pub expires: SystemTime,
+}
+
+impl VulnerabilityException {
+    pub fn is_for(&self, package: &str) -> bool {
+        pep_503::normalize(&self.package) == pep_503::normalize(package)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        SystemTime::now() >= self.expires
+    }
+}
+
+#[derive(Deserialize)]
+struct QueryResponse {
+    #[serde(default)]
+    vulns: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    id: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    affected: Vec<Affected>,
+    #[serde(default)]
+    database_specific: Option<DatabaseSpecific>,
+}
+
+#[derive(Deserialize)]
+struct DatabaseSpecific {
+    severity: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Affected {
+    package: Package,
+    #[serde(default)]
+    ranges: Vec<Range>,
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    ecosystem: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Range {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Event {
+    Introduced(String),
+    Fixed(String),
+    LastAffected(String),
+    Limit(String),
+}
+
+/// Which versions of a package an advisory affects.
+#[derive(Debug, Default)]
+struct Affects {
+    versions: Vec<Version>,
+    /// The events of each of its ranges, sorted by version.
+    ranges: Vec<Vec<(Version, EventKind)>>,
+    /// Versions it's been risk-accepted in.
+    excepted: Vec<Version>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EventKind {
+    Introduced,
+    Fixed,
+    LastAffected,
+}
+
+impl Affects {
+    fn contains(&self, version: &Version) -> bool {
+        // so that 1.0 is 1.0.0
+        let is = |other: &Version| other.partial_cmp(version) == Some(Ordering::Equal);
+        if self.excepted.iter().any(is) {
+            return false;
+        }
+        if self.versions.iter().any(is) {
+            return true;
+        }
+        self.ranges.iter().any(|events| {
+            let mut affected = false;
+            for (at, kind) in events {
+                match kind {
+                    EventKind::Introduced if version >= at => affected = true,
+                    EventKind::Fixed if version >= at => affected = false,
+                    EventKind::LastAffected if version > at => affected = false,
+                    _ => {}
+                }
+            }
+            affected
+        })
+    }
+}
+
+/// The advisories on a package, from OSV (https://osv.dev).
+#[derive(Debug, Default)]
+pub struct Vulnerabilities(Vec<(Vulnerability, Affects)>);
+
+impl Vulnerabilities {
+    /// Reads the advisories on `package` out of a response to OSV's `/v1/query`.
+    /// Versions (and ranges) which can't be parsed are left out.
+    pub fn parse(json: &[u8], package: &str) -> Result<Self, String> {
+        let response = serde_json::from_slice::<QueryResponse>(json)
+            .map_err(|e| format!("invalid OSV response: {e}"))?;
+
+        // PyPI's own advisories rarely say how severe they are, but usually alias GitHub's, which do
+        let severities = response
+            .vulns
+            .iter()
+            .filter_map(|entry| {
+                let severity = entry.database_specific.as_ref()?.severity.as_ref()?;
+                Some((entry.id.as_str(), Severity::from_str(severity).ok()?))
+            })
+            .collect::<HashMap<&str, Severity>>();
+
+        let package = pep_503::normalize(package);
+        let mut vulnerabilities = vec![];
+        for entry in response.vulns.iter() {
+            let severity = severities.get(entry.id.as_str()).copied().or_else(|| {
+                entry
+                    .aliases
+                    .iter()
+                    .filter_map(|alias| severities.get(alias.as_str()).copied())
+                    .max()
+            });
+
+            let mut affects = Affects::default();
+            for affected in entry.affected.iter() {
+                if affected.package.ecosystem != "PyPI"
+                    || pep_503::normalize(&affected.package.name) != package
+                {
+                    continue;
+                }
+                affects.versions.extend(
+                    affected
+                        .versions
+                        .iter()
+                        .filter_map(|version| Version::from_str(version).ok()),
+                );
+                for range in affected
+                    .ranges
+                    .iter()
+                    .filter(|range| range.kind == "ECOSYSTEM")
+                {
+                    let mut events = range
+                        .events
+                        .iter()
+                        .filter_map(|event| {
+                            let (version, kind) = match event {
+                                Event::Introduced(version) => (version, EventKind::Introduced),
+                                Event::Fixed(version) => (version, EventKind::Fixed),
+                                Event::LastAffected(version) => (version, EventKind::LastAffected),
+                                // nothing at or past a limit is affected, like a fix
+                                Event::Limit(version) => (version, EventKind::Fixed),
+                            };
+                            Some((Version::from_str(version).ok()?, kind))
+                        })
+                        .collect::<Vec<(Version, EventKind)>>();
+                    events.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                    affects.ranges.push(events);
+                }
+            }
+            vulnerabilities.push((
+                Vulnerability {
+                    id: entry.id.clone(),
+                    severity,
+                },
+                affects,
+            ));
+        }
+        Ok(Self(vulnerabilities))
+    }
+
+    /// The vulnerabilities which affect `version`.
+    pub fn affecting(&self, version: &Version) -> Vec<Vulnerability> {
+        self.0
+            .iter()
+            .filter(|(_, affects)| affects.contains(version))
+            .map(|(vulnerability, _)| vulnerability.clone())
+            .collect()
+    }
+
+    /// Stops `advisory` from affecting `version`, since it's been risk-accepted there.
+    pub fn except(&mut self, advisory: &str, version: Version) {
+        for (vulnerability, affects) in self.0.iter_mut() {
+            if vulnerability.id == advisory {
+                affects.excepted.push(version.clone());
+            }
+        }
+    }
+
+    /// Tells these apart from other advisories on the same package, e.g. for cache keys.
+    pub fn fingerprint(&self) -> String {
+        let mut ids = self
+            .0
+            .iter()
+            .map(|(vulnerability, affects)| {
+                let mut id = vulnerability.id.clone();
+                for version in affects.excepted.iter() {
+                    id.push_str(&format!(" except {}", version.to_string()));
+                }
+                id
+            })
+            .collect::<Vec<String>>();
+        ids.sort();
+        ids.join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json = br#"{
+            "vulns": [
+                {
+                    "id": "PYSEC-2023-1",
+                    "aliases": ["GHSA-aaaa-bbbb-cccc"],
+                    "affected": [{
+                        "package": {"ecosystem": "PyPI", "name": "Foo_Bar"},
+                        "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "1.2.0"}]}],
+                        "versions": ["1.0.0", "1.1.0"]
+                    }]
+                },
+                {
+                    "id": "GHSA-aaaa-bbbb-cccc",
+                    "affected": [{
+                        "package": {"ecosystem": "PyPI", "name": "foo-bar"},
+                        "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "1.2.0"}]}]
+                    }],
+                    "database_specific": {"severity": "HIGH"}
+                },
+                {
+                    "id": "GHSA-dddd-eeee-ffff",
+                    "affected": [
+                        {
+                            "package": {"ecosystem": "PyPI", "name": "foo-bar"},
+                            "ranges": [
+                                {"type": "ECOSYSTEM", "events": [{"last_affected": "2.1"}, {"introduced": "2.0"}]},
+                                {"type": "GIT", "events": [{"introduced": "0"}]}
+                            ]
+                        },
+                        {
+                            "package": {"ecosystem": "npm", "name": "foo-bar"},
+                            "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}]}]
+                        }
+                    ],
+                    "database_specific": {"severity": "MODERATE"}
+                },
+                {
+                    "id": "PYSEC-2024-2",
+                    "affected": [{
+                        "package": {"ecosystem": "PyPI", "name": "foo-bar"},
+                        "versions": ["3.0"]
+                    }]
+                }
+            ]
+        }"#;
+        let vulnerabilities = Vulnerabilities::parse(json, "foo-bar").unwrap();
+        let affecting = |version: &str| {
+            vulnerabilities
+                .affecting(&Version::from_str(version).unwrap())
+                .into_iter()
+                .map(|vulnerability| (vulnerability.id, vulnerability.severity))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            affecting("1.1.0"),
+            vec![
+                ("PYSEC-2023-1".to_owned(), Some(Severity::High)),
+                ("GHSA-aaaa-bbbb-cccc".to_owned(), Some(Severity::High)),
+            ],
+        );
+        assert_eq!(affecting("1.2.0"), vec![]);
+        assert_eq!(
+            affecting("2.1"),
+            vec![("GHSA-dddd-eeee-ffff".to_owned(), Some(Severity::Moderate))],
+        );
+        assert_eq!(affecting("2.1.1"), vec![]);
+        assert_eq!(affecting("3.0"), vec![("PYSEC-2024-2".to_owned(), None)]);
+        assert_eq!(
+            vulnerabilities.fingerprint(),
+            "GHSA-aaaa-bbbb-cccc,GHSA-dddd-eeee-ffff,PYSEC-2023-1,PYSEC-2024-2"
+        );
+
+        let mut vulnerabilities = vulnerabilities;
+        vulnerabilities.except("PYSEC-2024-2", Version::from_str("3.0.0").unwrap());
+        let affecting = |version: &str| {
+            vulnerabilities
+                .affecting(&Version::from_str(version).unwrap())
+                .len()
+        };
+        assert_eq!(affecting("3.0"), 0);
+        assert_eq!(affecting("1.1.0"), 2);
+        assert_eq!(
+            vulnerabilities.fingerprint(),
+            "GHSA-aaaa-bbbb-cccc,GHSA-dddd-eeee-ffff,PYSEC-2023-1,PYSEC-2024-2 except 3.0.0"
+        );
+
+        assert_eq!(
+            Vulnerabilities::parse(b"{}", "foo-bar")
+                .unwrap()
+                .fingerprint(),
+            ""
+        );
+        assert!(Vulnerabilities::parse(b"not json", "foo-bar").is_err());
+    }
+}
@@ -0,0 +1,3016 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use log::warn;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    audit_reports,
+    config::{self, Syntax},
+    glob::Glob,
+    lockfile::Lockfile,
+    osv::Severity,
+    pep_425::Environment,
+    pep_427::WheelInfo,
+    pep_440::{SpecifierSet, Version},
+    pep_503::{self, FileFormat, Release},
+    pep_600::{self, LibcVersion},
+    pep_656,
+    pypi_json::Reputation,
+    upstream::UpstreamConfig,
+};
+
+/// How a package's index page is filtered.
+/// Anything left out doesn't filter anything.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackageConfig {
+    /// Templates (from `package_config_templates`) this config is built on, in order.
+    /// Denylists add up, every version limit applies, and flags like `only_binary` can't be unset,
+    /// but settings with one value, like `pin`, `min_release_age` or `mode`, override the templates'.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
+    /// Release filenames which are never served, or globs matching them,
+    /// e.g. `numpy-1.22.*-win32.whl`, or regexes prefixed with `re:`,
+    /// or specifiers matching every file of their versions prefixed with `version:`, e.g. `version:<1.2`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub release_denylist: Vec<DenylistEntry>,
+    /// Specifiers every version served has to match, e.g. `[">=3", "<4"]`.
+    #[serde(skip_serializing_if = "SpecifierSet::is_empty")]
+    pub version_limits: SpecifierSet,
+    /// The only version served, e.g. `1.26.4`, as a clearer way of writing `==1.26.4`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+    /// Python versions the package is installed on, e.g. `["3.10", "3.11", "3.12"]`.
+    /// Releases whose requires-python excludes all of them aren't served.
+    /// Versions are compared as written, so `3.10` means 3.10.0.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub target_pythons: Vec<String>,
+    /// Hides manylinux wheels which need a newer glibc than this, e.g. `2.17` for our oldest distro,
+    /// overriding the `max_glibc` under `[upstream]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_glibc: Option<LibcVersion>,
+    /// What's done with musllinux wheels (PEP 656): `serve` them, `hide` them,
+    /// or serve `only` them of the wheels built for a platform, e.g. for Alpine-based builders,
+    /// overriding the `musllinux` under `[upstream]`. `serve` when it's left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub musllinux: Option<Musllinux>,
+    /// The kinds of machine the package is installed on (interpreter, ABI, OS and architecture);
+    /// only wheels which can be installed on one of them at least are served,
+    /// overriding the `environments` under `[upstream]`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub environments: Vec<Environment>,
+    /// Serves wheels, but never sdists, so that installing the package never means building it,
+    /// like pip's `--only-binary`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub only_binary: bool,
+    /// Serves sdists, but nothing built from them, so that the package is always built in-house,
+    /// like pip's `--no-binary`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub no_binary: bool,
+    /// Hides a version's sdists when a wheel of that version is served,
+    /// so that it's only ever built from source when it has to be.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub prefer_binary: bool,
+    /// The kinds of file served: `wheel`, `sdist`, `egg`, or `other` for anything else.
+    /// Everything but eggs when it's left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formats: Option<Vec<FileFormat>>,
+    /// What's done with yanked files. They're served, marked as yanked, when it's left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub yanked: Option<Yanked>,
+    /// Hides releases whose links don't come with a sha256, e.g. for `pip install --require-hashes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_sha256: Option<RequireSha256>,
+    /// Hides releases which aren't GPG signed.
+    /// With a `gpg_keyring` under `[upstream]`, the signature of every artifact
+    /// is also checked against it before the artifact is served.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub require_gpg_sig: bool,
+    /// Hides releases uploaded more recently than this, e.g. `7d`,
+    /// overriding the `min_release_age` under `[upstream]`.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub min_release_age: Option<Duration>,
+    /// Hides releases uploaded after this, e.g. `2024-06-01T00:00:00Z`,
+    /// to freeze the package as it was then,
+    /// overriding the `uploaded_before` under `[upstream]`.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub uploaded_before: Option<SystemTime>,
+    /// Hides releases with known vulnerabilities (going by OSV) at least this severe:
+    /// `low`, `moderate`, `high` or `critical`,
+    /// overriding the `vulnerability_severity` under `[upstream]`.
+    /// Vulnerabilities whose severity isn't known count as any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vulnerability_severity: Option<Severity>,
+    /// Serves releases regardless of their known vulnerabilities.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_vulnerabilities: bool,
+    /// Only serves the newest this many versions left after the other rules,
+    /// e.g. to keep resolvers from backtracking into ancient releases.
+    /// Pre-releases, and versions whose files are all yanked, don't count towards it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest_versions: Option<usize>,
+    /// Licenses which keep the package from being served, as globs matched case-insensitively
+    /// against each of its licenses (going by its latest release on PyPI's JSON API),
+    /// e.g. `AGPL-*` or `*Affero*`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub license_denylist: Vec<LicensePattern>,
+    /// Licenses the package has to be under (one of them, at least) to be served,
+    /// matched like `license_denylist`, e.g. `["MIT*", "Apache-2.0", "BSD*"]`.
+    /// Packages whose license can't be told aren't.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub license_allowlist: Vec<LicensePattern>,
+    /// Blocks the package when PyPI's JSON API makes it look too new to trust,
+    /// e.g. as a fresh typosquat.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reputation: Option<ReputationPolicy>,
+    /// Serves the upstream page exactly as we got it, without any parsing or filtering,
+    /// as an escape hatch for packages whose markup we can't handle.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub pass_through: bool,
+    /// Blocks the package altogether, e.g. because it's known to be malicious,
+    /// giving the reason why to anyone who asks for it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked: Option<String>,
+    /// `audit` to only log what the rest of the config would filter out (or block),
+    /// serving everything regardless, e.g. to trial a config before enforcing it.
+    /// `enforce` unless a template says otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<Mode>,
+    /// What's done with releases the rest of the config filters out:
+    /// `hide` them, or `yank` them, serving them marked as yanked (PEP 592) with the reason why,
+    /// so that pip only installs them when pinned to exactly them, and says why it won't otherwise.
+    /// `hide` when it's left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filtered: Option<Filtered>,
+}
+
+/// How established a package has to look to be served. Anything left out isn't checked.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReputationPolicy {
+    /// How long ago its first file has to have been uploaded, e.g. `30d`.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub min_project_age: Option<Duration>,
+    /// How many versions it has to have files of.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_releases: Option<usize>,
+    /// Whether it has to link to a source repository, e.g. on GitHub.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub require_repository: bool,
+    /// `audit` to only log a warning about packages which fall short, rather than block them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<Mode>,
+}
+
+impl ReputationPolicy {
+    /// Whether `reputation` is good enough: `Err` with why not if it isn't.
+    pub fn check(&self, reputation: &Reputation) -> Result<(), String> {
+        if let Some(min_project_age) = self.min_project_age {
+            let first_upload = reputation.first_upload.ok_or_else(|| {
+                "can't tell when it was first uploaded, to check reputation.min_project_age"
+                    .to_owned()
+            })?;
+            let age = SystemTime::now()
+                .duration_since(first_upload)
+                .unwrap_or_default();
+            if age < min_project_age {
+                return Err(format!(
+                    "it was first uploaded {} ago, within reputation.min_project_age `{}`",
+                    humantime::format_duration(Duration::from_secs(age.as_secs())),
+                    humantime::format_duration(min_project_age)
+                ));
+            }
+        }
+        if let Some(min_releases) = self.min_releases {
+            if reputation.releases < min_releases {
+                return Err(format!(
+                    "it has {} release{}, fewer than reputation.min_releases `{min_releases}`",
+                    reputation.releases,
+                    if reputation.releases == 1 { "" } else { "s" }
+                ));
+            }
+        }
+        if self.require_repository && reputation.repository.is_none() {
+            return Err(
+                "it links to no source repository, which reputation.require_repository requires"
+                    .to_owned(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn is_audit(&self) -> bool {
+        self.mode == Some(Mode::Audit)
+    }
+}
+
+/// Whether a package config's rules are applied.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Enforce,
+    Audit,
+}
+
+/// What's done with releases a package config filters out.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filtered {
+    Hide,
+    Yank,
+}
+
+/// What's done with musllinux wheels (PEP 656).
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Musllinux {
+    Serve,
+    /// Hides them, unless they're for some other platform as well.
+    Hide,
+    /// Hides wheels built for any other platform, leaving pure-Python wheels and sdists be.
+    Only,
+}
+
+/// What's done with yanked files (PEP 592).
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Yanked {
+    /// Served, marked as yanked, so that pip only installs them when pinned to exactly them.
+    Serve,
+    Hide,
+    /// Hidden, unless they're of the version the package is pinned to with `pin`.
+    HideUnlessPinned,
+}
+
+/// What's done with releases which don't come with a sha256.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequireSha256 {
+    /// Hides them.
+    Drop,
+    /// Hides them, and the whole package (with a 404) if that leaves nothing to serve.
+    Refuse,
+}
+
+impl PackageConfig {
+    /// The latest a release can have been uploaded and still be served, as of now, if there's a limit.
+    pub fn upload_cutoff(&self) -> Option<SystemTime> {
+        let min_release_age = self.min_release_age.map(|min_release_age| {
+            SystemTime::now()
+                .checked_sub(min_release_age)
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        });
+        match (min_release_age, self.uploaded_before) {
+            (Some(min_release_age), Some(uploaded_before)) => {
+                Some(min_release_age.min(uploaded_before))
+            }
+            (min_release_age, uploaded_before) => min_release_age.or(uploaded_before),
+        }
+    }
+
+    /// This config as it would have applied at `time`, to serve the package as it was then:
+    /// releases uploaded after it are hidden, and `min_release_age` is counted back from it.
+    pub fn at(mut self, time: SystemTime) -> Self {
+        let cutoff = match self.min_release_age.take() {
+            Some(min_release_age) => time
+                .checked_sub(min_release_age)
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+            None => time,
+        };
+        self.uploaded_before = Some(match self.uploaded_before {
+            Some(uploaded_before) => uploaded_before.min(cutoff),
+            None => cutoff,
+        });
+        self
+    }
+
+    /// How severe a known vulnerability has to be to hide a release, if they're checked at all.
+    pub fn vulnerability_threshold(&self) -> Option<Severity> {
+        self.vulnerability_severity
+            .filter(|_| !self.ignore_vulnerabilities)
+    }
+
+    /// Whether releases are checked against their licenses, which then have to be looked up.
+    pub fn checks_license(&self) -> bool {
+        !self.license_denylist.is_empty() || !self.license_allowlist.is_empty()
+    }
+
+    /// Checks `licenses` against `license_denylist` and `license_allowlist`,
+    /// like `check_release`, with the rule which lets them through, if any.
+    fn check_license(&self, licenses: &[String]) -> Result<Option<String>, (&'static str, String)> {
+        for license in licenses.iter() {
+            if let Some(pattern) = self
+                .license_denylist
+                .iter()
+                .find(|pattern| pattern.matches(license))
+            {
+                return Err((
+                    "license_denylist",
+                    format!(
+                        "its license, {license}, matches license_denylist entry `{}`",
+                        pattern.to_string()
+                    ),
+                ));
+            }
+        }
+        if self.license_allowlist.is_empty() {
+            return Ok(None);
+        }
+        if licenses.is_empty() {
+            return Err((
+                "license_allowlist",
+                "can't tell what its license is, to check license_allowlist".to_owned(),
+            ));
+        }
+        for license in licenses.iter() {
+            if let Some(pattern) = self
+                .license_allowlist
+                .iter()
+                .find(|pattern| pattern.matches(license))
+            {
+                return Ok(Some(format!(
+                    "its license, {license}, matches license_allowlist entry `{}`",
+                    pattern.to_string()
+                )));
+            }
+        }
+        Err((
+            "license_allowlist",
+            format!(
+                "its license, {}, matches no license_allowlist entry",
+                licenses.join(", ")
+            ),
+        ))
+    }
+
+    /// Whether this config's rules are only logged, rather than applied.
+    pub fn is_audit(&self) -> bool {
+        self.mode == Some(Mode::Audit)
+    }
+
+    /// Checks what serde can't, e.g. that `pin` is a valid version.
+    /// Errors come with the key of the field at fault.
+    pub fn validate(&self) -> Result<(), (&'static str, String)> {
+        if let Some(pin) = &self.pin {
+            Version::from_str(pin).map_err(|e| ("pin", e))?;
+        }
+        for python in self.target_pythons.iter() {
+            Version::from_str(python).map_err(|e| ("target_pythons", e))?;
+        }
+        if self.latest_versions == Some(0) {
+            return Err(("latest_versions", "would leave nothing to serve".to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Whether `release` is served under this config:
+    /// `Ok` with the rules which let it through, or `Err` with the rule which doesn't,
+    /// as the setting it's filtered out by, e.g. `version_limits`, and why.
+    pub fn check_release(&self, release: &Release) -> Result<Vec<String>, (&'static str, String)> {
+        let filename = release.name.as_str();
+        let mut rules = vec![];
+
+        if let Some(entry) = self
+            .release_denylist
+            .iter()
+            .find(|entry| entry.matches(filename))
+        {
+            return Err((
+                "release_denylist",
+                format!("matches release_denylist entry `{}`", entry.to_string()),
+            ));
+        }
+
+        let version = pep_503::release_version(filename);
+        let is_distribution = filename.ends_with(".whl") || pep_503::is_sdist(filename);
+        if !self.version_limits.is_empty() {
+            match &version {
+                Some(version) if self.version_limits.contains(version) => rules.push(format!(
+                    "version {} is within version_limits `{}`",
+                    version.to_string(),
+                    self.version_limits.to_string()
+                )),
+                Some(version) => {
+                    return Err((
+                        "version_limits",
+                        format!(
+                            "version {} is outside version_limits `{}`",
+                            version.to_string(),
+                            self.version_limits.to_string()
+                        ),
+                    ))
+                }
+                None if is_distribution => {
+                    return Err((
+                        "version_limits",
+                        "can't tell which version it is, to check version_limits".to_owned(),
+                    ))
+                }
+                None => {}
+            }
+        }
+
+        if let Some(pin) = &self.pin {
+            let pinned = SpecifierSet::from_str(&format!("=={pin}")).unwrap();
+            // files we can't tell the version of can't be shown to be the pinned one
+            match &version {
+                Some(version) if pinned.contains(version) => {
+                    rules.push(format!("version {} is pinned", version.to_string()))
+                }
+                Some(version) => {
+                    return Err((
+                        "pin",
+                        format!("version {} isn't the pinned {pin}", version.to_string()),
+                    ))
+                }
+                None => return Err(("pin", format!("can't tell whether it's the pinned {pin}"))),
+            }
+        }
+
+        if self.require_sha256.is_some() && release.sha256().is_none() {
+            return Err((
+                "require_sha256",
+                "it has no sha256, which require_sha256 requires".to_owned(),
+            ));
+        }
+        if self.require_gpg_sig && !release.has_gpg {
+            return Err((
+                "require_gpg_sig",
+                "it has no GPG signature, which require_gpg_sig requires".to_owned(),
+            ));
+        }
+
+        if let Some(min_release_age) = self.min_release_age {
+            let upload_time = release.upload_time.ok_or_else(|| {
+                (
+                    "min_release_age",
+                    "can't tell when it was uploaded, to check min_release_age".to_owned(),
+                )
+            })?;
+            let age = SystemTime::now()
+                .duration_since(upload_time)
+                .unwrap_or_default();
+            let uploaded = format!(
+                "it was uploaded {} ago",
+                humantime::format_duration(Duration::from_secs(age.as_secs()))
+            );
+            let limit = humantime::format_duration(min_release_age);
+            if age < min_release_age {
+                return Err((
+                    "min_release_age",
+                    format!("{uploaded}, within min_release_age `{limit}`"),
+                ));
+            }
+            rules.push(format!("{uploaded}, past min_release_age `{limit}`"));
+        }
+
+        if let Some(uploaded_before) = self.uploaded_before {
+            let upload_time = release.upload_time.ok_or_else(|| {
+                (
+                    "uploaded_before",
+                    "can't tell when it was uploaded, to check uploaded_before".to_owned(),
+                )
+            })?;
+            let uploaded = humantime::format_rfc3339_seconds(upload_time);
+            let limit = humantime::format_rfc3339_seconds(uploaded_before);
+            if upload_time > uploaded_before {
+                return Err((
+                    "uploaded_before",
+                    format!("it was uploaded at {uploaded}, after uploaded_before `{limit}`"),
+                ));
+            }
+            rules.push(format!(
+                "it was uploaded at {uploaded}, by uploaded_before `{limit}`"
+            ));
+        }
+
+        if let Some(threshold) = self.vulnerability_threshold() {
+            let vulnerability = release.vulnerabilities.iter().find(|vulnerability| {
+                vulnerability
+                    .severity
+                    .is_none_or(|severity| severity >= threshold)
+            });
+            if let Some(vulnerability) = vulnerability {
+                let severity = vulnerability
+                    .severity
+                    .map_or("unknown".to_owned(), |severity| severity.to_string());
+                return Err((
+                    "vulnerability_severity",
+                    format!(
+                        "it has a known vulnerability, {} ({severity} severity), \
+                         at or above vulnerability_severity `{}`",
+                        vulnerability.id,
+                        threshold.to_string()
+                    ),
+                ));
+            }
+            if !release.vulnerabilities.is_empty() {
+                rules.push(format!(
+                    "its known vulnerabilities are all below vulnerability_severity `{}`",
+                    threshold.to_string()
+                ));
+            }
+        }
+
+        if let Some(rule) = self.check_license(&release.licenses)? {
+            rules.push(rule);
+        }
+
+        if let Some(reason) = &release.yanked {
+            let yanked = match reason.as_str() {
+                "" => "it's yanked".to_owned(),
+                reason => format!("it's yanked ({reason})"),
+            };
+            match self.yanked {
+                Some(Yanked::Hide) => {
+                    return Err(("yanked", format!("{yanked}, and yanked is `hide`")))
+                }
+                // having gotten this far, it's of the pinned version
+                Some(Yanked::HideUnlessPinned) if self.pin.is_some() => {
+                    rules.push(format!("{yanked}, but pinned"))
+                }
+                Some(Yanked::HideUnlessPinned) => {
+                    return Err(("yanked", format!("{yanked}, and isn't pinned")))
+                }
+                Some(Yanked::Serve) | None => {}
+            }
+        }
+
+        // like pip, which ignores a requires-python it can't parse
+        let requires_python = release
+            .requires_python
+            .as_deref()
+            .and_then(|requires_python| SpecifierSet::parse_strict(requires_python).ok());
+        match requires_python {
+            Some(requires_python) if !self.target_pythons.is_empty() => {
+                let python = self.target_pythons.iter().find(|python| {
+                    Version::from_str(python).is_ok_and(|python| requires_python.contains(&python))
+                });
+                match python {
+                    Some(python) => rules.push(format!(
+                        "Python {python} satisfies requires-python `{}`",
+                        requires_python.to_string()
+                    )),
+                    None => {
+                        return Err((
+                            "target_pythons",
+                            format!(
+                                "requires-python `{}` excludes every one of target_pythons",
+                                requires_python.to_string()
+                            ),
+                        ))
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(max_glibc) = self.max_glibc {
+            // a wheel tagged for several platforms installs wherever any one of them does,
+            // so it only needs a newer glibc if every one of them is a manylinux tag
+            let glibc = WheelInfo::from_str(filename).ok().and_then(|wheel| {
+                wheel
+                    .platform_tags()
+                    .map(pep_600::glibc)
+                    .collect::<Option<Vec<_>>>()?
+                    .into_iter()
+                    .min()
+            });
+            if let Some(glibc) = glibc {
+                if glibc > max_glibc {
+                    return Err((
+                        "max_glibc",
+                        format!(
+                            "it needs glibc {}, newer than max_glibc `{}`",
+                            glibc.to_string(),
+                            max_glibc.to_string()
+                        ),
+                    ));
+                }
+                rules.push(format!(
+                    "it needs glibc {}, within max_glibc `{}`",
+                    glibc.to_string(),
+                    max_glibc.to_string()
+                ));
+            }
+        }
+
+        if let Some(rule) = self.check_musllinux(filename)? {
+            rules.push(rule);
+        }
+
+        if !self.environments.is_empty() {
+            if let Ok(wheel) = WheelInfo::from_str(filename) {
+                match self
+                    .environments
+                    .iter()
+                    .find(|environment| environment.supports(&wheel))
+                {
+                    Some(environment) => rules.push(format!(
+                        "it can be installed in environments entry `{}`",
+                        environment.spec.name
+                    )),
+                    None => {
+                        return Err((
+                            "environments",
+                            "it can't be installed in any of environments".to_owned(),
+                        ))
+                    }
+                }
+            }
+        }
+
+        if self.only_binary && pep_503::is_sdist(filename) {
+            return Err((
+                "only_binary",
+                "sdists aren't served, since only_binary is set".to_owned(),
+            ));
+        }
+        if self.no_binary && !pep_503::is_sdist(filename) {
+            return Err((
+                "no_binary",
+                "only sdists are served, since no_binary is set".to_owned(),
+            ));
+        }
+
+        let format = FileFormat::of(filename);
+        match &self.formats {
+            Some(formats) if formats.contains(&format) => {
+                rules.push(format!("{} files are in formats", format.to_string()))
+            }
+            Some(_) => {
+                return Err((
+                    "formats",
+                    format!("{} files aren't in formats", format.to_string()),
+                ))
+            }
+            // Opinionated choice: we don't care about eggs anymore!
+            // We have a standardized built distribution format in wheels.
+            // If a project only publishes eggs you probably don't want to use it.
+            None if format == FileFormat::Egg => {
+                return Err((
+                    "formats",
+                    "eggs aren't served unless they're in formats".to_owned(),
+                ))
+            }
+            None => {}
+        }
+
+        Ok(rules)
+    }
+
+    fn check_musllinux(&self, filename: &str) -> Result<Option<String>, (&'static str, String)> {
+        let musllinux = match self.musllinux {
+            Some(musllinux) if musllinux != Musllinux::Serve => musllinux,
+            _ => return Ok(None),
+        };
+        let wheel = match WheelInfo::from_str(filename) {
+            Ok(wheel) => wheel,
+            Err(_) => return Ok(None),
+        };
+        let musl = wheel.platform_tags().filter_map(pep_656::musl).min();
+        match (musllinux, musl) {
+            (Musllinux::Hide, Some(musl))
+                if wheel
+                    .platform_tags()
+                    .all(|tag| pep_656::musl(tag).is_some()) =>
+            {
+                Err((
+                    "musllinux",
+                    format!(
+                        "it's a musllinux wheel (musl {}), and musllinux is `hide`",
+                        musl.to_string()
+                    ),
+                ))
+            }
+            (Musllinux::Only, Some(musl)) => Ok(Some(format!(
+                "it's a musllinux wheel (musl {}), and musllinux is `only`",
+                musl.to_string()
+            ))),
+            (Musllinux::Only, None) if wheel.platform_tags().all(|tag| tag != "any") => Err((
+                "musllinux",
+                "it's built for a platform other than musllinux, and musllinux is `only`"
+                    .to_owned(),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    /// Like [`PackageConfig::check_release`] for each of a package's `releases`, in order,
+    /// along with the rules which depend on its other releases, e.g. `prefer_binary`.
+    pub fn check_releases(
+        &self,
+        releases: &[Release],
+    ) -> Vec<Result<Vec<String>, (&'static str, String)>> {
+        let mut results = releases
+            .iter()
+            .map(|release| self.check_release(release))
+            .collect::<Vec<_>>();
+        if self.prefer_binary {
+            self.check_prefer_binary(releases, &mut results);
+        }
+        if let Some(latest_versions) = self.latest_versions {
+            check_latest_versions(latest_versions, releases, &mut results);
+        }
+        results
+    }
+
+    fn check_prefer_binary(
+        &self,
+        releases: &[Release],
+        results: &mut [Result<Vec<String>, (&'static str, String)>],
+    ) {
+        let with_wheels = releases
+            .iter()
+            .zip(results.iter())
+            .filter(|(release, result)| result.is_ok() && release.name.ends_with(".whl"))
+            .filter_map(|(release, _)| release.version())
+            .collect::<Vec<Version>>();
+        for (release, result) in releases.iter().zip(results.iter_mut()) {
+            let rules = match result {
+                Ok(rules) if pep_503::is_sdist(&release.name) => rules,
+                _ => continue,
+            };
+            // sdists we can't tell the version of can't be shown to have a wheel
+            let version = if let Some(version) = release.version() {
+                version
+            } else {
+                continue;
+            };
+            if with_wheels.contains(&version) {
+                *result = Err((
+                    "prefer_binary",
+                    format!(
+                        "a wheel of version {} is served instead, since prefer_binary is set",
+                        version.to_string()
+                    ),
+                ));
+            } else {
+                rules.push(format!(
+                    "no wheel of version {} is served, to prefer to it",
+                    version.to_string()
+                ));
+            }
+        }
+    }
+
+    /// This config on top of the templates it extends, and whichever templates those extend.
+    pub fn resolve(
+        self,
+        templates: &HashMap<String, PackageConfig>,
+    ) -> Result<Self, (&'static str, String)> {
+        let resolved = self.resolve_with(templates, &mut vec![])?;
+        if resolved.only_binary && resolved.no_binary {
+            return Err((
+                "no_binary",
+                "can't be set along with only_binary, which would leave nothing to serve"
+                    .to_owned(),
+            ));
+        }
+        Ok(resolved)
+    }
+
+    /// `extending` holds the templates being resolved already, to catch templates extending themselves.
+    fn resolve_with(
+        mut self,
+        templates: &HashMap<String, PackageConfig>,
+        extending: &mut Vec<String>,
+    ) -> Result<Self, (&'static str, String)> {
+        let mut resolved = Self::default();
+        for name in std::mem::take(&mut self.extends) {
+            if extending.contains(&name) {
+                return Err(("extends", format!("template `{name}` extends itself")));
+            }
+            let template = templates
+                .get(&name)
+                .ok_or_else(|| ("extends", format!("no template named `{name}`")))?;
+            extending.push(name);
+            resolved.merge(template.clone().resolve_with(templates, extending)?);
+            extending.pop();
+        }
+        resolved.merge(self);
+        Ok(resolved)
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.release_denylist.extend(other.release_denylist);
+        self.version_limits.extend(other.version_limits);
+        if other.pin.is_some() {
+            self.pin = other.pin;
+        }
+        if !other.target_pythons.is_empty() {
+            self.target_pythons = other.target_pythons;
+        }
+        if other.max_glibc.is_some() {
+            self.max_glibc = other.max_glibc;
+        }
+        if other.musllinux.is_some() {
+            self.musllinux = other.musllinux;
+        }
+        if !other.environments.is_empty() {
+            self.environments = other.environments;
+        }
+        self.only_binary |= other.only_binary;
+        self.no_binary |= other.no_binary;
+        self.prefer_binary |= other.prefer_binary;
+        if other.formats.is_some() {
+            self.formats = other.formats;
+        }
+        if other.yanked.is_some() {
+            self.yanked = other.yanked;
+        }
+        if other.require_sha256.is_some() {
+            self.require_sha256 = other.require_sha256;
+        }
+        self.require_gpg_sig |= other.require_gpg_sig;
+        if other.min_release_age.is_some() {
+            self.min_release_age = other.min_release_age;
+        }
+        if other.uploaded_before.is_some() {
+            self.uploaded_before = other.uploaded_before;
+        }
+        if other.vulnerability_severity.is_some() {
+            self.vulnerability_severity = other.vulnerability_severity;
+        }
+        self.ignore_vulnerabilities |= other.ignore_vulnerabilities;
+        if other.latest_versions.is_some() {
+            self.latest_versions = other.latest_versions;
+        }
+        self.license_denylist.extend(other.license_denylist);
+        if !other.license_allowlist.is_empty() {
+            self.license_allowlist = other.license_allowlist;
+        }
+        if other.reputation.is_some() {
+            self.reputation = other.reputation;
+        }
+        self.pass_through |= other.pass_through;
+        if other.blocked.is_some() {
+            self.blocked = other.blocked;
+        }
+        if other.mode.is_some() {
+            self.mode = other.mode;
+        }
+        if other.filtered.is_some() {
+            self.filtered = other.filtered;
+        }
+    }
+}
+
+/// Hides the releases left in `results` which are older than the newest `latest_versions` versions.
+fn check_latest_versions(
+    latest_versions: usize,
+    releases: &[Release],
+    results: &mut [Result<Vec<String>, (&'static str, String)>],
+) {
+    let mut versions = releases
+        .iter()
+        .zip(results.iter())
+        .filter(|(release, result)| result.is_ok() && release.yanked.is_none())
+        .filter_map(|(release, _)| release.version())
+        .filter(|version| !version.is_prerelease())
+        .collect::<Vec<Version>>();
+    versions.sort_by(|a, b| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    versions.dedup_by(|a, b| (*a).partial_cmp(b) == Some(Ordering::Equal));
+    // with no more versions than that, there's nothing to trim
+    let oldest = versions.get(latest_versions.saturating_sub(1));
+
+    for (release, result) in releases.iter().zip(results.iter_mut()) {
+        let rules = match result {
+            Ok(rules) => rules,
+            Err(_) => continue,
+        };
+        let version = if let Some(version) = release.version() {
+            version
+        } else {
+            *result = Err((
+                "latest_versions",
+                "can't tell which version it is, to check latest_versions".to_owned(),
+            ));
+            continue;
+        };
+        match oldest {
+            Some(oldest) if &version < oldest => {
+                *result = Err((
+                    "latest_versions",
+                    format!(
+                        "version {} isn't one of the newest {latest_versions}, \
+                         which latest_versions keeps",
+                        version.to_string()
+                    ),
+                ))
+            }
+            _ => rules.push(format!(
+                "version {} is one of the newest {latest_versions}, which latest_versions keeps",
+                version.to_string()
+            )),
+        }
+    }
+}
+
+/// An entry of a `release_denylist`, compiled when it's read.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum DenylistEntry {
+    Glob(Glob),
+    /// Matched anywhere in the filename, unless it's anchored.
+    Regex(Regex),
+    /// Matches every file of the versions it contains,
+    /// but no file whose version can't be told.
+    Version(SpecifierSet),
+}
+
+impl PartialEq for DenylistEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for DenylistEntry {}
+
+impl DenylistEntry {
+    pub fn matches(&self, filename: &str) -> bool {
+        match self {
+            DenylistEntry::Glob(glob) => glob.matches(filename),
+            DenylistEntry::Regex(regex) => regex.is_match(filename),
+            DenylistEntry::Version(versions) => pep_503::release_version(filename)
+                .is_some_and(|version| versions.contains(&version)),
+        }
+    }
+}
+
+impl ToString for DenylistEntry {
+    fn to_string(&self) -> String {
+        match self {
+            DenylistEntry::Glob(glob) => glob.to_string(),
+            DenylistEntry::Regex(regex) => format!("re:{}", regex.as_str()),
+            DenylistEntry::Version(versions) => format!("version:{}", versions.to_string()),
+        }
+    }
+}
+
+impl FromStr for DenylistEntry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(versions) = s.strip_prefix("version:") {
+            return SpecifierSet::parse_strict(versions).map(DenylistEntry::Version);
+        }
+        match s.strip_prefix("re:") {
+            Some(regex) => Regex::new(regex)
+                .map(DenylistEntry::Regex)
+                .map_err(|e| format!("invalid regex `{regex}`: {e}")),
+            None => Glob::from_str(s).map(DenylistEntry::Glob),
+        }
+    }
+}
+
+impl TryFrom<String> for DenylistEntry {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::from_str(&s)
+    }
+}
+
+impl From<DenylistEntry> for String {
+    fn from(entry: DenylistEntry) -> Self {
+        entry.to_string()
+    }
+}
+
+/// An entry of a `license_denylist` or `license_allowlist`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LicensePattern(Glob);
+
+impl LicensePattern {
+    pub fn matches(&self, license: &str) -> bool {
+        self.0.matches(license)
+    }
+}
+
+impl ToString for LicensePattern {
+    fn to_string(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+impl TryFrom<String> for LicensePattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Glob::case_insensitive(&s).map(LicensePattern)
+    }
+}
+
+impl From<LicensePattern> for String {
+    fn from(pattern: LicensePattern) -> Self {
+        pattern.to_string()
+    }
+}
+
+/// Describes a config which failed to validate, as precisely as we can find it in the file:
+/// `keys` is the path to the field at fault, e.g. `["packages", "numpy", "version_limits"]`.
+fn invalid(path: &Path, contents: &str, keys: &[&str], e: &str) -> String {
+    let location = match line_of(contents, keys) {
+        Some(line) => format!("{}:{line}", path.display()),
+        None => path.display().to_string(),
+    };
+    format!("invalid config in {location}: {}: {e}", keys.join("."))
+}
+
+/// The line number of the field at `keys`, found by looking for each key after the last.
+/// Good enough for configs written by hand, which is what it's for.
+fn line_of(contents: &str, keys: &[&str]) -> Option<usize> {
+    let mut offset = 0;
+    for key in keys {
+        offset += contents[offset..].find(key)?;
+    }
+    Some(contents[..offset].matches('\n').count() + 1)
+}
+
+/// Where package configs are loaded from.
+///
+/// Stores which read their configs up front are snapshots:
+/// picking up changes means opening a new one, e.g. with [`open`].
+#[async_trait]
+pub trait ConfigStore: Send + Sync {
+    /// The config for `package`, or `None` if it doesn't have one,
+    /// in which case it's served unfiltered.
+    async fn get(&self, package: &str) -> Result<Option<PackageConfig>, String>;
+
+    /// Every package which has a config, by its normalized name.
+    async fn packages(&self) -> Result<HashMap<String, PackageConfig>, String>;
+
+    /// Like [`ConfigStore::packages`], but with every config as it's applied,
+    /// along with packages which are filtered without having one, e.g. by a lockfile.
+    async fn effective(&self) -> Result<HashMap<String, PackageConfig>, String> {
+        self.packages().await
+    }
+
+    /// Whether `package` has a config of its own, i.e. is among [`ConfigStore::packages`].
+    async fn has(&self, package: &str) -> Result<bool, String> {
+        Ok(self
+            .packages()
+            .await?
+            .contains_key(&pep_503::normalize(package)))
+    }
+}
+
+/// A directory with a `{package}.json` (or `.yaml`, or `.yml`) config per package.
+/// Package names can be spelled any way that normalizes to the same name, e.g. `Foo_Bar.json`.
+/// Every config is read, and checked, when it's opened.
+pub struct DirectoryStore {
+    packages: HashMap<String, PackageConfig>,
+}
+
+impl DirectoryStore {
+    /// Opens the configs in `dir`, resolved against `templates`.
+    /// A directory which doesn't exist has no configs in it.
+    ///
+    /// Every config is checked, even once one has failed to, so that the error lists every problem,
+    /// one per line.
+    pub fn open(dir: &Path, templates: &HashMap<String, PackageConfig>) -> Result<Self, String> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Ok(Self {
+                    packages: HashMap::new(),
+                })
+            }
+            Err(e) => return Err(format!("failed to read {}: {e}", dir.display())),
+        };
+        let mut paths = entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, _>>()
+            .map_err(|e| format!("failed to read {}: {e}", dir.display()))?;
+        paths.sort();
+
+        let mut packages = HashMap::new();
+        let mut errors = vec![];
+        for path in paths {
+            if !matches!(
+                Syntax::from_path(&path),
+                Some(Syntax::Json) | Some(Syntax::Yaml)
+            ) {
+                continue;
+            }
+            let package = if let Some(package) = path.file_stem().and_then(|stem| stem.to_str()) {
+                pep_503::normalize(package)
+            } else {
+                continue;
+            };
+
+            match Self::load(&path, templates) {
+                Ok(config) => {
+                    if packages.insert(package.clone(), config).is_some() {
+                        errors.push(format!(
+                            "{package} has more than one config in {}",
+                            dir.display()
+                        ));
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        Ok(Self { packages })
+    }
+
+    fn load(
+        path: &Path,
+        templates: &HashMap<String, PackageConfig>,
+    ) -> Result<PackageConfig, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let config = config::parse::<PackageConfig>(path, &contents, Syntax::Json)?;
+        config
+            .validate()
+            .and_then(|()| config.resolve(templates))
+            .map_err(|(key, e)| invalid(path, &contents, &[key], &e))
+    }
+}
+
+#[async_trait]
+impl ConfigStore for DirectoryStore {
+    async fn get(&self, package: &str) -> Result<Option<PackageConfig>, String> {
+        Ok(self.packages.get(&pep_503::normalize(package)).cloned())
+    }
+
+    async fn packages(&self) -> Result<HashMap<String, PackageConfig>, String> {
+        Ok(self.packages.clone())
+    }
+
+    async fn has(&self, package: &str) -> Result<bool, String> {
+        Ok(self.packages.contains_key(&pep_503::normalize(package)))
+    }
+}
+
+/// One file with the configs of every package, like:
+///
+/// ```json
+/// {
+///   "packages": {
+///     "protobuf": {"release_denylist": [], "version_limits": ">=3,<4"}
+///   }
+/// }
+/// ```
+///
+/// Or the same in YAML, in a `.yaml` or `.yml` file. Read once, when it's opened.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileStore {
+    packages: HashMap<String, PackageConfig>,
+}
+
+impl FileStore {
+    /// Opens the configs in the file at `path`, resolved against `templates`.
+    /// Like [`DirectoryStore::open`], errors list every invalid config.
+    pub fn open(path: &Path, templates: &HashMap<String, PackageConfig>) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let store = config::parse::<Self>(path, &contents, Syntax::Json)?;
+        let mut configs = store.packages.into_iter().collect::<Vec<_>>();
+        configs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut packages = HashMap::new();
+        let mut errors = vec![];
+        for (package, config) in configs {
+            let resolved = config
+                .validate()
+                .and_then(|()| config.resolve(templates))
+                .map_err(|(key, e)| invalid(path, &contents, &["packages", &package, key], &e));
+            match resolved {
+                Ok(config) => {
+                    if packages
+                        .insert(pep_503::normalize(&package), config)
+                        .is_some()
+                    {
+                        errors.push(format!(
+                            "{package} has more than one config in {}",
+                            path.display()
+                        ));
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        Ok(Self { packages })
+    }
+}
+
+#[async_trait]
+impl ConfigStore for FileStore {
+    async fn get(&self, package: &str) -> Result<Option<PackageConfig>, String> {
+        Ok(self.packages.get(&pep_503::normalize(package)).cloned())
+    }
+
+    async fn packages(&self) -> Result<HashMap<String, PackageConfig>, String> {
+        Ok(self.packages.clone())
+    }
+
+    async fn has(&self, package: &str) -> Result<bool, String> {
+        Ok(self.packages.contains_key(&pep_503::normalize(package)))
+    }
+}
+
+/// Falls back to a default config for packages which don't have their own.
+struct WithDefault {
+    store: Box<dyn ConfigStore>,
+    default: PackageConfig,
+}
+
+#[async_trait]
+impl ConfigStore for WithDefault {
+    async fn get(&self, package: &str) -> Result<Option<PackageConfig>, String> {
+        Ok(Some(
+            self.store
+                .get(package)
+                .await?
+                .unwrap_or_else(|| self.default.clone()),
+        ))
+    }
+
+    async fn packages(&self) -> Result<HashMap<String, PackageConfig>, String> {
+        self.store.packages().await
+    }
+
+    async fn has(&self, package: &str) -> Result<bool, String> {
+        self.store.has(package).await
+    }
+}
+
+/// Applies rules from elsewhere, e.g. a lockfile, on top of packages' own configs.
+struct WithOverlay {
+    store: Box<dyn ConfigStore>,
+    /// By normalized package name.
+    overlay: HashMap<String, PackageConfig>,
+}
+
+#[async_trait]
+impl ConfigStore for WithOverlay {
+    async fn get(&self, package: &str) -> Result<Option<PackageConfig>, String> {
+        let overlay = match self.overlay.get(&pep_503::normalize(package)) {
+            Some(overlay) => overlay,
+            None => return self.store.get(package).await,
+        };
+        let mut config = self.store.get(package).await?.unwrap_or_default();
+        config.merge(overlay.clone());
+        Ok(Some(config))
+    }
+
+    async fn packages(&self) -> Result<HashMap<String, PackageConfig>, String> {
+        self.store.packages().await
+    }
+
+    async fn effective(&self) -> Result<HashMap<String, PackageConfig>, String> {
+        let mut packages = self.store.effective().await?;
+        for package in self.overlay.keys() {
+            if let Some(config) = self.get(package).await? {
+                packages.insert(package.clone(), config);
+            }
+        }
+        Ok(packages)
+    }
+
+    async fn has(&self, package: &str) -> Result<bool, String> {
+        self.store.has(package).await
+    }
+}
+
+/// The rules `config` applies on top of packages' own configs:
+/// its lockfile's pins, and denying what its audit reports found,
+/// short of its vulnerability exceptions.
+fn overlay(config: &UpstreamConfig) -> Result<HashMap<String, PackageConfig>, Vec<String>> {
+    let mut overlay = HashMap::<String, PackageConfig>::new();
+    let mut errors = vec![];
+    for (i, exception) in config.vulnerability_exceptions.iter().enumerate() {
+        if let Err(e) = Version::from_str(&exception.version) {
+            errors.push(format!(
+                "invalid config: upstream.vulnerability_exceptions.{i}.version: {e}"
+            ));
+        }
+    }
+    match config.lockfile.as_deref().map(Lockfile::open) {
+        Some(Ok(lockfile)) => {
+            for (package, pins) in lockfile.pins() {
+                overlay
+                    .entry(package.clone())
+                    .or_default()
+                    .version_limits
+                    .extend(pins.clone());
+            }
+        }
+        Some(Err(e)) => errors.push(e),
+        None => {}
+    }
+    match config.audit_report_dir.as_deref().map(audit_reports::load) {
+        Some(Ok(findings)) => {
+            for mut finding in findings {
+                for exception in config.vulnerability_exceptions.iter() {
+                    let version = match Version::from_str(&exception.version) {
+                        Ok(version) => version,
+                        Err(_) => continue,
+                    };
+                    if !exception.is_for(&finding.package)
+                        || exception.advisory != finding.id
+                        || !finding.versions.contains(&version)
+                    {
+                        continue;
+                    }
+                    if exception.is_expired() {
+                        warn!(
+                            "{} {}: the exception for {} has expired, so it's denied again",
+                            finding.package, exception.version, exception.advisory
+                        );
+                        continue;
+                    }
+                    // it parsed as a version, so it parses as a specifier
+                    if let Ok(excepted) =
+                        SpecifierSet::parse_strict(&format!("!={}", exception.version))
+                    {
+                        finding.versions.extend(excepted);
+                    }
+                }
+                let denylist = &mut overlay.entry(finding.package).or_default().release_denylist;
+                let entry = DenylistEntry::Version(finding.versions);
+                if !denylist.contains(&entry) {
+                    denylist.push(entry);
+                }
+            }
+        }
+        Some(Err(e)) => errors.push(e),
+        None => {}
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(overlay)
+}
+
+/// Opens whichever store `config` points at: a single file if it has one, or a directory,
+/// behind `config`'s default package config, lockfile and audit reports, if it has them.
+/// Errors list every problem with any of them, one per line.
+pub fn open(config: &UpstreamConfig) -> Result<Box<dyn ConfigStore>, String> {
+    let mut errors = vec![];
+
+    let templates = &config.package_config_templates;
+    let mut names = templates.keys().collect::<Vec<&String>>();
+    names.sort();
+    for name in names {
+        if let Err((key, e)) = templates[name].validate() {
+            errors.push(format!(
+                "invalid config: upstream.package_config_templates.{name}.{key}: {e}"
+            ));
+        }
+    }
+
+    let store: Result<Box<dyn ConfigStore>, String> = match &config.package_config_file {
+        Some(path) => FileStore::open(path, templates).map(|store| Box::new(store) as _),
+        None => DirectoryStore::open(&config.package_config_dir, templates)
+            .map(|store| Box::new(store) as _),
+    };
+    let default = config.default_package_config.as_ref().map(|default| {
+        default
+            .validate()
+            .and_then(|()| default.clone().resolve(templates))
+            .and_then(|default| match default.blocked {
+                // blocking everything which isn't configured is better done with routes
+                Some(_) => Err(("blocked", "only packages can be blocked".to_owned())),
+                None => Ok(default),
+            })
+            .map_err(|(key, e)| {
+                format!("invalid config: upstream.default_package_config.{key}: {e}")
+            })
+    });
+
+    let (store, default, overlay) = match (store, default.transpose(), overlay(config)) {
+        (Ok(store), Ok(default), Ok(overlay)) if errors.is_empty() => (store, default, overlay),
+        (store, default, overlay) => {
+            errors.extend(store.err());
+            errors.extend(default.err());
+            errors.extend(overlay.err().into_iter().flatten());
+            return Err(errors.join("\n"));
+        }
+    };
+    let store = match default {
+        Some(default) => Box::new(WithDefault { store, default }),
+        None => store,
+    };
+    Ok(if overlay.is_empty() {
+        store
+    } else {
+        Box::new(WithOverlay { store, overlay })
+    })
+}
+
+/// Every package's config, shaped like a `package_config_file`,
+/// e.g. for moving them all from one proxy to another.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Packages {
+    pub packages: BTreeMap<String, PackageConfig>,
+}
+
+/// Renders the version limits and pins of `packages` as a pip constraints file,
+/// so that `pip install -c` resolves to what would be served.
+/// Configs in audit mode are left out, since they don't limit anything.
+pub fn render_constraints(packages: &HashMap<String, PackageConfig>) -> String {
+    let mut names = packages.keys().collect::<Vec<&String>>();
+    names.sort();
+
+    let mut rendered = String::new();
+    for name in names {
+        let config = &packages[name];
+        if config.is_audit() {
+            continue;
+        }
+        if let Some(reason) = &config.blocked {
+            // constraints can't keep a package out, so this is only for whoever reads it
+            rendered.push_str(&format!("# {name} is blocked: {reason}\n"));
+            continue;
+        }
+        let mut specifiers = vec![];
+        if let Some(pin) = &config.pin {
+            specifiers.push(format!("=={pin}"));
+        }
+        if !config.version_limits.is_empty() {
+            specifiers.push(config.version_limits.to_string());
+        }
+        if !specifiers.is_empty() {
+            rendered.push_str(&format!("{name}{}\n", specifiers.join(",")));
+        }
+    }
+    rendered
+}
+
+/// `package`'s config as it's stored, if it has one: before its templates are resolved
+/// and anything else (the `default_package_config`, the lockfile, audit reports) is applied,
+/// e.g. to change a setting of it and [`save`] it back.
+pub fn stored(upstream: &UpstreamConfig, package: &str) -> Result<Option<PackageConfig>, String> {
+    let package = pep_503::normalize(package);
+    if let Some(path) = &upstream.package_config_file {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+        };
+        let packages = config::parse::<Packages>(path, &contents, Syntax::Json)?;
+        return Ok(packages
+            .packages
+            .into_iter()
+            .find(|(name, _)| pep_503::normalize(name) == package)
+            .map(|(_, config)| config));
+    }
+
+    let dir = &upstream.package_config_dir;
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("failed to read {}: {e}", dir.display())),
+    };
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("failed to read {}: {e}", dir.display()))?
+            .path();
+        let is_config = matches!(
+            Syntax::from_path(&path),
+            Some(Syntax::Json) | Some(Syntax::Yaml)
+        );
+        let stem = path.file_stem().and_then(|stem| stem.to_str());
+        if is_config && stem.is_some_and(|stem| pep_503::normalize(stem) == package) {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            return config::parse(&path, &contents, Syntax::Json).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Saves `config` as `package`'s config, replacing the one it had, if any,
+/// in whichever file or directory `upstream` reads package configs from.
+/// Returns whether it had one. Open the store again to pick it up.
+pub fn save(
+    upstream: &UpstreamConfig,
+    package: &str,
+    config: &PackageConfig,
+) -> Result<bool, String> {
+    edit(upstream, package, Some(config))
+}
+
+/// Removes `package`'s config, like [`save`]. Returns whether it had one.
+pub fn remove(upstream: &UpstreamConfig, package: &str) -> Result<bool, String> {
+    edit(upstream, package, None)
+}
+
+/// Replaces every package config with `packages`, like [`save`],
+/// removing the configs of any package which isn't in it.
+pub fn save_all(upstream: &UpstreamConfig, packages: Packages) -> Result<(), String> {
+    let mut normalized = BTreeMap::new();
+    for (package, config) in packages.packages {
+        // it ends up in a filesystem path
+        if !pep_503::is_valid_project_name(&package) {
+            return Err(format!("{package} isn't a valid package name"));
+        }
+        if normalized
+            .insert(pep_503::normalize(&package), config)
+            .is_some()
+        {
+            return Err(format!("{package} has more than one config"));
+        }
+    }
+
+    match &upstream.package_config_file {
+        Some(path) => write_file(
+            path,
+            Packages {
+                packages: normalized,
+            },
+        ),
+        None => {
+            let dir = &upstream.package_config_dir;
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+            for (package, config) in normalized.iter() {
+                write(
+                    &dir.join(format!("{package}.json")),
+                    &config::render(config, Syntax::Json)?,
+                )?;
+            }
+            remove_stale(dir, |package, path| {
+                normalized.contains_key(package) && path == dir.join(format!("{package}.json"))
+            })?;
+            Ok(())
+        }
+    }
+}
+
+fn edit(
+    upstream: &UpstreamConfig,
+    package: &str,
+    config: Option<&PackageConfig>,
+) -> Result<bool, String> {
+    let package = pep_503::normalize(package);
+    match &upstream.package_config_file {
+        Some(path) => edit_file(path, &package, config),
+        None => edit_directory(&upstream.package_config_dir, &package, config),
+    }
+}
+
+fn edit_file(path: &Path, package: &str, config: Option<&PackageConfig>) -> Result<bool, String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => "{}".to_owned(),
+        Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+    };
+    let mut packages = config::parse::<Packages>(path, &contents, Syntax::Json)?;
+    let before = packages.packages.len();
+    packages
+        .packages
+        .retain(|name, _| pep_503::normalize(name) != package);
+    let existed = packages.packages.len() != before;
+    if let Some(config) = config {
+        packages.packages.insert(package.to_owned(), config.clone());
+    }
+    write_file(path, packages)?;
+    Ok(existed)
+}
+
+/// Rewrites the whole file, in the syntax it's in; comments and formatting aren't kept.
+fn write_file(path: &Path, packages: Packages) -> Result<(), String> {
+    let syntax = Syntax::from_path(path).unwrap_or(Syntax::Json);
+    write(path, &config::render(&packages, syntax)?)
+}
+
+/// Writes `{package}.json`, removing any other file the package's config was in.
+fn edit_directory(
+    dir: &Path,
+    package: &str,
+    config: Option<&PackageConfig>,
+) -> Result<bool, String> {
+    let path = dir.join(format!("{package}.json"));
+    let existed = path.exists();
+    if let Some(config) = config {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+        write(&path, &config::render(config, Syntax::Json)?)?;
+    }
+
+    let removed = remove_stale(dir, |other_package, other| {
+        other_package != package || (config.is_some() && other == path)
+    })?;
+    Ok(existed || removed)
+}
+
+/// Removes the config files in `dir` which `keep` (given the package and the file) says not to.
+/// Returns whether there were any.
+fn remove_stale(dir: &Path, keep: impl Fn(&str, &Path) -> bool) -> Result<bool, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(format!("failed to read {}: {e}", dir.display())),
+    };
+    let mut removed = false;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("failed to read {}: {e}", dir.display()))?
+            .path();
+        if !matches!(
+            Syntax::from_path(&path),
+            Some(Syntax::Json) | Some(Syntax::Yaml)
+        ) {
+            continue;
+        }
+        let package = if let Some(package) = path.file_stem().and_then(|stem| stem.to_str()) {
+            pep_503::normalize(package)
+        } else {
+            continue;
+        };
+        if keep(&package, &path) {
+            continue;
+        }
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("failed to remove {}: {e}", path.display()))?;
+        removed = true;
+    }
+    Ok(removed)
+}
+
+/// Replaces the file at `path` all at once, so that it's never read half written.
+pub fn write(path: &Path, contents: &str) -> Result<(), String> {
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, contents)
+        .and_then(|()| std::fs::rename(&partial, path))
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+/// Summarizes how the packages with configs differ between two stores,
+/// e.g. `added numpy; changed protobuf, six`, or `None` if they don't.
+pub fn describe_changes(
+    old: &HashMap<String, PackageConfig>,
+    new: &HashMap<String, PackageConfig>,
+) -> Option<String> {
+    let mut added = BTreeSet::new();
+    let mut changed = BTreeSet::new();
+    for (package, config) in new.iter() {
+        match old.get(package) {
+            None => {
+                added.insert(package.as_str());
+            }
+            Some(old_config) if old_config != config => {
+                changed.insert(package.as_str());
+            }
+            Some(_) => {}
+        }
+    }
+    let removed = old
+        .keys()
+        .filter(|package| !new.contains_key(*package))
+        .map(String::as_str)
+        .collect::<BTreeSet<&str>>();
+
+    let changes = [("added", added), ("changed", changed), ("removed", removed)]
+        .into_iter()
+        .filter(|(_, packages)| !packages.is_empty())
+        .map(|(verb, packages)| {
+            format!(
+                "{verb} {}",
+                packages.into_iter().collect::<Vec<_>>().join(", ")
+            )
+        })
+        .collect::<Vec<String>>();
+    if changes.is_empty() {
+        None
+    } else {
+        Some(changes.join("; "))
+    }
+}
+
+/// How long files have to stop changing before a change is reported,
+/// so that e.g. a sync which rewrites a whole directory is picked up once, and in full.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches the files which `config`'s package configs are read from.
+pub struct Watcher {
+    // stops watching when it's dropped
+    _watcher: RecommendedWatcher,
+    changes: mpsc::UnboundedReceiver<PathBuf>,
+}
+
+impl Watcher {
+    pub fn new(config: &UpstreamConfig) -> Result<Self, String> {
+        // watches the directory a config file is in, rather than the file itself,
+        // so that files which are replaced rather than written to are still followed
+        let (dir, file) = match &config.package_config_file {
+            Some(file) => (
+                file.parent().unwrap_or_else(|| Path::new(".")).to_owned(),
+                Some(file.clone()),
+            ),
+            None => (config.package_config_dir.clone(), None),
+        };
+
+        let (tx, changes) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = if let Ok(event) = event {
+                    event
+                } else {
+                    return;
+                };
+                if matches!(event.kind, EventKind::Access(_)) {
+                    return;
+                }
+                for path in event.paths {
+                    let is_config = match &file {
+                        Some(file) => path.file_name() == file.file_name(),
+                        None => matches!(
+                            Syntax::from_path(&path),
+                            Some(Syntax::Json) | Some(Syntax::Yaml)
+                        ),
+                    };
+                    if is_config {
+                        let _ = tx.send(path);
+                    }
+                }
+            })
+            .map_err(|e| format!("failed to watch {}: {e}", dir.display()))?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("failed to watch {}: {e}", dir.display()))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Waits for package configs to change, and then to settle,
+    /// returning the names of the files which changed.
+    pub async fn changed(&mut self) -> BTreeSet<String> {
+        let mut changed = BTreeSet::new();
+        let mut add = |path: PathBuf| {
+            if let Some(name) = path.file_name() {
+                changed.insert(name.to_string_lossy().into_owned());
+            }
+        };
+        match self.changes.recv().await {
+            Some(path) => add(path),
+            // only happens if the watcher stops, which it doesn't while we own it
+            None => std::future::pending().await,
+        }
+        while let Ok(Some(path)) = tokio::time::timeout(DEBOUNCE, self.changes.recv()).await {
+            add(path);
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::osv::{Vulnerability, VulnerabilityException};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_directory_store() {
+        let store = DirectoryStore::open(Path::new("fixtures"), &HashMap::new()).unwrap();
+        let config = store.get("protobuf").await.unwrap().unwrap();
+        assert_eq!(config.version_limits.to_string(), ">=3,!=3.16.*,<4");
+        assert_eq!(store.get("no-such-package").await, Ok(None));
+
+        let dir = std::env::temp_dir().join(format!("pyproxide-configs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("numpy.yaml"),
+            "release_denylist: []\nversion_limits: <2\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("protobuf.yml"),
+            "release_denylist: []\nversion_limits: <four\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("six.json"), r#"{"pin": "latest"}"#).unwrap();
+        // one bad config spoils the lot, so that nothing is half loaded,
+        // but every bad one is reported
+        let e = DirectoryStore::open(&dir, &HashMap::new()).err().unwrap();
+        let errors = e.lines().collect::<Vec<&str>>();
+        assert_eq!(errors.len(), 2, "{e}");
+        assert!(errors[0].contains("protobuf.yml"), "{e}");
+        assert!(
+            errors[0].contains("could not match version str: `four` at line 2"),
+            "{e}"
+        );
+        assert!(errors[1].contains("six.json:1: pin"), "{e}");
+        std::fs::remove_file(dir.join("six.json")).unwrap();
+
+        std::fs::remove_file(dir.join("protobuf.yml")).unwrap();
+        std::fs::write(dir.join("Foo_Bar.json"), "{}").unwrap();
+        let store = DirectoryStore::open(&dir, &HashMap::new()).unwrap();
+        assert!(store.get("numpy").await.unwrap().is_some());
+        // names are normalized on both sides
+        assert!(store.get("foo-bar").await.unwrap().is_some());
+        assert!(store.get("FOO.bar").await.unwrap().is_some());
+
+        std::fs::write(dir.join("foo-bar.yaml"), "{}").unwrap();
+        let e = DirectoryStore::open(&dir, &HashMap::new()).err().unwrap();
+        assert!(e.contains("foo-bar has more than one config"), "{e}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let path =
+            std::env::temp_dir().join(format!("pyproxide-packages-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"packages": {"protobuf": {"release_denylist": [], "version_limits": "<4"}}}"#,
+        )
+        .unwrap();
+        let store = FileStore::open(&path, &HashMap::new()).unwrap();
+        let config = store.get("protobuf").await.unwrap().unwrap();
+        assert_eq!(config.version_limits.to_string(), "<4");
+        assert_eq!(store.get("numpy").await, Ok(None));
+
+        std::fs::write(&path, r#"{"protobuf": {}}"#).unwrap();
+        let e = FileStore::open(&path, &HashMap::new()).unwrap_err();
+        assert!(e.contains("unknown field `protobuf`"), "{e}");
+
+        std::fs::write(
+            &path,
+            r#"{
+  "packages": {
+    "numpy": {"release_denylist": [], "version_limits": "<2"},
+    "protobuf": {
+      "release_denylist": [],
+      "pin": "latest"
+    }
+  }
+}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            FileStore::open(&path, &HashMap::new()).unwrap_err(),
+            format!(
+                "invalid config in {}:6: packages.protobuf.pin: \
+                 could not match version str: `latest`",
+                path.display()
+            ),
+        );
+
+        std::fs::remove_file(path).unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("pyproxide-packages-{}.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            "packages:\n  protobuf:\n    release_denylist: []\n    version_limits: <4\n",
+        )
+        .unwrap();
+        let store = FileStore::open(&path, &HashMap::new()).unwrap();
+        let config = store.get("protobuf").await.unwrap().unwrap();
+        assert_eq!(config.version_limits.to_string(), "<4");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_save() {
+        let config = PackageConfig {
+            version_limits: SpecifierSet::from_str("<2").unwrap(),
+            ..PackageConfig::default()
+        };
+
+        let dir = std::env::temp_dir().join(format!("pyproxide-saved-{}", std::process::id()));
+        let upstream = UpstreamConfig {
+            package_config_dir: dir.clone(),
+            ..UpstreamConfig::default()
+        };
+        assert_eq!(save(&upstream, "Foo_Bar", &config), Ok(false));
+        std::fs::write(dir.join("Foo_Bar.yaml"), "pin: '1'\n").unwrap();
+        assert_eq!(save(&upstream, "foo.bar", &config), Ok(true));
+        assert!(!dir.join("Foo_Bar.yaml").exists());
+        let store = open(&upstream).unwrap();
+        assert_eq!(store.get("foo-bar").await, Ok(Some(config.clone())));
+        assert_eq!(stored(&upstream, "FOO_BAR"), Ok(Some(config.clone())));
+        assert_eq!(stored(&upstream, "six"), Ok(None));
+        assert_eq!(remove(&upstream, "foo-bar"), Ok(true));
+        assert_eq!(remove(&upstream, "foo-bar"), Ok(false));
+        assert_eq!(
+            open(&upstream).unwrap().packages().await,
+            Ok(HashMap::new())
+        );
+
+        std::fs::write(dir.join("six.yaml"), "pass_through: true\n").unwrap();
+        std::fs::write(dir.join("Foo_Bar.yaml"), "pin: '1'\n").unwrap();
+        let packages = Packages {
+            packages: BTreeMap::from([("Foo_Bar".to_owned(), config.clone())]),
+        };
+        save_all(&upstream, packages).unwrap();
+        assert_eq!(
+            open(&upstream).unwrap().packages().await,
+            Ok(HashMap::from([("foo-bar".to_owned(), config.clone())]))
+        );
+        let packages = Packages {
+            packages: BTreeMap::from([
+                ("Foo_Bar".to_owned(), config.clone()),
+                ("foo-bar".to_owned(), config.clone()),
+            ]),
+        };
+        assert_eq!(
+            save_all(&upstream, packages),
+            Err("foo-bar has more than one config".to_owned())
+        );
+        let packages = Packages {
+            packages: BTreeMap::from([("../../etc/cron.d/x".to_owned(), config.clone())]),
+        };
+        assert_eq!(
+            save_all(&upstream, packages),
+            Err("../../etc/cron.d/x isn't a valid package name".to_owned())
+        );
+        // as it's stored, with its templates left unresolved
+        std::fs::write(dir.join("NumPy.yaml"), "extends: [strict]\n").unwrap();
+        assert_eq!(
+            stored(&upstream, "numpy"),
+            Ok(Some(PackageConfig {
+                extends: vec!["strict".to_owned()],
+                ..PackageConfig::default()
+            }))
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("pyproxide-saved-{}.toml", std::process::id()));
+        std::fs::write(&path, "[packages.six]\npass_through = true\n").unwrap();
+        let upstream = UpstreamConfig {
+            package_config_file: Some(path.clone()),
+            ..UpstreamConfig::default()
+        };
+        assert_eq!(save(&upstream, "Foo_Bar", &config), Ok(false));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[packages.foo-bar]\nversion_limits = \"<2\"\n\n[packages.six]\npass_through = true\n"
+        );
+        assert_eq!(stored(&upstream, "foo.bar"), Ok(Some(config.clone())));
+        assert_eq!(remove(&upstream, "six"), Ok(true));
+        let store = open(&upstream).unwrap();
+        assert_eq!(store.get("six").await, Ok(None));
+        assert_eq!(store.get("foo-bar").await, Ok(Some(config)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_default_package_config() {
+        let default = PackageConfig {
+            version_limits: SpecifierSet::parse_strict("<2").unwrap(),
+            ..PackageConfig::default()
+        };
+        let store = open(&UpstreamConfig {
+            default_package_config: Some(default.clone()),
+            ..UpstreamConfig::default()
+        })
+        .unwrap();
+        // packages with their own config don't get the default's
+        let config = store.get("protobuf").await.unwrap().unwrap();
+        assert_eq!(config.version_limits.to_string(), ">=3,!=3.16.*,<4");
+        assert_eq!(store.get("numpy").await, Ok(Some(default)));
+        assert_eq!(store.has("protobuf").await, Ok(true));
+        assert_eq!(store.has("numpy").await, Ok(false));
+
+        let e = open(&UpstreamConfig {
+            default_package_config: Some(PackageConfig {
+                pin: Some("two".to_owned()),
+                ..PackageConfig::default()
+            }),
+            ..UpstreamConfig::default()
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            e,
+            "invalid config: upstream.default_package_config.pin: \
+             could not match version str: `two`",
+        );
+
+        let e = open(&UpstreamConfig {
+            default_package_config: Some(PackageConfig {
+                blocked: Some("malware".to_owned()),
+                ..PackageConfig::default()
+            }),
+            ..UpstreamConfig::default()
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            e,
+            "invalid config: upstream.default_package_config.blocked: only packages can be blocked",
+        );
+    }
+
+    #[test]
+    fn test_render_constraints() {
+        let packages = HashMap::from([
+            (
+                "protobuf".to_owned(),
+                PackageConfig {
+                    version_limits: SpecifierSet::parse_strict(">=3,<4").unwrap(),
+                    ..PackageConfig::default()
+                },
+            ),
+            (
+                "numpy".to_owned(),
+                PackageConfig {
+                    pin: Some("1.26.4".to_owned()),
+                    version_limits: SpecifierSet::parse_strict("<2").unwrap(),
+                    ..PackageConfig::default()
+                },
+            ),
+            (
+                "evil".to_owned(),
+                PackageConfig {
+                    blocked: Some("malware".to_owned()),
+                    ..PackageConfig::default()
+                },
+            ),
+            (
+                "trial".to_owned(),
+                PackageConfig {
+                    version_limits: SpecifierSet::parse_strict("<2").unwrap(),
+                    mode: Some(Mode::Audit),
+                    ..PackageConfig::default()
+                },
+            ),
+            (
+                "six".to_owned(),
+                PackageConfig {
+                    only_binary: true,
+                    ..PackageConfig::default()
+                },
+            ),
+        ]);
+        assert_eq!(
+            render_constraints(&packages),
+            "# evil is blocked: malware\nnumpy==1.26.4,<2\nprotobuf>=3,<4\n",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lockfile() {
+        let lockfile =
+            std::env::temp_dir().join(format!("pyproxide-lockfile-{}.txt", std::process::id()));
+        std::fs::write(&lockfile, "protobuf==3.20.3\nsix==1.16.0\n").unwrap();
+        let store = open(&UpstreamConfig {
+            lockfile: Some(lockfile.clone()),
+            ..UpstreamConfig::default()
+        })
+        .unwrap();
+        std::fs::remove_file(&lockfile).unwrap();
+
+        // on top of the package's own config
+        let config = store.get("protobuf").await.unwrap().unwrap();
+        assert_eq!(
+            config.version_limits.to_string(),
+            ">=3,!=3.16.*,<4,==3.20.3"
+        );
+        let config = store.get("six").await.unwrap().unwrap();
+        assert_eq!(config.version_limits.to_string(), "==1.16.0");
+        assert_eq!(store.get("numpy").await, Ok(None));
+        let effective = store.effective().await.unwrap();
+        assert_eq!(effective["six"].version_limits.to_string(), "==1.16.0");
+        assert!(!store.packages().await.unwrap().contains_key("six"));
+        assert_eq!(store.has("Protobuf").await, Ok(true));
+        assert_eq!(store.has("six").await, Ok(false));
+
+        let e = open(&UpstreamConfig {
+            lockfile: Some(lockfile.clone()),
+            ..UpstreamConfig::default()
+        })
+        .err()
+        .unwrap();
+        assert!(e.starts_with("failed to read"), "{e}");
+    }
+
+    #[tokio::test]
+    async fn test_audit_reports() {
+        let dir = std::env::temp_dir().join(format!("pyproxide-audit-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        audit_reports::save(
+            &dir,
+            "ci",
+            br#"{"dependencies": [
+                {"name": "protobuf", "version": "3.20.1", "vulns": [{"id": "GHSA-8gq9-2x98-w8hf"}, {"id": "PYSEC-2022-1"}]}
+            ]}"#,
+        )
+        .unwrap();
+        let store = open(&UpstreamConfig {
+            audit_report_dir: Some(dir.clone()),
+            ..UpstreamConfig::default()
+        })
+        .unwrap();
+
+        let config = store.get("protobuf").await.unwrap().unwrap();
+        assert_eq!(
+            config.release_denylist.last(),
+            Some(&DenylistEntry::from_str("version:==3.20.1").unwrap())
+        );
+        // on top of the package's own config
+        assert_eq!(config.version_limits.to_string(), ">=3,!=3.16.*,<4");
+        assert_eq!(
+            config.check_release(&Release {
+                name: "protobuf-3.20.1.tar.gz".to_owned(),
+                uri: String::new(),
+                has_gpg: false,
+                requires_python: None,
+                yanked: None,
+                upload_time: None,
+                vulnerabilities: vec![],
+                licenses: vec![],
+                filtered: None,
+            }),
+            Err((
+                "release_denylist",
+                "matches release_denylist entry `version:==3.20.1`".to_owned()
+            ))
+        );
+
+        let exception = |advisory: &str, expires: &str| VulnerabilityException {
+            package: "Protobuf".to_owned(),
+            version: "3.20.1".to_owned(),
+            advisory: advisory.to_owned(),
+            expires: humantime::parse_rfc3339(expires).unwrap(),
+        };
+        let store = open(&UpstreamConfig {
+            audit_report_dir: Some(dir.clone()),
+            vulnerability_exceptions: vec![
+                exception("GHSA-8gq9-2x98-w8hf", "2999-01-01T00:00:00Z"),
+                exception("PYSEC-2022-1", "2000-01-01T00:00:00Z"),
+            ],
+            ..UpstreamConfig::default()
+        })
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let config = store.get("protobuf").await.unwrap().unwrap();
+        assert_eq!(
+            config.release_denylist[config.release_denylist.len() - 2..],
+            [
+                DenylistEntry::from_str("version:==3.20.1,!=3.20.1").unwrap(),
+                DenylistEntry::from_str("version:==3.20.1").unwrap(),
+            ]
+        );
+
+        assert!(open(&UpstreamConfig {
+            vulnerability_exceptions: vec![VulnerabilityException {
+                version: "latest".to_owned(),
+                ..exception("PYSEC-2022-1", "2999-01-01T00:00:00Z")
+            }],
+            ..UpstreamConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_denylist_entry() {
+        let entry = |s: &str| DenylistEntry::from_str(s).unwrap();
+        assert!(entry("protobuf-4.21.0.tar.gz").matches("protobuf-4.21.0.tar.gz"));
+        let glob = entry("protobuf-3.*-py2.py3-none-any.whl");
+        assert!(glob.matches("protobuf-3.20.1-py2.py3-none-any.whl"));
+        assert!(!glob.matches("protobuf-3.20.1.tar.gz"));
+        let regex = entry(r"re:-cp3(6|7)-.*-win(32|_amd64)\.whl$");
+        assert!(regex.matches("protobuf-3.20.1-cp37-cp37m-win32.whl"));
+        assert!(!regex.matches("protobuf-3.20.1-cp38-cp38-win32.whl"));
+        assert_eq!(regex.to_string(), r"re:-cp3(6|7)-.*-win(32|_amd64)\.whl$");
+        let versions = entry("version:>=3.20,<3.21");
+        assert!(versions.matches("protobuf-3.20.1-py2.py3-none-any.whl"));
+        assert!(!versions.matches("protobuf-3.19.0.tar.gz"));
+        assert!(!versions.matches("protobuf-latest.tar.gz"));
+        assert_eq!(versions.to_string(), "version:>=3.20,<3.21");
+
+        assert_eq!(
+            DenylistEntry::from_str("protobuf-[3.tar.gz").err(),
+            Some("unclosed `[` in glob: `protobuf-[3.tar.gz`".to_owned()),
+        );
+        let e = config::parse::<PackageConfig>(
+            Path::new("protobuf.yaml"),
+            "release_denylist:\n  - re:protobuf-(3\n",
+            Syntax::Json,
+        )
+        .unwrap_err();
+        assert!(e.contains("invalid regex `protobuf-(3`"), "{e}");
+        assert!(e.contains("line 2"), "{e}");
+    }
+
+    /// A release of `name` with nothing else known about it.
+    fn release(name: &str) -> Release {
+        Release {
+            name: name.to_owned(),
+            uri: name.to_owned(),
+            has_gpg: false,
+            requires_python: None,
+            yanked: None,
+            upload_time: None,
+            vulnerabilities: vec![],
+            licenses: vec![],
+            filtered: None,
+        }
+    }
+
+    #[test]
+    fn test_check_release() {
+        let config = PackageConfig {
+            release_denylist: vec![DenylistEntry::from_str("numpy-1.22.*-win32.whl").unwrap()],
+            version_limits: SpecifierSet::parse_strict(">=1.22,<2").unwrap(),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release(
+                "numpy-1.26.4-cp311-cp311-manylinux_2_17_x86_64.whl"
+            )),
+            Ok(vec![
+                "version 1.26.4 is within version_limits `>=1.22,<2`".to_owned()
+            ]),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-1.22.0-cp310-cp310-win32.whl")),
+            Err((
+                "release_denylist",
+                "matches release_denylist entry `numpy-1.22.*-win32.whl`".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-2.0.0.tar.gz")),
+            Err((
+                "version_limits",
+                "version 2.0.0 is outside version_limits `>=1.22,<2`".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-latest.tar.gz")),
+            Err((
+                "version_limits",
+                "can't tell which version it is, to check version_limits".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-1.0-py2.7.egg")),
+            Err((
+                "formats",
+                "eggs aren't served unless they're in formats".to_owned()
+            )),
+        );
+
+        let config = PackageConfig {
+            pin: Some("1.26.4".to_owned()),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4.tar.gz")),
+            Ok(vec!["version 1.26.4 is pinned".to_owned()]),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.3.tar.gz")),
+            Err(("pin", "version 1.26.3 isn't the pinned 1.26.4".to_owned())),
+        );
+
+        let config = PackageConfig {
+            max_glibc: Some("2.17".parse().unwrap()),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release(
+                "numpy-1.26.4-cp311-cp311-manylinux_2_17_x86_64.manylinux2014_x86_64.whl"
+            )),
+            Ok(vec![
+                "it needs glibc 2.17, within max_glibc `2.17`".to_owned()
+            ]),
+        );
+        assert_eq!(
+            config.check_release(&release(
+                "numpy-2.1.0-cp312-cp312-manylinux_2_28_aarch64.whl"
+            )),
+            Err((
+                "max_glibc",
+                "it needs glibc 2.28, newer than max_glibc `2.17`".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-2.1.0-cp312-cp312-musllinux_1_2_x86_64.whl")),
+            Ok(vec![]),
+        );
+        assert_eq!(
+            config.check_release(&release(
+                "numpy-2.1.0-cp312-cp312-manylinux_2_28_x86_64.musllinux_1_1_x86_64.whl"
+            )),
+            Ok(vec![]),
+        );
+
+        let config = PackageConfig {
+            musllinux: Some(Musllinux::Hide),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-2.1.0-cp312-cp312-musllinux_1_2_x86_64.whl")),
+            Err((
+                "musllinux",
+                "it's a musllinux wheel (musl 1.2), and musllinux is `hide`".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release(
+                "numpy-2.1.0-cp312-cp312-manylinux_2_17_x86_64.musllinux_1_1_x86_64.whl"
+            )),
+            Ok(vec![]),
+        );
+        let config = PackageConfig {
+            musllinux: Some(Musllinux::Only),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-2.1.0-cp312-cp312-musllinux_1_2_x86_64.whl")),
+            Ok(vec![
+                "it's a musllinux wheel (musl 1.2), and musllinux is `only`".to_owned()
+            ]),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-2.1.0-cp312-cp312-win_amd64.whl")),
+            Err((
+                "musllinux",
+                "it's built for a platform other than musllinux, and musllinux is `only`"
+                    .to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release("six-1.16.0-py2.py3-none-any.whl")),
+            Ok(vec![]),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-2.1.0.tar.gz")),
+            Ok(vec![]),
+        );
+
+        let config: PackageConfig = serde_json::from_str(
+            r#"{"environments": [
+                {"name": "prod", "python": "3.11", "os": "linux", "arch": "x86_64", "glibc": "2.28"},
+                {"name": "laptops", "python": "3.12", "os": "macos", "arch": "arm64", "macos": "14.0"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.check_release(&release("numpy-2.1.0-cp312-cp312-macosx_14_0_arm64.whl")),
+            Ok(vec![
+                "it can be installed in environments entry `laptops`".to_owned()
+            ]),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-2.1.0-cp312-cp312-win_amd64.whl")),
+            Err((
+                "environments",
+                "it can't be installed in any of environments".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-2.1.0.tar.gz")),
+            Ok(vec![]),
+        );
+
+        let config = PackageConfig {
+            target_pythons: vec!["3.10".to_owned(), "3.12".to_owned()],
+            ..PackageConfig::default()
+        };
+        let requiring = |requires_python: &str| Release {
+            requires_python: Some(requires_python.to_owned()),
+            ..release("numpy-1.26.4.tar.gz")
+        };
+        assert_eq!(
+            config.check_release(&requiring(">=3.11")),
+            Ok(vec![
+                "Python 3.12 satisfies requires-python `>=3.11`".to_owned()
+            ]),
+        );
+        assert_eq!(
+            config.check_release(&requiring(">=2.7, !=3.0.*, <3.10")),
+            Err((
+                "target_pythons",
+                "requires-python `>=2.7,!=3.0.*,<3.10` excludes every one of target_pythons"
+                    .to_owned()
+            )),
+        );
+        assert_eq!(config.check_release(&requiring(">=3.x")), Ok(vec![]));
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4.tar.gz")),
+            Ok(vec![])
+        );
+
+        let config = PackageConfig {
+            only_binary: true,
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4.tar.gz")),
+            Err((
+                "only_binary",
+                "sdists aren't served, since only_binary is set".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4-cp312-cp312-win_amd64.whl")),
+            Ok(vec![])
+        );
+
+        let config = PackageConfig {
+            no_binary: true,
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4-cp312-cp312-win_amd64.whl")),
+            Err((
+                "no_binary",
+                "only sdists are served, since no_binary is set".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4.tar.gz")),
+            Ok(vec![])
+        );
+
+        let config = PackageConfig {
+            formats: Some(vec![FileFormat::Egg]),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-1.0-py2.7.egg")),
+            Ok(vec!["egg files are in formats".to_owned()]),
+        );
+        assert_eq!(
+            config.check_release(&release("numpy-1.0.win32.exe")),
+            Err(("formats", "other files aren't in formats".to_owned())),
+        );
+
+        let yanked = Release {
+            yanked: Some("broken".to_owned()),
+            ..release("numpy-1.26.4.tar.gz")
+        };
+        assert_eq!(PackageConfig::default().check_release(&yanked), Ok(vec![]));
+        let config = PackageConfig {
+            yanked: Some(Yanked::HideUnlessPinned),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&yanked),
+            Err((
+                "yanked",
+                "it's yanked (broken), and isn't pinned".to_owned()
+            )),
+        );
+        let config = PackageConfig {
+            pin: Some("1.26.4".to_owned()),
+            ..config
+        };
+        assert_eq!(
+            config.check_release(&yanked),
+            Ok(vec![
+                "version 1.26.4 is pinned".to_owned(),
+                "it's yanked (broken), but pinned".to_owned(),
+            ]),
+        );
+
+        let config = PackageConfig {
+            require_sha256: Some(RequireSha256::Drop),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4.tar.gz")),
+            Err((
+                "require_sha256",
+                "it has no sha256, which require_sha256 requires".to_owned()
+            )),
+        );
+        let hashed = Release {
+            uri: "numpy-1.26.4.tar.gz#sha256=abc123".to_owned(),
+            ..release("numpy-1.26.4.tar.gz")
+        };
+        assert_eq!(config.check_release(&hashed), Ok(vec![]));
+
+        let config = PackageConfig {
+            require_gpg_sig: true,
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4.tar.gz")),
+            Err((
+                "require_gpg_sig",
+                "it has no GPG signature, which require_gpg_sig requires".to_owned()
+            )),
+        );
+        let signed = Release {
+            has_gpg: true,
+            ..release("numpy-1.26.4.tar.gz")
+        };
+        assert_eq!(config.check_release(&signed), Ok(vec![]));
+
+        let config = PackageConfig {
+            min_release_age: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4.tar.gz")),
+            Err((
+                "min_release_age",
+                "can't tell when it was uploaded, to check min_release_age".to_owned()
+            )),
+        );
+        let uploaded = |ago: u64| Release {
+            upload_time: Some(SystemTime::now() - Duration::from_secs(ago)),
+            ..release("numpy-1.26.4.tar.gz")
+        };
+        assert_eq!(
+            config.check_release(&uploaded(60 * 60)),
+            Err((
+                "min_release_age",
+                "it was uploaded 1h ago, within min_release_age `7days`".to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&uploaded(8 * 24 * 60 * 60)),
+            Ok(vec![
+                "it was uploaded 8days ago, past min_release_age `7days`".to_owned()
+            ]),
+        );
+
+        let freeze = humantime::parse_rfc3339("2024-06-01T00:00:00Z").unwrap();
+        let config = PackageConfig {
+            uploaded_before: Some(freeze),
+            ..PackageConfig::default()
+        };
+        let uploaded = |at: &str| Release {
+            upload_time: Some(humantime::parse_rfc3339(at).unwrap()),
+            ..release("numpy-1.26.4.tar.gz")
+        };
+        assert_eq!(
+            config.check_release(&uploaded("2024-06-02T12:00:00Z")),
+            Err(("uploaded_before", 
+                "it was uploaded at 2024-06-02T12:00:00Z, after uploaded_before `2024-06-01T00:00:00Z`"
+                    .to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&uploaded("2024-02-05T00:00:00Z")),
+            Ok(vec![
+                "it was uploaded at 2024-02-05T00:00:00Z, by uploaded_before `2024-06-01T00:00:00Z`"
+                    .to_owned()
+            ]),
+        );
+        assert_eq!(config.upload_cutoff(), Some(freeze));
+        let config = PackageConfig {
+            min_release_age: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            ..config
+        };
+        assert_eq!(config.upload_cutoff(), Some(freeze));
+
+        let config = PackageConfig {
+            vulnerability_severity: Some(Severity::High),
+            ..PackageConfig::default()
+        };
+        let vulnerable = |severity: Option<Severity>| Release {
+            vulnerabilities: vec![Vulnerability {
+                id: "GHSA-aaaa-bbbb-cccc".to_owned(),
+                severity,
+            }],
+            ..release("numpy-1.26.4.tar.gz")
+        };
+        assert_eq!(
+            config.check_release(&release("numpy-1.26.4.tar.gz")),
+            Ok(vec![])
+        );
+        assert_eq!(
+            config.check_release(&vulnerable(Some(Severity::Moderate))),
+            Ok(vec![
+                "its known vulnerabilities are all below vulnerability_severity `high`".to_owned()
+            ]),
+        );
+        assert_eq!(
+            config.check_release(&vulnerable(Some(Severity::Critical))),
+            Err((
+                "vulnerability_severity",
+                "it has a known vulnerability, GHSA-aaaa-bbbb-cccc (critical severity), \
+                 at or above vulnerability_severity `high`"
+                    .to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&vulnerable(None)),
+            Err((
+                "vulnerability_severity",
+                "it has a known vulnerability, GHSA-aaaa-bbbb-cccc (unknown severity), \
+                 at or above vulnerability_severity `high`"
+                    .to_owned()
+            )),
+        );
+        let config = PackageConfig {
+            ignore_vulnerabilities: true,
+            ..config
+        };
+        assert_eq!(config.vulnerability_threshold(), None);
+        assert_eq!(config.check_release(&vulnerable(None)), Ok(vec![]));
+
+        let pattern = |s: &str| LicensePattern::try_from(s.to_owned()).unwrap();
+        let config = PackageConfig {
+            license_denylist: vec![pattern("AGPL-*"), pattern("*affero*")],
+            license_allowlist: vec![pattern("MIT*"), pattern("Apache-2.0")],
+            ..PackageConfig::default()
+        };
+        assert!(config.checks_license());
+        let licensed = |licenses: &[&str]| Release {
+            licenses: licenses.iter().map(|license| license.to_string()).collect(),
+            ..release("numpy-1.26.4.tar.gz")
+        };
+        assert_eq!(
+            config.check_release(&licensed(&["Apache-2.0", "MIT License"])),
+            Ok(vec![
+                "its license, Apache-2.0, matches license_allowlist entry `Apache-2.0`".to_owned()
+            ]),
+        );
+        assert_eq!(
+            config.check_release(&licensed(&["MIT", "GNU Affero General Public License v3"])),
+            Err((
+                "license_denylist",
+                "its license, GNU Affero General Public License v3, \
+                 matches license_denylist entry `*affero*`"
+                    .to_owned()
+            )),
+        );
+        // the rule comes with the rejection, rather than being guessed from its reason
+        assert_eq!(
+            config.check_release(&licensed(&["Mapping Toolkit Affero License"])),
+            Err((
+                "license_denylist",
+                "its license, Mapping Toolkit Affero License, \
+                 matches license_denylist entry `*affero*`"
+                    .to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&licensed(&["BSD-3-Clause", "BSD License"])),
+            Err((
+                "license_allowlist",
+                "its license, BSD-3-Clause, BSD License, matches no license_allowlist entry"
+                    .to_owned()
+            )),
+        );
+        assert_eq!(
+            config.check_release(&licensed(&[])),
+            Err((
+                "license_allowlist",
+                "can't tell what its license is, to check license_allowlist".to_owned()
+            )),
+        );
+        let config = PackageConfig {
+            license_allowlist: vec![],
+            ..config
+        };
+        assert_eq!(config.check_release(&licensed(&[])), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_reputation_policy() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let policy = ReputationPolicy {
+            min_project_age: Some(30 * day),
+            min_releases: Some(3),
+            require_repository: true,
+            mode: None,
+        };
+        let established = Reputation {
+            first_upload: Some(SystemTime::now() - 365 * day),
+            releases: 12,
+            repository: Some("https://github.com/acme/thing".to_owned()),
+        };
+        assert_eq!(policy.check(&established), Ok(()));
+        assert_eq!(
+            policy.check(&Reputation {
+                first_upload: Some(SystemTime::now() - 2 * day),
+                ..established.clone()
+            }),
+            Err(
+                "it was first uploaded 2days ago, within reputation.min_project_age `30days`"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            policy.check(&Reputation {
+                first_upload: None,
+                ..established.clone()
+            }),
+            Err(
+                "can't tell when it was first uploaded, to check reputation.min_project_age"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            policy.check(&Reputation {
+                releases: 1,
+                ..established.clone()
+            }),
+            Err("it has 1 release, fewer than reputation.min_releases `3`".to_owned())
+        );
+        assert_eq!(
+            policy.check(&Reputation {
+                repository: None,
+                ..established.clone()
+            }),
+            Err(
+                "it links to no source repository, which reputation.require_repository requires"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            ReputationPolicy::default().check(&Reputation::default()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_at() {
+        let snapshot = humantime::parse_rfc3339("2024-06-01T00:00:00Z").unwrap();
+        assert_eq!(
+            PackageConfig::default().at(snapshot),
+            PackageConfig {
+                uploaded_before: Some(snapshot),
+                ..PackageConfig::default()
+            }
+        );
+
+        // counted back from the snapshot rather than from now
+        let config = PackageConfig {
+            min_release_age: Some(Duration::from_secs(24 * 60 * 60)),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.at(snapshot).uploaded_before,
+            humantime::parse_rfc3339("2024-05-31T00:00:00Z").ok()
+        );
+
+        // and never later than a cutoff the config already has
+        let freeze = humantime::parse_rfc3339("2024-01-01T00:00:00Z").unwrap();
+        let config = PackageConfig {
+            uploaded_before: Some(freeze),
+            ..PackageConfig::default()
+        };
+        assert_eq!(config.at(snapshot).uploaded_before, Some(freeze));
+    }
+
+    #[test]
+    fn test_check_releases() {
+        let releases = [
+            release("numpy-1.26.4.tar.gz"),
+            release("numpy-1.26.4-cp312-cp312-win_amd64.whl"),
+            release("numpy-1.26.3.tar.gz"),
+            release("numpy-1.26.3-cp312-cp312-win32.whl"),
+        ];
+        let config = PackageConfig {
+            release_denylist: vec![DenylistEntry::from_str("*-win32.whl").unwrap()],
+            prefer_binary: true,
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.check_releases(&releases),
+            vec![
+                Err((
+                    "prefer_binary",
+                    "a wheel of version 1.26.4 is served instead, since prefer_binary is set"
+                        .to_owned()
+                )),
+                Ok(vec![]),
+                Ok(vec![
+                    "no wheel of version 1.26.3 is served, to prefer to it".to_owned()
+                ]),
+                Err((
+                    "release_denylist",
+                    "matches release_denylist entry `*-win32.whl`".to_owned()
+                )),
+            ],
+        );
+
+        let releases = [
+            release("six-1.17.0rc1.tar.gz"),
+            release("six-1.16.0.tar.gz"),
+            Release {
+                yanked: Some(String::new()),
+                ..release("six-1.15.1.tar.gz")
+            },
+            release("six-1.15.0.tar.gz"),
+            release("six-1.9.0.tar.gz"),
+            release("six-1.15.0-py2.py3-none-any.whl"),
+            release("six.tar.gz"),
+        ];
+        let config = PackageConfig {
+            latest_versions: Some(2),
+            ..PackageConfig::default()
+        };
+        let kept = |version: &str| {
+            Ok(vec![format!(
+                "version {version} is one of the newest 2, which latest_versions keeps"
+            )])
+        };
+        assert_eq!(
+            config.check_releases(&releases),
+            vec![
+                kept("1.17.0rc1"),
+                kept("1.16.0"),
+                kept("1.15.1"),
+                kept("1.15.0"),
+                Err((
+                    "latest_versions",
+                    "version 1.9.0 isn't one of the newest 2, which latest_versions keeps"
+                        .to_owned()
+                )),
+                kept("1.15.0"),
+                Err((
+                    "latest_versions",
+                    "can't tell which version it is, to check latest_versions".to_owned()
+                )),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_validate_pin() {
+        let pinned = |pin: &str| PackageConfig {
+            pin: Some(pin.to_owned()),
+            ..PackageConfig::default()
+        };
+        assert_eq!(pinned("1.26.4").validate(), Ok(()));
+        assert!(matches!(pinned(">=1.26").validate(), Err(("pin", _))));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let templates = HashMap::from([
+            (
+                "no-eggs".to_owned(),
+                PackageConfig {
+                    release_denylist: vec![DenylistEntry::from_str("numpy-1.0.egg").unwrap()],
+                    ..PackageConfig::default()
+                },
+            ),
+            (
+                "stable".to_owned(),
+                PackageConfig {
+                    extends: vec!["no-eggs".to_owned()],
+                    version_limits: SpecifierSet::parse_strict(">=1").unwrap(),
+                    ..PackageConfig::default()
+                },
+            ),
+            (
+                "trial".to_owned(),
+                PackageConfig {
+                    mode: Some(Mode::Audit),
+                    ..PackageConfig::default()
+                },
+            ),
+            (
+                "recursive".to_owned(),
+                PackageConfig {
+                    extends: vec!["recursive".to_owned()],
+                    ..PackageConfig::default()
+                },
+            ),
+        ]);
+
+        let config = PackageConfig {
+            extends: vec!["stable".to_owned()],
+            release_denylist: vec![DenylistEntry::from_str("numpy-1.1.tar.gz").unwrap()],
+            version_limits: SpecifierSet::parse_strict("<2").unwrap(),
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.resolve(&templates),
+            Ok(PackageConfig {
+                extends: vec![],
+                release_denylist: vec![
+                    DenylistEntry::from_str("numpy-1.0.egg").unwrap(),
+                    DenylistEntry::from_str("numpy-1.1.tar.gz").unwrap(),
+                ],
+                version_limits: SpecifierSet::parse_strict(">=1,<2").unwrap(),
+                ..PackageConfig::default()
+            }),
+        );
+
+        // settings with one value are overridden, even by laxer ones, but flags stay set
+        let cautious = HashMap::from([(
+            "cautious".to_owned(),
+            PackageConfig {
+                min_release_age: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+                max_glibc: Some("2.17".parse().unwrap()),
+                only_binary: true,
+                ..PackageConfig::default()
+            },
+        )]);
+        let config = PackageConfig {
+            extends: vec!["cautious".to_owned()],
+            min_release_age: Some(Duration::from_secs(24 * 60 * 60)),
+            only_binary: false,
+            ..PackageConfig::default()
+        };
+        assert_eq!(
+            config.resolve(&cautious),
+            Ok(PackageConfig {
+                min_release_age: Some(Duration::from_secs(24 * 60 * 60)),
+                max_glibc: Some("2.17".parse().unwrap()),
+                only_binary: true,
+                ..PackageConfig::default()
+            }),
+        );
+
+        let extends = |name: &str| PackageConfig {
+            extends: vec![name.to_owned()],
+            ..PackageConfig::default()
+        };
+        assert!(extends("trial").resolve(&templates).unwrap().is_audit());
+        let enforced = PackageConfig {
+            mode: Some(Mode::Enforce),
+            ..extends("trial")
+        };
+        assert!(!enforced.resolve(&templates).unwrap().is_audit());
+        let conflicting = PackageConfig {
+            only_binary: true,
+            no_binary: true,
+            ..PackageConfig::default()
+        };
+        assert!(matches!(
+            conflicting.resolve(&templates),
+            Err(("no_binary", _))
+        ));
+        assert_eq!(
+            extends("unstable").resolve(&templates),
+            Err(("extends", "no template named `unstable`".to_owned())),
+        );
+        assert_eq!(
+            extends("recursive").resolve(&templates),
+            Err(("extends", "template `recursive` extends itself".to_owned())),
+        );
+    }
+
+    #[test]
+    fn test_describe_changes() {
+        let config = |version_limits: &str| PackageConfig {
+            version_limits: SpecifierSet::parse_strict(version_limits).unwrap(),
+            ..PackageConfig::default()
+        };
+        let old = HashMap::from([
+            ("numpy".to_owned(), config("<2")),
+            ("protobuf".to_owned(), config("<4")),
+            ("six".to_owned(), config("<2")),
+        ]);
+        assert_eq!(describe_changes(&old, &old), None);
+
+        let new = HashMap::from([
+            ("numpy".to_owned(), config("<2")),
+            ("protobuf".to_owned(), config("<5")),
+            ("requests".to_owned(), config("<3")),
+        ]);
+        assert_eq!(
+            describe_changes(&old, &new).as_deref(),
+            Some("added requests; changed protobuf; removed six"),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watcher() {
+        let dir = std::env::temp_dir().join(format!("pyproxide-watched-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut watcher = Watcher::new(&UpstreamConfig {
+            package_config_dir: dir.clone(),
+            ..UpstreamConfig::default()
+        })
+        .unwrap();
+
+        std::fs::write(dir.join("README.md"), "not a config").unwrap();
+        std::fs::write(dir.join("numpy.json"), "{}").unwrap();
+        std::fs::write(dir.join("six.yaml"), "{}").unwrap();
+        let changed = tokio::time::timeout(Duration::from_secs(5), watcher.changed())
+            .await
+            .unwrap();
+        assert_eq!(
+            changed.into_iter().collect::<Vec<String>>(),
+            vec!["numpy.json", "six.yaml"],
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
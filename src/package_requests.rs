@@ -0,0 +1,234 @@
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, Syntax},
+    package_config, pep_503,
+};
+
+/// How many clients' requests are kept for one package; any more are turned away unrecorded.
+const MAX_REQUESTS: usize = 100;
+
+/// How many packages requests are kept for, so that asking for one made-up name after another
+/// can't fill the disk; requests for any others are turned away unrecorded.
+const MAX_PACKAGES: usize = 1000;
+
+/// Held while a package's requests are read and written back,
+/// so that two clients asking for it at once can't lose one of them.
+static RECORDS: Mutex<()> = Mutex::new(());
+
+/// Someone asking for a package which isn't on the allowlist.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Request {
+    /// The client's address, or the one a trusted frontend forwarded the request for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(with = "humantime_serde")]
+    pub at: SystemTime,
+}
+
+/// A package which isn't on the allowlist, waiting for an admin to approve or reject it.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageRequest {
+    /// The first request from each client, oldest first.
+    pub requests: Vec<Request>,
+    /// Why it was rejected, if it was. It's still refused, and its requests are still recorded,
+    /// in case it's reconsidered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<String>,
+}
+
+fn path(dir: &Path, package: &str) -> PathBuf {
+    dir.join(format!("{}.json", pep_503::normalize(package)))
+}
+
+/// The requests for `package` in `dir`, if there are any.
+pub fn load(dir: &Path, package: &str) -> Result<Option<PackageRequest>, String> {
+    let path = path(dir, package);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => config::parse(&path, &contents, Syntax::Json).map(Some),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("failed to read {}: {e}", path.display())),
+    }
+}
+
+/// Every package requested in `dir`, by normalized name.
+pub fn list(dir: &Path) -> Result<BTreeMap<String, PackageRequest>, String> {
+    let mut requests = BTreeMap::new();
+    for package in packages(dir)? {
+        if let Some(request) = load(dir, &package)? {
+            requests.insert(pep_503::normalize(&package), request);
+        }
+    }
+    Ok(requests)
+}
+
+/// The names of the packages requested in `dir`, as their files are named.
+fn packages(dir: &Path) -> Result<Vec<String>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(format!("failed to read {}: {e}", dir.display())),
+    };
+
+    let mut packages = vec![];
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("failed to read {}: {e}", dir.display()))?;
+        let file_name = entry.file_name();
+        match file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".json"))
+        {
+            Some(package) if pep_503::is_valid_project_name(package) => {
+                packages.push(package.to_owned())
+            }
+            _ => continue,
+        }
+    }
+    Ok(packages)
+}
+
+fn save(dir: &Path, package: &str, request: &PackageRequest) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    package_config::write(&path(dir, package), &config::render(request, Syntax::Json)?)
+}
+
+/// Records `request` for `package` in `dir`, unless its client has asked for it before,
+/// and returns every request for it, or `None` if requests for as many packages as are kept
+/// have been recorded already and it isn't one of them.
+pub fn record(
+    dir: &Path,
+    package: &str,
+    request: Request,
+) -> Result<Option<PackageRequest>, String> {
+    if !pep_503::is_valid_project_name(package) {
+        return Err(format!("{package} isn't a valid package name"));
+    }
+
+    let _records = RECORDS.lock().unwrap();
+    let mut requests = match load(dir, package)? {
+        Some(requests) => requests,
+        None if packages(dir)?.len() >= MAX_PACKAGES => return Ok(None),
+        None => PackageRequest::default(),
+    };
+    if requests.requests.len() < MAX_REQUESTS
+        && !requests
+            .requests
+            .iter()
+            .any(|requested| requested.client == request.client)
+    {
+        requests.requests.push(request);
+        save(dir, package, &requests)?;
+    }
+    Ok(Some(requests))
+}
+
+/// Rejects `package` for `reason`, returning its requests, or `None` if there are none to reject.
+pub fn reject(dir: &Path, package: &str, reason: &str) -> Result<Option<PackageRequest>, String> {
+    let _records = RECORDS.lock().unwrap();
+    let mut requests = match load(dir, package)? {
+        Some(requests) => requests,
+        None => return Ok(None),
+    };
+    requests.rejected = Some(reason.to_owned());
+    save(dir, package, &requests)?;
+    Ok(Some(requests))
+}
+
+/// Removes the requests for `package` from `dir`, e.g. once it's been approved,
+/// returning whether there were any.
+pub fn remove(dir: &Path, package: &str) -> Result<bool, String> {
+    let _records = RECORDS.lock().unwrap();
+    let path = path(dir, package);
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(format!("failed to remove {}: {e}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn request(client: &str, at: u64) -> Request {
+        Request {
+            client: Some(client.to_owned()),
+            user_agent: Some("pip/24.0".to_owned()),
+            at: SystemTime::UNIX_EPOCH + Duration::from_secs(at),
+        }
+    }
+
+    #[test]
+    fn test_requests() {
+        let dir =
+            std::env::temp_dir().join(format!("pyproxide-package-requests-{}", std::process::id()));
+        assert_eq!(list(&dir), Ok(BTreeMap::new()));
+        assert_eq!(reject(&dir, "six", "no"), Ok(None));
+
+        record(&dir, "Foo_Bar", request("10.0.0.1", 1)).unwrap();
+        // a client asking again isn't recorded again
+        record(&dir, "foo-bar", request("10.0.0.1", 2)).unwrap();
+        let requests = record(&dir, "foo.bar", request("10.0.0.2", 3))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            requests,
+            PackageRequest {
+                requests: vec![request("10.0.0.1", 1), request("10.0.0.2", 3)],
+                rejected: None,
+            }
+        );
+        assert_eq!(load(&dir, "FOO-BAR"), Ok(Some(requests.clone())));
+
+        let rejected = reject(&dir, "foo-bar", "unmaintained").unwrap().unwrap();
+        assert_eq!(rejected.rejected.as_deref(), Some("unmaintained"));
+        // and it stays rejected as it's asked for again
+        let requests = record(&dir, "foo-bar", request("10.0.0.3", 4))
+            .unwrap()
+            .unwrap();
+        assert_eq!(requests.rejected.as_deref(), Some("unmaintained"));
+        assert_eq!(requests.requests.len(), 3);
+
+        record(&dir, "six", request("10.0.0.1", 5)).unwrap();
+        assert_eq!(
+            list(&dir).unwrap().keys().collect::<Vec<_>>(),
+            vec!["foo-bar", "six"]
+        );
+
+        assert_eq!(remove(&dir, "foo_bar"), Ok(true));
+        assert_eq!(remove(&dir, "foo_bar"), Ok(false));
+        assert_eq!(load(&dir, "foo-bar"), Ok(None));
+
+        assert_eq!(
+            record(&dir, "../six", request("10.0.0.1", 6)),
+            Err("../six isn't a valid package name".to_owned())
+        );
+        for i in 1..MAX_PACKAGES {
+            std::fs::write(dir.join(format!("made-up-{i}.json")), "{}").unwrap();
+        }
+        // once as many packages as are kept have been requested, only those are recorded
+        assert_eq!(record(&dir, "numpy", request("10.0.0.1", 7)), Ok(None));
+        assert_eq!(load(&dir, "numpy"), Ok(None));
+        let requests = record(&dir, "six", request("10.0.0.2", 8))
+            .unwrap()
+            .unwrap();
+        assert_eq!(requests.requests.len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+// reference: https://peps.python.org/pep-0425/
+// and https://packaging.python.org/en/latest/specifications/platform-compatibility-tags/
+
+use std::{collections::HashSet, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{pep_427::WheelInfo, pep_600::LibcVersion};
+
+/// An operating system an environment runs.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Os {
+    Linux,
+    Macos,
+    Windows,
+}
+
+/// A kind of machine packages are installed on, as it's written in config, e.g.
+/// `{name = "prod", python = "3.11", os = "linux", arch = "x86_64", glibc = "2.28"}`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentSpec {
+    /// What it's called in the rules which let a wheel through.
+    pub name: String,
+    /// The Python version it runs, e.g. `3.11`.
+    pub python: String,
+    /// The interpreter, as it's abbreviated in tags: `cp` for CPython (when it's left out), `pp` for PyPy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub implementation: Option<String>,
+    /// The interpreter's ABI, e.g. `pypy310_pp73`.
+    /// For CPython it's e.g. `cp311` when it's left out; for anything else, only wheels
+    /// which don't depend on an ABI are compatible without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<String>,
+    pub os: Os,
+    /// The architecture, e.g. `x86_64` or `aarch64`.
+    pub arch: String,
+    /// On Linux, the glibc it has, e.g. `2.28`, for manylinux wheels to be compatible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glibc: Option<LibcVersion>,
+    /// On Linux, the musl it has, e.g. `1.2`, for musllinux wheels to be compatible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub musl: Option<LibcVersion>,
+    /// On macOS, the version it runs, e.g. `11.0`, which it's required on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub macos: Option<String>,
+}
+
+/// An environment, along with the tags of every wheel compatible with it, computed when it's read.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "EnvironmentSpec", into = "EnvironmentSpec")]
+pub struct Environment {
+    pub spec: EnvironmentSpec,
+    tags: Arc<HashSet<String>>,
+}
+
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        self.spec == other.spec
+    }
+}
+
+impl Eq for Environment {}
+
+impl TryFrom<EnvironmentSpec> for Environment {
+    type Error = String;
+
+    fn try_from(spec: EnvironmentSpec) -> Result<Self, Self::Error> {
+        let tags = tags(&spec).map_err(|e| format!("environment `{}`: {e}", spec.name))?;
+        Ok(Self {
+            spec,
+            tags: Arc::new(tags),
+        })
+    }
+}
+
+impl From<Environment> for EnvironmentSpec {
+    fn from(environment: Environment) -> Self {
+        environment.spec
+    }
+}
+
+impl Environment {
+    /// Whether `wheel` can be installed here, i.e. any one of its tags is.
+    pub fn supports(&self, wheel: &WheelInfo) -> bool {
+        wheel.tags().iter().any(|tag| self.tags.contains(tag))
+    }
+}
+
+/// A version like `3.11`, as its major and minor parts.
+fn version(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("`{s}` isn't a version like `3.11`");
+    let (major, minor) = s.split_once('.').ok_or_else(invalid)?;
+    Ok((
+        major.parse().map_err(|_| invalid())?,
+        minor.parse().map_err(|_| invalid())?,
+    ))
+}
+
+/// Every tag `spec` is compatible with, the way pip's own list of them is built.
+fn tags(spec: &EnvironmentSpec) -> Result<HashSet<String>, String> {
+    let (major, minor) = version(&spec.python)?;
+    let implementation = spec.implementation.as_deref().unwrap_or("cp");
+    let interpreter = format!("{implementation}{major}{minor}");
+    let abi = match &spec.abi {
+        Some(abi) => Some(abi.clone()),
+        None if implementation == "cp" => Some(interpreter.clone()),
+        None => None,
+    };
+    // pure-Python wheels for this version, the major version, and every version before this one
+    let pythons = [format!("py{major}{minor}"), format!("py{major}")]
+        .into_iter()
+        .chain((0..minor).rev().map(|minor| format!("py{major}{minor}")))
+        .collect::<Vec<_>>();
+
+    let mut tags = HashSet::new();
+    for platform in platforms(spec)? {
+        if let Some(abi) = &abi {
+            tags.insert(format!("{interpreter}-{abi}-{platform}"));
+        }
+        if implementation == "cp" && major == 3 {
+            // the stable ABI, as of this version or any before it
+            for minor in (2..=minor).rev() {
+                tags.insert(format!("cp3{minor}-abi3-{platform}"));
+            }
+        }
+        tags.insert(format!("{interpreter}-none-{platform}"));
+        for python in pythons.iter() {
+            tags.insert(format!("{python}-none-{platform}"));
+        }
+    }
+    tags.insert(format!("{interpreter}-none-any"));
+    for python in pythons.iter() {
+        tags.insert(format!("{python}-none-any"));
+    }
+    Ok(tags)
+}
+
+/// Every platform tag `spec` is compatible with.
+fn platforms(spec: &EnvironmentSpec) -> Result<Vec<String>, String> {
+    if spec.os != Os::Linux && (spec.glibc.is_some() || spec.musl.is_some()) {
+        return Err("only Linux has a glibc or musl".to_owned());
+    }
+    if spec.os != Os::Macos && spec.macos.is_some() {
+        return Err("only macOS has a macos version".to_owned());
+    }
+
+    let mut platforms = vec![];
+    match spec.os {
+        Os::Linux => {
+            let arch = match spec.arch.as_str() {
+                "arm64" => "aarch64",
+                "amd64" => "x86_64",
+                arch => arch,
+            };
+            if let Some(glibc) = spec.glibc {
+                for minor in (0..=glibc.minor).rev() {
+                    platforms.push(format!("manylinux_{}_{minor}_{arch}", glibc.major));
+                }
+                for (legacy, minor) in [
+                    ("manylinux2014", 17),
+                    ("manylinux2010", 12),
+                    ("manylinux1", 5),
+                ] {
+                    if glibc >= (LibcVersion { major: 2, minor }) {
+                        platforms.push(format!("{legacy}_{arch}"));
+                    }
+                }
+            }
+            if let Some(musl) = spec.musl {
+                for minor in (0..=musl.minor).rev() {
+                    platforms.push(format!("musllinux_{}_{minor}_{arch}", musl.major));
+                }
+            }
+            platforms.push(format!("linux_{arch}"));
+        }
+        Os::Macos => {
+            let arch = match spec.arch.as_str() {
+                "aarch64" => "arm64",
+                "amd64" => "x86_64",
+                arch => arch,
+            };
+            let macos = spec
+                .macos
+                .as_deref()
+                .ok_or("macOS needs a macos version, e.g. `11.0`")?;
+            let (major, minor) = version(macos)?;
+            // since macOS 11, only major versions are compatibility boundaries
+            let mut versions = (11..=major)
+                .rev()
+                .map(|major| (major, 0))
+                .collect::<Vec<_>>();
+            let last_minor = if major >= 11 { 16 } else { minor };
+            versions.extend((0..=last_minor).rev().map(|minor| (10, minor)));
+            let formats: &[&str] = match arch {
+                "arm64" => &["arm64", "universal2"],
+                "x86_64" => &[
+                    "x86_64",
+                    "intel",
+                    "fat64",
+                    "fat32",
+                    "universal2",
+                    "universal",
+                ],
+                arch => return Err(format!("unknown macOS architecture `{arch}`")),
+            };
+            for (major, minor) in versions {
+                for format in formats {
+                    // Apple silicon only ever ran macOS 11 and up
+                    if major == 10 && *format == "arm64" {
+                        continue;
+                    }
+                    platforms.push(format!("macosx_{major}_{minor}_{format}"));
+                }
+            }
+        }
+        Os::Windows => platforms.push(
+            match spec.arch.as_str() {
+                "x86_64" | "amd64" => "win_amd64",
+                "x86" | "i686" => "win32",
+                "aarch64" | "arm64" => "win_arm64",
+                arch => return Err(format!("unknown Windows architecture `{arch}`")),
+            }
+            .to_owned(),
+        ),
+    }
+    Ok(platforms)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn environment(os: Os, arch: &str) -> EnvironmentSpec {
+        EnvironmentSpec {
+            name: "prod".to_owned(),
+            python: "3.11".to_owned(),
+            implementation: None,
+            abi: None,
+            os,
+            arch: arch.to_owned(),
+            glibc: None,
+            musl: None,
+            macos: None,
+        }
+    }
+
+    fn supports(spec: &EnvironmentSpec, wheel: &str) -> bool {
+        let environment = Environment::try_from(spec.clone()).unwrap();
+        environment.supports(&WheelInfo::from_str(wheel).unwrap())
+    }
+
+    #[test]
+    fn test_linux() {
+        let spec = EnvironmentSpec {
+            glibc: Some("2.28".parse().unwrap()),
+            ..environment(Os::Linux, "x86_64")
+        };
+        assert!(supports(
+            &spec,
+            "numpy-2.1.0-cp311-cp311-manylinux_2_28_x86_64.whl"
+        ));
+        assert!(supports(
+            &spec,
+            "numpy-1.26.4-cp311-cp311-manylinux_2_17_x86_64.manylinux2014_x86_64.whl"
+        ));
+        assert!(supports(
+            &spec,
+            "cryptography-43.0.0-cp39-abi3-manylinux2014_x86_64.whl"
+        ));
+        assert!(supports(&spec, "six-1.16.0-py2.py3-none-any.whl"));
+        assert!(!supports(
+            &spec,
+            "numpy-2.1.0-cp311-cp311-manylinux_2_31_x86_64.whl"
+        ));
+        assert!(!supports(
+            &spec,
+            "numpy-2.1.0-cp311-cp311-manylinux_2_28_aarch64.whl"
+        ));
+        assert!(!supports(
+            &spec,
+            "numpy-2.1.0-cp312-cp312-manylinux_2_28_x86_64.whl"
+        ));
+        assert!(!supports(
+            &spec,
+            "numpy-2.1.0-cp311-cp311-musllinux_1_2_x86_64.whl"
+        ));
+        assert!(!supports(
+            &spec,
+            "cryptography-43.0.0-cp312-abi3-manylinux2014_x86_64.whl"
+        ));
+        assert!(!supports(&spec, "futures-3.0.5-py2-none-any.whl"));
+
+        let spec = EnvironmentSpec {
+            musl: Some("1.2".parse().unwrap()),
+            ..environment(Os::Linux, "aarch64")
+        };
+        assert!(supports(
+            &spec,
+            "numpy-2.1.0-cp311-cp311-musllinux_1_1_aarch64.whl"
+        ));
+        assert!(!supports(
+            &spec,
+            "numpy-2.1.0-cp311-cp311-manylinux_2_17_aarch64.whl"
+        ));
+    }
+
+    #[test]
+    fn test_macos_and_windows() {
+        let spec = EnvironmentSpec {
+            macos: Some("14.2".to_owned()),
+            ..environment(Os::Macos, "arm64")
+        };
+        assert!(supports(
+            &spec,
+            "numpy-2.1.0-cp311-cp311-macosx_14_0_arm64.whl"
+        ));
+        assert!(supports(
+            &spec,
+            "numpy-1.26.4-cp311-cp311-macosx_11_0_arm64.whl"
+        ));
+        assert!(supports(
+            &spec,
+            "pyyaml-6.0.2-cp311-cp311-macosx_10_9_universal2.whl"
+        ));
+        assert!(!supports(
+            &spec,
+            "numpy-1.26.4-cp311-cp311-macosx_10_9_x86_64.whl"
+        ));
+        assert!(!supports(
+            &spec,
+            "numpy-2.1.0-cp311-cp311-macosx_15_0_arm64.whl"
+        ));
+
+        let spec = environment(Os::Windows, "x86_64");
+        assert!(supports(&spec, "numpy-2.1.0-cp311-cp311-win_amd64.whl"));
+        assert!(!supports(&spec, "numpy-2.1.0-cp311-cp311-win32.whl"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let spec = EnvironmentSpec {
+            glibc: Some("2.28".parse().unwrap()),
+            ..environment(Os::Windows, "x86_64")
+        };
+        assert_eq!(
+            Environment::try_from(spec).map(|_| ()),
+            Err("environment `prod`: only Linux has a glibc or musl".to_owned())
+        );
+        assert_eq!(
+            Environment::try_from(environment(Os::Macos, "arm64")).map(|_| ()),
+            Err("environment `prod`: macOS needs a macos version, e.g. `11.0`".to_owned())
+        );
+        let spec = EnvironmentSpec {
+            python: "3".to_owned(),
+            ..environment(Os::Linux, "x86_64")
+        };
+        assert_eq!(
+            Environment::try_from(spec).map(|_| ()),
+            Err("environment `prod`: `3` isn't a version like `3.11`".to_owned())
+        );
+    }
+}
@@ -60,3 +60,24 @@ impl FromStr for WheelInfo {
         })
     }
 }
+
+impl WheelInfo {
+    /// Each platform the wheel is for, since its platform tag can be a compressed set of them,
+    /// e.g. `manylinux_2_17_x86_64.manylinux2014_x86_64`.
+    pub fn platform_tags(&self) -> impl Iterator<Item = &str> {
+        self.platform_tag.split('.')
+    }
+
+    /// Each tag the wheel is for, e.g. `py2-none-any` and `py3-none-any` for `py2.py3-none-any`.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags = vec![];
+        for python in self.python_tag.split('.') {
+            for abi in self.abi_tag.split('.') {
+                for platform in self.platform_tags() {
+                    tags.push(format!("{python}-{abi}-{platform}"));
+                }
+            }
+        }
+        tags
+    }
+}
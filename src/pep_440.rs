@@ -7,6 +7,7 @@ use std::str::FromStr;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PreRelease {
@@ -54,44 +55,50 @@ pub struct Version {
     local: Option<String>,
 }
 
-impl PartialOrd for Version {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        if let Some(epoch_cmp) = self.epoch.partial_cmp(&other.epoch) {
-            if epoch_cmp != Ordering::Equal {
-                return Some(epoch_cmp);
-            }
-        }
-
-	if let (None, Some(_)) = (self.pre_release, other.pre_release) {
-	    return Some(Ordering::Greater);
-	} else if let (Some(_), None) = (self.pre_release, other.pre_release) {
-	    return Some(Ordering::Less);
-	}
-
-        let versions_cmp = self.versions.cmp(&other.versions);
-        if versions_cmp != Ordering::Equal {
-            return Some(versions_cmp);
-        }
-
-        if let Some(pre_release_cmp) = self.pre_release.partial_cmp(&other.pre_release) {
-            if pre_release_cmp != Ordering::Equal {
-                return Some(pre_release_cmp);
-            }
-        }
-
-        if let Some(post_release_cmp) = self.post_release.partial_cmp(&other.post_release) {
-            if post_release_cmp != Ordering::Equal {
-                return Some(post_release_cmp);
-            }
+/// Epoch, release, pre-release, post-release and dev release, in the order they're compared by.
+type SortKey<'a> = (u32, &'a [u32], (u8, u32), Option<u32>, (u8, u32));
+
+impl Version {
+    /// What versions are ordered by, following PEP 440:
+    /// trailing zeros don't matter, and dev releases come before pre-releases,
+    /// which come before the final release, which comes before post-releases.
+    fn sort_key(&self) -> SortKey<'_> {
+        let mut versions = self.versions.as_slice();
+        while let [rest @ .., 0] = versions {
+            versions = rest;
         }
+        let pre_release = match (self.pre_release, self.post_release, self.dev_release) {
+            (Some(PreRelease::Alpha(n)), _, _) => (1, n),
+            (Some(PreRelease::Beta(n)), _, _) => (2, n),
+            (Some(PreRelease::ReleaseCandidate(n)), _, _) => (3, n),
+            // e.g. 1.0.dev1, before 1.0a1
+            (None, None, Some(_)) => (0, 0),
+            (None, _, _) => (4, 0),
+        };
+        let dev_release = match self.dev_release {
+            Some(n) => (0, n),
+            None => (1, 0),
+        };
+        (
+            self.epoch.unwrap_or(0),
+            versions,
+            pre_release,
+            self.post_release,
+            dev_release,
+        )
+    }
+}
 
-        if let Some(dev_release_cmp) = self.dev_release.partial_cmp(&other.dev_release) {
-            if dev_release_cmp != Ordering::Equal {
-                return Some(dev_release_cmp);
-            }
-        }
+impl Version {
+    /// Whether this is a pre-release or dev release, which pip passes over unless asked for them.
+    pub fn is_prerelease(&self) -> bool {
+        self.pre_release.is_some() || self.dev_release.is_some()
+    }
+}
 
-        Some(Ordering::Equal)
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.sort_key().cmp(&other.sort_key()))
     }
 }
 
@@ -220,19 +227,23 @@ impl ToString for Operator {
     }
 }
 
-// TODO: support wildcards in specifier comparisons
-// e.g. !=3.16.*
-// should mean no release in that range
-// but i'm not sure how we'd handle that here
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Specifier {
     operator: Operator,
     version: Version,
+    /// e.g. `!=3.16.*`, which matches on the release segments in `version` as a prefix.
+    /// Only allowed with `==` and `!=`.
+    wildcard: bool,
 }
 
 impl ToString for Specifier {
     fn to_string(&self) -> String {
-        format!("{}{}", self.operator.to_string(), self.version.to_string())
+        let wildcard_part = if self.wildcard { ".*" } else { "" };
+        format!(
+            "{}{}{wildcard_part}",
+            self.operator.to_string(),
+            self.version.to_string()
+        )
     }
 }
 
@@ -259,20 +270,71 @@ impl FromStr for Specifier {
             "<" => Operator::LessThan,
             other => return Err(format!("invalid operator: `{other}`")),
         };
-        let version = Version::from_str(captures.name("version").unwrap().as_str())?;
+        let version = captures.name("version").unwrap().as_str();
+        let (version, wildcard) = match version.strip_suffix(".*") {
+            Some(version) if matches!(operator, Operator::Equals | Operator::NotEquals) => {
+                (version, true)
+            }
+            Some(_) => {
+                return Err(format!(
+                    "wildcards are only allowed with == and !=: `{specifier_str}`"
+                ))
+            }
+            None => (version, false),
+        };
+        let version = Version::from_str(version)?;
 
-        Ok(Self { operator, version })
+        Ok(Self {
+            operator,
+            version,
+            wildcard,
+        })
     }
 }
 
 impl Specifier {
+    /// Whether `version` is this specifier's version, as far as `==` is concerned:
+    /// release segments are padded with zeros, so 2 is 2.0,
+    /// and local labels only count when the specifier has one.
+    fn matches_exactly(&self, version: &Version) -> bool {
+        let expected = &self.version;
+        let len = expected.versions.len().max(version.versions.len());
+        version.epoch.unwrap_or(0) == expected.epoch.unwrap_or(0)
+            && (0..len).all(|i| {
+                version.versions.get(i).copied().unwrap_or(0)
+                    == expected.versions.get(i).copied().unwrap_or(0)
+            })
+            && version.pre_release == expected.pre_release
+            && version.post_release == expected.post_release
+            && version.dev_release == expected.dev_release
+            && (expected.local.is_none() || version.local == expected.local)
+    }
+
     pub fn contains(&self, version: &Version) -> bool {
         use Operator::*;
 
+        if self.wildcard {
+            // shorter versions are padded with zeros, so 3.16 is in 3.16.0.*
+            let prefix = &self.version.versions;
+            let matches = version.epoch == self.version.epoch
+                && (0..prefix.len())
+                    .all(|i| version.versions.get(i).copied().unwrap_or(0) == prefix[i]);
+            return match self.operator {
+                NotEquals => !matches,
+                _ => matches,
+            };
+        }
+
         match self.operator {
-            Compatible => todo!(),
-            Equals => version == &self.version,
-            NotEquals => version != &self.version,
+            // ~=2.2 means >=2.2,==2.*
+            Compatible => {
+                let prefix = &self.version.versions[..self.version.versions.len() - 1];
+                version >= &self.version
+                    && version.epoch == self.version.epoch
+                    && version.versions.starts_with(prefix)
+            }
+            Equals => self.matches_exactly(version),
+            NotEquals => !self.matches_exactly(version),
             GreaterThanOrEqual => version >= &self.version,
             LessThanOrEqual => version <= &self.version,
             GreaterThan => version > &self.version,
@@ -281,7 +343,7 @@ impl Specifier {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct SpecifierSet {
     specifiers: Vec<Specifier>,
 }
@@ -311,6 +373,27 @@ impl FromStr for SpecifierSet {
 }
 
 impl SpecifierSet {
+    /// Like [`SpecifierSet::from_str`], except it fails on specifiers it can't parse
+    /// rather than skipping over them.
+    pub fn parse_strict(s: &str) -> Result<Self, String> {
+        let specifiers = s
+            .split(',')
+            .map(str::trim)
+            .filter(|specifier| !specifier.is_empty())
+            .map(Specifier::from_str)
+            .collect::<Result<Vec<Specifier>, String>>()?;
+        Ok(Self { specifiers })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specifiers.is_empty()
+    }
+
+    /// Narrows this set down to the versions which `other` contains as well.
+    pub fn extend(&mut self, other: SpecifierSet) {
+        self.specifiers.extend(other.specifiers);
+    }
+
     pub fn contains(&self, version: &Version) -> bool {
         for specifier in self.specifiers.iter() {
             if !specifier.contains(version) {
@@ -321,6 +404,49 @@ impl SpecifierSet {
     }
 }
 
+/// Written as a list of specifiers, e.g. `[">=3", "<4"]`,
+/// or as a single string of them, e.g. `">=3,<4"`.
+/// Either way, every specifier has to parse.
+impl<'de> Deserialize<'de> for SpecifierSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = SpecifierSet;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a list of version specifiers")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                SpecifierSet::parse_strict(s).map_err(E::custom)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut specifier_set = SpecifierSet::default();
+                while let Some(specifiers) = seq.next_element::<String>()? {
+                    specifier_set.extend(
+                        SpecifierSet::parse_strict(&specifiers)
+                            .map_err(serde::de::Error::custom)?,
+                    );
+                }
+                Ok(specifier_set)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl Serialize for SpecifierSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -339,12 +465,45 @@ mod tests {
                 pre_release: Some(PreRelease::ReleaseCandidate(3)),
                 post_release: Some(1),
                 dev_release: Some(2),
-		local: None,
+                local: None,
             }),
         );
     }
 
-    const SPECIFIER_SET_STR: &'static str = ">=1.2.3,<2";
+    #[test]
+    fn test_version_ordering() {
+        let ordered = [
+            "0.9",
+            "1.0.dev1",
+            "1.0a1.dev1",
+            "1.0a1",
+            "1.0b2",
+            "1.0rc1",
+            "1.0",
+            "1.0.post1.dev1",
+            "1.0.post1",
+            "1.1rc1",
+            "1.10",
+            "1!0.1",
+        ]
+        .map(|version| Version::from_str(version).unwrap());
+        for pair in ordered.windows(2) {
+            assert!(
+                pair[0] < pair[1],
+                "{} < {}",
+                pair[0].to_string(),
+                pair[1].to_string()
+            );
+        }
+
+        let padded = Version::from_str("1.0.0").unwrap();
+        assert_eq!(
+            padded.partial_cmp(&Version::from_str("1").unwrap()),
+            Some(Ordering::Equal)
+        );
+    }
+
+    const SPECIFIER_SET_STR: &str = ">=1.2.3,<2";
 
     fn make_specifier_set() -> SpecifierSet {
         SpecifierSet {
@@ -357,8 +516,9 @@ mod tests {
                         pre_release: None,
                         post_release: None,
                         dev_release: None,
-			local: None,
+                        local: None,
                     },
+                    wildcard: false,
                 },
                 Specifier {
                     operator: Operator::LessThan,
@@ -368,8 +528,9 @@ mod tests {
                         pre_release: None,
                         post_release: None,
                         dev_release: None,
-			local: None,
+                        local: None,
                     },
+                    wildcard: false,
                 },
             ],
         }
@@ -389,9 +550,64 @@ mod tests {
 
     #[test]
     fn test_specifier_set_pre_releases() {
-	let specifier_set = SpecifierSet::from_str(">=1.0.0").unwrap();
-	let version = Version::from_str("1.0.0a0").unwrap();
+        let specifier_set = SpecifierSet::from_str(">=1.0.0").unwrap();
+        let version = Version::from_str("1.0.0a0").unwrap();
 
-	assert_eq!(specifier_set.contains(&version), false);
+        assert_eq!(specifier_set.contains(&version), false);
+    }
+
+    #[test]
+    fn test_specifier_compatible() {
+        let specifier = Specifier::from_str("~=2.2").unwrap();
+        assert!(specifier.contains(&Version::from_str("2.2").unwrap()));
+        assert!(specifier.contains(&Version::from_str("2.10").unwrap()));
+        assert!(!specifier.contains(&Version::from_str("2.1").unwrap()));
+        assert!(!specifier.contains(&Version::from_str("3.0").unwrap()));
+    }
+
+    #[test]
+    fn test_specifier_wildcard() {
+        let specifier_set = SpecifierSet::parse_strict(">=3,!=3.16.*").unwrap();
+        assert_eq!(specifier_set.to_string(), ">=3,!=3.16.*");
+        assert!(specifier_set.contains(&Version::from_str("3.15.8").unwrap()));
+        assert!(!specifier_set.contains(&Version::from_str("3.16").unwrap()));
+        assert!(!specifier_set.contains(&Version::from_str("3.16.2").unwrap()));
+        assert!(specifier_set.contains(&Version::from_str("3.17.0").unwrap()));
+
+        let specifier = Specifier::from_str("==2.*").unwrap();
+        assert!(specifier.contains(&Version::from_str("2.31.0").unwrap()));
+        assert!(!specifier.contains(&Version::from_str("20.0").unwrap()));
+
+        assert_eq!(
+            SpecifierSet::parse_strict(">=3.*"),
+            Err("wildcards are only allowed with == and !=: `>=3.*`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_specifier_set_deserialize() {
+        let specifier_set = serde_json::from_str::<SpecifierSet>(r#"[">=3", "!=3.16.*", "<4"]"#);
+        assert_eq!(specifier_set.unwrap().to_string(), ">=3,!=3.16.*,<4");
+        let specifier_set = serde_json::from_str::<SpecifierSet>(r#"">=3, <4""#);
+        assert_eq!(specifier_set.unwrap().to_string(), ">=3,<4");
+
+        let e = serde_json::from_str::<SpecifierSet>(r#"[">=3", "<four"]"#).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "could not match version str: `four` at line 1 column 16"
+        );
+    }
+
+    #[test]
+    fn test_specifier_equals() {
+        let specifier = Specifier::from_str("==2").unwrap();
+        assert!(specifier.contains(&Version::from_str("2.0").unwrap()));
+        assert!(specifier.contains(&Version::from_str("2.0.0+ubuntu1").unwrap()));
+        assert!(!specifier.contains(&Version::from_str("2.0.1").unwrap()));
+        assert!(!specifier.contains(&Version::from_str("2.0rc1").unwrap()));
+
+        let specifier = Specifier::from_str("!=1.26.0+cpu").unwrap();
+        assert!(!specifier.contains(&Version::from_str("1.26+cpu").unwrap()));
+        assert!(specifier.contains(&Version::from_str("1.26").unwrap()));
     }
 }
@@ -1,20 +1,62 @@
 // reference: https://peps.python.org/pep-0503/
 
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, HashSet},
+    str::FromStr,
+    time::SystemTime,
+};
 
 use kuchiki::traits::TendrilSink;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use url::Url;
 
-#[derive(Eq, Debug, PartialEq)]
+use crate::{osv::Vulnerability, pep_427::WheelInfo, pep_440::Version};
+
+/// Whether `name` is a valid project name, and so safe to build paths and URLs from.
+///
+/// reference: https://packaging.python.org/en/latest/specifications/name-normalization/
+pub fn is_valid_project_name(name: &str) -> bool {
+    lazy_static! {
+        static ref PROJECT_NAME_RE: Regex =
+            Regex::new(r"^(?i:[A-Z0-9]|[A-Z0-9][A-Z0-9._-]*[A-Z0-9])$").unwrap();
+    }
+    PROJECT_NAME_RE.is_match(name)
+}
+
+/// The normalized form of a project name, e.g. `foo-bar` for `Foo_Bar`,
+/// which every spelling of the same project has in common.
+///
+/// reference: https://packaging.python.org/en/latest/specifications/name-normalization/
+pub fn normalize(name: &str) -> String {
+    lazy_static! {
+        static ref SEPARATORS_RE: Regex = Regex::new(r"[-_.]+").unwrap();
+    }
+    SEPARATORS_RE.replace_all(name, "-").to_lowercase()
+}
+
+#[derive(Eq, Debug, Default, PartialEq)]
 pub struct RootIndex {
     pub packages: Vec<String>,
 }
 
 impl ToString for RootIndex {
     fn to_string(&self) -> String {
+        self.render("")
+    }
+}
+
+impl RootIndex {
+    /// Renders the index with links under `base_url` (e.g. `https://pypi.example.com`),
+    /// or relative to the host it's served from when `base_url` is empty.
+    pub fn render(&self, base_url: &str) -> String {
         let links = self
             .packages
             .iter()
-            .map(|package| -> String { format!("<a href=\"/simple/{package}/\">{package}</a>") })
+            .map(|package| -> String {
+                format!("<a href=\"{base_url}/simple/{package}/\">{package}</a>")
+            })
             .collect::<Vec<String>>()
             .join("<br/>\n    ");
 
@@ -27,6 +69,16 @@ impl ToString for RootIndex {
 </html>"#
         )
     }
+
+    /// Adds the packages from a lower priority index which aren't already present.
+    pub fn merge(&mut self, other: RootIndex) {
+        let mut seen = self.packages.iter().cloned().collect::<HashSet<String>>();
+        for package in other.packages.into_iter() {
+            if seen.insert(package.clone()) {
+                self.packages.push(package);
+            }
+        }
+    }
 }
 
 impl FromStr for RootIndex {
@@ -45,7 +97,7 @@ impl FromStr for RootIndex {
             }
 
             let package = if let Some(child) = node_ref.first_child() {
-                child.as_text().unwrap().borrow().clone()
+                child.text_contents()
             } else {
                 continue;
             };
@@ -55,9 +107,12 @@ impl FromStr for RootIndex {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct PackageIndex {
     pub releases: Vec<Release>,
+    /// How many files were filtered out of it (or served as yanked instead),
+    /// by the setting which filtered each out.
+    pub filtered: BTreeMap<String, usize>,
 }
 
 impl ToString for PackageIndex {
@@ -68,18 +123,67 @@ impl ToString for PackageIndex {
             .map(Release::to_string)
             .collect::<Vec<String>>()
             .join("<br/>\n    ");
+        let summary = match self.filtered_summary() {
+            Some(summary) => format!("<!-- {summary} -->\n    "),
+            None => "".to_string(),
+        };
 
         format!(
             r#"<!DOCTYPE html>
 <html>
     <body>
-    {links}
+    {summary}{links}
     </body>
 </html>"#
         )
     }
 }
 
+impl PackageIndex {
+    /// How many files were filtered out, and by what, e.g. `3 filtered out: version_limits 2, yanked 1`,
+    /// or `None` if none were.
+    pub fn filtered_summary(&self) -> Option<String> {
+        if self.filtered.is_empty() {
+            return None;
+        }
+        let total = self.filtered.values().sum::<usize>();
+        let rules = self
+            .filtered
+            .iter()
+            .map(|(rule, count)| format!("{rule} {count}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        Some(format!("{total} filtered out: {rules}"))
+    }
+
+    /// Adds the releases from a lower priority index.
+    /// When both indexes have a file with the same name, ours wins.
+    pub fn merge(&mut self, other: PackageIndex) {
+        let mut seen = self
+            .releases
+            .iter()
+            .map(|release| release.name.clone())
+            .collect::<HashSet<String>>();
+        for release in other.releases.into_iter() {
+            if seen.insert(release.name.clone()) {
+                self.releases.push(release);
+            }
+        }
+    }
+}
+
+impl PackageIndex {
+    /// Resolves every release's (possibly relative) link
+    /// against `base`, the URL that this page was fetched from.
+    pub fn resolve_uris(&mut self, base: &Url) {
+        for release in self.releases.iter_mut() {
+            if let Ok(uri) = base.join(&release.uri) {
+                release.uri = uri.to_string();
+            }
+        }
+    }
+}
+
 impl FromStr for PackageIndex {
     type Err = ();
 
@@ -110,29 +214,157 @@ impl FromStr for PackageIndex {
             }
             .to_owned();
 
-            // TODO: do some verification that each has_gpg==true entry
-            // also has an associated GPG key
+            // taken at its word here; the `.asc` is only fetched (and checked)
+            // when an artifact of a package with `require_gpg_sig` is downloaded
             let has_gpg = attributes.get("data-gpg-sig") == Some("true");
             let requires_python = attributes.get("data-requires-python").map(str::to_owned);
+            let yanked = attributes.get("data-yanked").map(str::to_owned);
 
             releases.push(Release {
                 name,
                 uri,
                 has_gpg,
                 requires_python,
+                yanked,
+                upload_time: None,
+                vulnerabilities: vec![],
+                licenses: vec![],
+                filtered: None,
             })
         }
 
-        Ok(Self { releases })
+        Ok(Self {
+            releases,
+            filtered: BTreeMap::new(),
+        })
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Release {
     pub name: String,
     pub uri: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub has_gpg: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub requires_python: Option<String>,
+    /// Why the file was yanked (PEP 592), if it was: empty when no reason was given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yanked: Option<String>,
+    /// When the file was uploaded, if upstream says.
+    /// Simple index pages don't, so it's filled in from elsewhere when it's needed.
+    #[serde(skip)]
+    pub upload_time: Option<SystemTime>,
+    /// Known vulnerabilities in the file's version, filled in like `upload_time` is.
+    #[serde(skip)]
+    pub vulnerabilities: Vec<Vulnerability>,
+    /// The licenses of the file's project, filled in like `upload_time` is.
+    #[serde(skip)]
+    pub licenses: Vec<String>,
+    /// Why the file would have been filtered out, if it would have, in a dry run.
+    #[serde(skip)]
+    pub filtered: Option<String>,
+}
+
+impl Release {
+    /// The version this file is a distribution of, going by its filename.
+    pub fn version(&self) -> Option<Version> {
+        release_version(&self.name)
+    }
+
+    /// The file's sha256 digest, as given in the fragment of its link, e.g. `#sha256=...`.
+    pub fn sha256(&self) -> Option<&str> {
+        let (_, fragment) = self.uri.split_once('#')?;
+        fragment
+            .strip_prefix("sha256=")
+            .filter(|digest| !digest.is_empty())
+    }
+}
+
+/// Every archive pip will build a package from, like it, in lowercase.
+const SDIST_EXTENSIONS: [&str; 11] = [
+    ".tar.gz",
+    ".tgz",
+    ".tar.bz2",
+    ".tbz",
+    ".tar.xz",
+    ".txz",
+    ".tar.lz",
+    ".tlz",
+    ".tar.lzma",
+    ".tar",
+    ".zip",
+];
+
+/// Whether `filename` is named like a source distribution.
+pub fn is_sdist(filename: &str) -> bool {
+    let filename = filename.to_ascii_lowercase();
+    SDIST_EXTENSIONS
+        .iter()
+        .any(|extension| filename.ends_with(extension))
+}
+
+/// The kinds of file a release can be.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    Wheel,
+    Sdist,
+    Egg,
+    /// Anything else, e.g. Windows installers.
+    Other,
+}
+
+impl FileFormat {
+    /// The kind of file `filename` is, going by its extension.
+    pub fn of(filename: &str) -> Self {
+        let lowercase = filename.to_ascii_lowercase();
+        if lowercase.ends_with(".whl") {
+            FileFormat::Wheel
+        } else if is_sdist(&lowercase) {
+            FileFormat::Sdist
+        } else if lowercase.ends_with(".egg") {
+            FileFormat::Egg
+        } else {
+            FileFormat::Other
+        }
+    }
+}
+
+impl ToString for FileFormat {
+    fn to_string(&self) -> String {
+        match self {
+            FileFormat::Wheel => "wheel",
+            FileFormat::Sdist => "sdist",
+            FileFormat::Egg => "egg",
+            FileFormat::Other => "other",
+        }
+        .to_owned()
+    }
+}
+
+/// The version a wheel or source distribution is of, going by its filename.
+pub fn release_version(filename: &str) -> Option<Version> {
+    if let Ok(wheel_info) = WheelInfo::from_str(filename) {
+        return Version::from_str(&wheel_info.version).ok();
+    }
+    let sdist = SDIST_EXTENSIONS
+        .iter()
+        .find_map(|extension| filename.strip_suffix(extension))?;
+    let (_, version) = sdist.rsplit_once('-')?;
+    Version::from_str(version).ok()
+}
+
+/// The normalized name of the package a wheel or source distribution is of, going by its filename.
+pub fn release_package(filename: &str) -> Option<String> {
+    if let Ok(wheel_info) = WheelInfo::from_str(filename) {
+        return Some(normalize(&wheel_info.distribution));
+    }
+    let sdist = SDIST_EXTENSIONS
+        .iter()
+        .find_map(|extension| filename.strip_suffix(extension))?;
+    let (package, _) = sdist.rsplit_once('-')?;
+    Some(normalize(package))
 }
 
 impl ToString for Release {
@@ -148,9 +380,21 @@ impl ToString for Release {
         } else {
             ""
         };
+        let yanked_part = if let Some(reason) = &self.yanked {
+            let reason = reason.replace('&', "&amp;").replace('"', "&quot;");
+            format!(" data-yanked=\"{reason}\"")
+        } else {
+            "".to_string()
+        };
+        let filtered_part = if let Some(reason) = &self.filtered {
+            let reason = reason.replace('&', "&amp;").replace('"', "&quot;");
+            format!(" data-pyproxide-filtered=\"{reason}\"")
+        } else {
+            "".to_string()
+        };
         let name = &self.name;
 
-        format!("<a href=\"{uri}\"{requires_python_part}{gpg_sig_part}>{name}</a>")
+        format!("<a href=\"{uri}\"{requires_python_part}{gpg_sig_part}{yanked_part}{filtered_part}>{name}</a>")
     }
 }
 
@@ -193,4 +437,217 @@ mod tests {
 </html>"#,
         );
     }
+
+    #[test]
+    fn test_is_valid_project_name() {
+        assert!(is_valid_project_name("numpy"));
+        assert!(is_valid_project_name("Django"));
+        assert!(is_valid_project_name("zope.interface"));
+        assert!(is_valid_project_name("typing_extensions"));
+        assert!(is_valid_project_name("x"));
+        assert!(!is_valid_project_name(""));
+        assert!(!is_valid_project_name("-numpy"));
+        assert!(!is_valid_project_name("numpy."));
+        assert!(!is_valid_project_name(".."));
+        assert!(!is_valid_project_name("../../etc/passwd"));
+        assert!(!is_valid_project_name("numpy json"));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("numpy"), "numpy");
+        assert_eq!(normalize("Foo_Bar"), "foo-bar");
+        assert_eq!(normalize("zope.interface"), "zope-interface");
+        assert_eq!(normalize("Typing__Extensions"), "typing-extensions");
+        assert_eq!(normalize("a-_.b"), "a-b");
+    }
+
+    #[test]
+    fn test_root_index_nested_anchor_text() {
+        let root_index =
+            RootIndex::from_str(r#"<a href="/simple/numpy/"><span>numpy</span></a>"#).unwrap();
+        assert_eq!(root_index.packages, vec!["numpy".to_string()]);
+    }
+
+    #[test]
+    fn test_release_version() {
+        let version = |name: &str| make_release(name, "").version().map(|v| v.to_string());
+        assert_eq!(
+            version("numpy-1.26.0-cp312-cp312-manylinux_2_17_x86_64.whl"),
+            Some("1.26.0".to_string()),
+        );
+        assert_eq!(
+            version("zope.interface-6.0.tar.gz"),
+            Some("6.0".to_string())
+        );
+        assert_eq!(version("scikit-learn-1.3.0.zip"), Some("1.3.0".to_string()));
+        assert_eq!(version("numpy-1.0.egg"), None);
+    }
+
+    #[test]
+    fn test_is_sdist() {
+        assert!(is_sdist("zope.interface-6.0.tar.gz"));
+        assert!(is_sdist("lxml-5.1.0.tar.xz"));
+        assert!(is_sdist("Pillow-2.0.0.ZIP"));
+        assert!(!is_sdist("numpy-1.26.0-cp312-cp312-win_amd64.whl"));
+        assert!(!is_sdist("numpy-1.0-py2.7.egg"));
+    }
+
+    #[test]
+    fn test_file_format() {
+        assert_eq!(FileFormat::of("Pillow-2.0.0.ZIP"), FileFormat::Sdist);
+        assert_eq!(
+            FileFormat::of("numpy-1.26.0-cp312-cp312-win_amd64.whl"),
+            FileFormat::Wheel
+        );
+        assert_eq!(FileFormat::of("numpy-1.0-py2.7.egg"), FileFormat::Egg);
+        assert_eq!(FileFormat::of("numpy-1.0.win32.exe"), FileFormat::Other);
+    }
+
+    #[test]
+    fn test_release_package() {
+        assert_eq!(
+            release_package("Zope.Interface-6.0.tar.gz"),
+            Some("zope-interface".to_owned())
+        );
+        assert_eq!(
+            release_package("typing_extensions-4.9.0-py3-none-any.whl"),
+            Some("typing-extensions".to_owned())
+        );
+        assert_eq!(release_package("numpy-1.0.win32.exe"), None);
+    }
+
+    fn make_release(name: &str, uri: &str) -> Release {
+        Release {
+            name: name.to_string(),
+            uri: uri.to_string(),
+            has_gpg: false,
+            requires_python: None,
+            yanked: None,
+            upload_time: None,
+            vulnerabilities: vec![],
+            licenses: vec![],
+            filtered: None,
+        }
+    }
+
+    #[test]
+    fn test_root_index_merge() {
+        let mut root_index = RootIndex {
+            packages: vec!["numpy".to_string(), "protobuf".to_string()],
+        };
+        root_index.merge(RootIndex {
+            packages: vec!["acme-utils".to_string(), "numpy".to_string()],
+        });
+        assert_eq!(
+            root_index.packages,
+            vec![
+                "numpy".to_string(),
+                "protobuf".to_string(),
+                "acme-utils".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_package_index_merge_prefers_self() {
+        let mut package_index = PackageIndex {
+            releases: vec![make_release(
+                "acme-1.0.tar.gz",
+                "https://internal/acme-1.0.tar.gz",
+            )],
+            ..PackageIndex::default()
+        };
+        package_index.merge(PackageIndex {
+            releases: vec![
+                make_release("acme-1.0.tar.gz", "https://public/acme-1.0.tar.gz"),
+                make_release("acme-0.9.tar.gz", "https://public/acme-0.9.tar.gz"),
+            ],
+            ..PackageIndex::default()
+        });
+        assert_eq!(
+            package_index,
+            PackageIndex {
+                releases: vec![
+                    make_release("acme-1.0.tar.gz", "https://internal/acme-1.0.tar.gz"),
+                    make_release("acme-0.9.tar.gz", "https://public/acme-0.9.tar.gz"),
+                ],
+                ..PackageIndex::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_yanked() {
+        let package_index = PackageIndex::from_str(
+            r#"<a href="a-1.0.tar.gz" data-yanked="">a-1.0.tar.gz</a>
+<a href="a-0.9.tar.gz" data-yanked="&quot;bad&quot; &amp; broken">a-0.9.tar.gz</a>
+<a href="a-0.8.tar.gz">a-0.8.tar.gz</a>"#,
+        )
+        .unwrap();
+        let yanked = package_index
+            .releases
+            .iter()
+            .map(|release| release.yanked.as_deref())
+            .collect::<Vec<Option<&str>>>();
+        assert_eq!(yanked, vec![Some(""), Some("\"bad\" & broken"), None]);
+        assert_eq!(
+            package_index.releases[1].to_string(),
+            r#"<a href="a-0.9.tar.gz" data-yanked="&quot;bad&quot; &amp; broken">a-0.9.tar.gz</a>"#,
+        );
+
+        let package_index = PackageIndex {
+            releases: vec![package_index.releases[2].clone()],
+            filtered: BTreeMap::from([("version_limits".to_owned(), 2), ("yanked".to_owned(), 1)]),
+        };
+        assert_eq!(
+            package_index.filtered_summary().as_deref(),
+            Some("3 filtered out: version_limits 2, yanked 1"),
+        );
+        assert!(package_index
+            .to_string()
+            .contains("<!-- 3 filtered out: version_limits 2, yanked 1 -->"));
+
+        let mut release = package_index.releases[0].clone();
+        release.filtered = Some("it's \"bad\"".to_owned());
+        assert_eq!(
+            release.to_string(),
+            r#"<a href="a-0.8.tar.gz" data-pyproxide-filtered="it's &quot;bad&quot;">a-0.8.tar.gz</a>"#,
+        );
+    }
+
+    #[test]
+    fn test_package_index_resolve_uris() {
+        let mut package_index = PackageIndex {
+            releases: vec![
+                make_release(
+                    "acme-1.0.tar.gz",
+                    "../../packages/acme/acme-1.0.tar.gz#sha256=abc",
+                ),
+                make_release(
+                    "acme-0.9.tar.gz",
+                    "https://files.pythonhosted.org/packages/ab/cd/acme-0.9.tar.gz",
+                ),
+            ],
+            ..PackageIndex::default()
+        };
+        package_index
+            .resolve_uris(&Url::parse("https://devpi.example.com/root/simple/acme/").unwrap());
+        assert_eq!(
+            package_index,
+            PackageIndex {
+                releases: vec![
+                    make_release(
+                        "acme-1.0.tar.gz",
+                        "https://devpi.example.com/root/packages/acme/acme-1.0.tar.gz#sha256=abc",
+                    ),
+                    make_release(
+                        "acme-0.9.tar.gz",
+                        "https://files.pythonhosted.org/packages/ab/cd/acme-0.9.tar.gz",
+                    ),
+                ],
+                ..PackageIndex::default()
+            },
+        );
+    }
 }
@@ -0,0 +1,106 @@
+// reference: https://peps.python.org/pep-0600/
+
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// The version of a C library a platform tag names, e.g. glibc 2.17 for `manylinux_2_17_x86_64`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LibcVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ToString for LibcVersion {
+    fn to_string(&self) -> String {
+        format!("{}.{}", self.major, self.minor)
+    }
+}
+
+impl FromStr for LibcVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{s}` isn't a version like `2.17`");
+        let (major, minor) = s.split_once('.').ok_or_else(invalid)?;
+        Ok(Self {
+            major: major.parse().map_err(|_| invalid())?,
+            minor: minor.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for LibcVersion {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::from_str(&s)
+    }
+}
+
+impl From<LibcVersion> for String {
+    fn from(version: LibcVersion) -> Self {
+        version.to_string()
+    }
+}
+
+/// The glibc a `manylinux` platform tag (one of a wheel's, which can have several) needs,
+/// or `None` if it isn't one, including the legacy `manylinux1`, `manylinux2010` and `manylinux2014`.
+pub fn glibc(platform_tag: &str) -> Option<LibcVersion> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^manylinux_(\d+)_(\d+)_[a-z0-9_]+$").unwrap();
+        static ref LEGACY_RE: Regex = Regex::new(r"^manylinux(1|2010|2014)_[a-z0-9_]+$").unwrap();
+    }
+
+    if let Some(captures) = RE.captures(platform_tag) {
+        return Some(LibcVersion {
+            major: captures[1].parse().ok()?,
+            minor: captures[2].parse().ok()?,
+        });
+    }
+    let minor = match &LEGACY_RE.captures(platform_tag)?[1] {
+        "1" => 5,
+        "2010" => 12,
+        _ => 17,
+    };
+    Some(LibcVersion { major: 2, minor })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn version(major: u32, minor: u32) -> Option<LibcVersion> {
+        Some(LibcVersion { major, minor })
+    }
+
+    #[test]
+    fn test_glibc() {
+        assert_eq!(glibc("manylinux_2_17_x86_64"), version(2, 17));
+        assert_eq!(glibc("manylinux_2_28_aarch64"), version(2, 28));
+        assert_eq!(glibc("manylinux1_i686"), version(2, 5));
+        assert_eq!(glibc("manylinux2010_x86_64"), version(2, 12));
+        assert_eq!(glibc("manylinux2014_ppc64le"), version(2, 17));
+        assert_eq!(glibc("musllinux_1_1_x86_64"), None);
+        assert_eq!(glibc("linux_x86_64"), None);
+        assert_eq!(glibc("manylinux_2_x86_64"), None);
+        assert_eq!(glibc("any"), None);
+    }
+
+    #[test]
+    fn test_libc_version() {
+        assert_eq!("2.17".parse::<LibcVersion>().ok(), version(2, 17));
+        assert!(version(2, 5) < version(2, 17));
+        assert_eq!(version(2, 28).unwrap().to_string(), "2.28");
+        assert_eq!(
+            "2".parse::<LibcVersion>(),
+            Err("`2` isn't a version like `2.17`".to_owned())
+        );
+        assert!("2.x".parse::<LibcVersion>().is_err());
+    }
+}
@@ -0,0 +1,42 @@
+// reference: https://peps.python.org/pep-0656/
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::pep_600::LibcVersion;
+
+/// The musl a `musllinux` platform tag (one of a wheel's, which can have several) needs,
+/// e.g. musl 1.2 for `musllinux_1_2_x86_64`, or `None` if it isn't one.
+pub fn musl(platform_tag: &str) -> Option<LibcVersion> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^musllinux_(\d+)_(\d+)_[a-z0-9_]+$").unwrap();
+    }
+
+    let captures = RE.captures(platform_tag)?;
+    Some(LibcVersion {
+        major: captures[1].parse().ok()?,
+        minor: captures[2].parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_musl() {
+        assert_eq!(
+            musl("musllinux_1_2_x86_64"),
+            Some(LibcVersion { major: 1, minor: 2 })
+        );
+        assert_eq!(
+            musl("musllinux_1_1_aarch64"),
+            Some(LibcVersion { major: 1, minor: 1 })
+        );
+        assert_eq!(musl("manylinux_2_17_x86_64"), None);
+        assert_eq!(musl("musllinux_1_x86_64"), None);
+        assert_eq!(musl("any"), None);
+    }
+}
@@ -0,0 +1,279 @@
+// reference: https://peps.python.org/pep-0691/
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::pep_503::{PackageIndex, Release, RootIndex};
+
+pub const JSON_CONTENT_TYPE: &str = "application/vnd.pypi.simple.v1+json";
+pub const HTML_CONTENT_TYPE: &str = "application/vnd.pypi.simple.v1+html";
+pub const LEGACY_HTML_CONTENT_TYPE: &str = "text/html";
+
+const API_VERSION: &str = "1.0";
+
+/// The representations an index page can be served in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Json,
+    Html,
+    /// Plain PEP 503 HTML, for clients which predate PEP 691.
+    LegacyHtml,
+}
+
+impl Format {
+    /// Picks the representation to serve a client with, given its `Accept`.
+    /// Clients which don't say get legacy HTML, like PyPI does.
+    /// `None` means none of the client's choices are something we serve.
+    pub fn negotiate(accept: Option<&str>) -> Option<Self> {
+        let accept = if let Some(accept) = accept {
+            accept
+        } else {
+            return Some(Format::LegacyHtml);
+        };
+
+        let mut best: Option<(Format, f32)> = None;
+        for media_range in accept.split(',') {
+            let mut parts = media_range.split(';').map(str::trim);
+            let mime = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map(|q| q.parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            if quality <= 0.0 {
+                continue;
+            }
+
+            let format = match mime.as_str() {
+                JSON_CONTENT_TYPE => Format::Json,
+                HTML_CONTENT_TYPE => Format::Html,
+                LEGACY_HTML_CONTENT_TYPE | "text/*" | "*/*" => Format::LegacyHtml,
+                _ => continue,
+            };
+
+            // ties go to whichever the client listed first
+            let is_better = match best {
+                None => true,
+                Some((_, best_quality)) => quality > best_quality,
+            };
+            if is_better {
+                best = Some((format, quality));
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => JSON_CONTENT_TYPE,
+            Format::Html => HTML_CONTENT_TYPE,
+            Format::LegacyHtml => LEGACY_HTML_CONTENT_TYPE,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Meta<'a> {
+    #[serde(rename = "api-version")]
+    api_version: &'static str,
+    /// How many files were filtered out of a project, by setting, under a key PEP 691 leaves to us.
+    #[serde(
+        rename = "_pyproxide-filtered",
+        skip_serializing_if = "Option::is_none"
+    )]
+    filtered: Option<&'a BTreeMap<String, usize>>,
+}
+
+const META: Meta<'static> = Meta {
+    api_version: API_VERSION,
+    filtered: None,
+};
+
+#[derive(Serialize)]
+struct Project<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct RootPage<'a> {
+    meta: Meta<'a>,
+    projects: Vec<Project<'a>>,
+}
+
+#[derive(Serialize)]
+struct File<'a> {
+    filename: &'a str,
+    url: &'a str,
+    hashes: BTreeMap<&'a str, &'a str>,
+    #[serde(rename = "requires-python", skip_serializing_if = "Option::is_none")]
+    requires_python: Option<&'a str>,
+    #[serde(rename = "gpg-sig", skip_serializing_if = "std::ops::Not::not")]
+    gpg_sig: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    yanked: Option<Yanked<'a>>,
+    /// Why the file would have been filtered out, in a dry run, under a key PEP 691 leaves to us.
+    #[serde(
+        rename = "_pyproxide-filtered",
+        skip_serializing_if = "Option::is_none"
+    )]
+    filtered: Option<&'a str>,
+}
+
+/// `true` for files yanked without a reason, or else the reason.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Yanked<'a> {
+    Yanked(bool),
+    Reason(&'a str),
+}
+
+impl<'a> From<&'a Release> for File<'a> {
+    fn from(release: &'a Release) -> Self {
+        // PEP 503 carries the hash in the URL fragment, e.g. `#sha256=...`
+        let (url, fragment) = release
+            .uri
+            .split_once('#')
+            .unwrap_or((release.uri.as_str(), ""));
+        let hashes = fragment
+            .split_once('=')
+            .filter(|(_, digest)| !digest.is_empty())
+            .into_iter()
+            .collect();
+
+        Self {
+            filename: &release.name,
+            url,
+            hashes,
+            requires_python: release.requires_python.as_deref(),
+            gpg_sig: release.has_gpg,
+            yanked: release.yanked.as_deref().map(|reason| match reason {
+                "" => Yanked::Yanked(true),
+                reason => Yanked::Reason(reason),
+            }),
+            filtered: release.filtered.as_deref(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProjectPage<'a> {
+    meta: Meta<'a>,
+    name: &'a str,
+    files: Vec<File<'a>>,
+}
+
+pub fn render_root_index(root_index: &RootIndex) -> String {
+    let page = RootPage {
+        meta: META,
+        projects: root_index
+            .packages
+            .iter()
+            .map(|name| Project { name })
+            .collect(),
+    };
+    serde_json::to_string(&page).unwrap()
+}
+
+pub fn render_package_index(name: &str, package_index: &PackageIndex) -> String {
+    let page = ProjectPage {
+        meta: Meta {
+            filtered: Some(&package_index.filtered).filter(|filtered| !filtered.is_empty()),
+            ..META
+        },
+        name,
+        files: package_index.releases.iter().map(File::from).collect(),
+    };
+    serde_json::to_string(&page).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Format::negotiate(None), Some(Format::LegacyHtml));
+        assert_eq!(Format::negotiate(Some("*/*")), Some(Format::LegacyHtml));
+        assert_eq!(
+            Format::negotiate(Some(
+                "application/vnd.pypi.simple.v1+json, application/vnd.pypi.simple.v1+html;q=0.2, text/html;q=0.01"
+            )),
+            Some(Format::Json),
+        );
+        assert_eq!(
+            Format::negotiate(Some(
+                "application/vnd.pypi.simple.v1+json;q=0.5, application/vnd.pypi.simple.v1+html"
+            )),
+            Some(Format::Html),
+        );
+        assert_eq!(Format::negotiate(Some("application/json")), None);
+    }
+
+    #[test]
+    fn test_render_package_index() {
+        let package_index = PackageIndex {
+            releases: vec![
+                Release {
+                    name: "numpy-1.0.tar.gz".to_string(),
+                    uri: "/packages/ab/numpy-1.0.tar.gz#sha256=abc123".to_string(),
+                    has_gpg: false,
+                    requires_python: Some(">=3.8".to_string()),
+                    yanked: None,
+                    upload_time: None,
+                    vulnerabilities: vec![],
+                    licenses: vec![],
+                    filtered: None,
+                },
+                Release {
+                    name: "numpy-0.9.tar.gz".to_string(),
+                    uri: "/packages/cd/numpy-0.9.tar.gz".to_string(),
+                    has_gpg: true,
+                    requires_python: None,
+                    yanked: Some("".to_string()),
+                    upload_time: None,
+                    vulnerabilities: vec![],
+                    licenses: vec![],
+                    filtered: Some("it's yanked".to_string()),
+                },
+            ],
+            filtered: BTreeMap::from([("yanked".to_string(), 1)]),
+        };
+        let page = render_package_index("numpy", &package_index);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&page).unwrap(),
+            serde_json::json!({
+                "meta": {"api-version": "1.0", "_pyproxide-filtered": {"yanked": 1}},
+                "name": "numpy",
+                "files": [
+                    {
+                        "filename": "numpy-1.0.tar.gz",
+                        "url": "/packages/ab/numpy-1.0.tar.gz",
+                        "hashes": {"sha256": "abc123"},
+                        "requires-python": ">=3.8",
+                    },
+                    {
+                        "filename": "numpy-0.9.tar.gz",
+                        "url": "/packages/cd/numpy-0.9.tar.gz",
+                        "hashes": {},
+                        "gpg-sig": true,
+                        "yanked": true,
+                        "_pyproxide-filtered": "it's yanked",
+                    },
+                ],
+            }),
+        );
+    }
+
+    #[test]
+    fn test_render_root_index() {
+        let root_index = RootIndex {
+            packages: vec!["numpy".to_string(), "scipy".to_string()],
+        };
+        assert_eq!(
+            render_root_index(&root_index),
+            r#"{"meta":{"api-version":"1.0"},"projects":[{"name":"numpy"},{"name":"scipy"}]}"#,
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::{collections::HashMap, sync::Mutex};
+
+/// Counts how often each package is requested, favoring recent requests:
+/// every count is halved on each [`Popularity::decay`],
+/// so a package which stops being requested drops out of the top after a while.
+#[derive(Default)]
+pub struct Popularity {
+    counts: Mutex<HashMap<String, u64>>,
+}
+
+impl Popularity {
+    pub fn record(&self, package: &str) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry(package.to_owned())
+            .or_default() += 1;
+    }
+
+    /// The `n` most requested packages, most requested first.
+    pub fn top(&self, n: usize) -> Vec<String> {
+        let counts = self.counts.lock().unwrap();
+        let mut packages = counts.iter().collect::<Vec<(&String, &u64)>>();
+        // ties are broken by name, to keep the order stable
+        packages.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        packages
+            .into_iter()
+            .take(n)
+            .map(|(package, _)| package.clone())
+            .collect()
+    }
+
+    pub fn decay(&self) {
+        let mut counts = self.counts.lock().unwrap();
+        for count in counts.values_mut() {
+            *count /= 2;
+        }
+        counts.retain(|_, count| *count > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_top() {
+        let popularity = Popularity::default();
+        for package in ["numpy", "scipy", "numpy", "torch", "numpy", "scipy"] {
+            popularity.record(package);
+        }
+        assert_eq!(popularity.top(2), vec!["numpy", "scipy"]);
+        assert_eq!(popularity.top(5), vec!["numpy", "scipy", "torch"]);
+
+        popularity.decay();
+        assert_eq!(popularity.top(5), vec!["numpy", "scipy"]);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::SystemTime,
+};
+
+use serde::Deserialize;
+use url::Url;
+
+#[derive(Deserialize)]
+struct Project {
+    #[serde(default)]
+    info: Info,
+    #[serde(default)]
+    releases: HashMap<String, Vec<File>>,
+}
+
+/// About the project's latest release.
+#[derive(Default, Deserialize)]
+struct Info {
+    /// An SPDX expression (PEP 639), e.g. `MIT OR Apache-2.0`.
+    license_expression: Option<String>,
+    /// Free text: anything from `MIT` to the whole license.
+    license: Option<String>,
+    #[serde(default)]
+    classifiers: Vec<String>,
+    home_page: Option<String>,
+    /// By label, e.g. `Source`. `null` when there are none.
+    project_urls: Option<BTreeMap<String, String>>,
+}
+
+/// Hosts whose links are taken for a source repository, whatever they're labelled.
+const FORGES: &[&str] = &[
+    "github.com",
+    "gitlab.com",
+    "bitbucket.org",
+    "codeberg.org",
+    "git.sr.ht",
+];
+
+/// Labels of project URLs which are taken for a source repository, wherever they point.
+const REPOSITORY_LABELS: &[&str] = &["source", "source code", "repository", "code"];
+
+#[derive(Deserialize)]
+struct File {
+    filename: String,
+    upload_time_iso_8601: Option<String>,
+}
+
+/// When each file of a project was uploaded, by filename,
+/// from PyPI's JSON API (https://docs.pypi.org/api/json/), since simple index pages don't say.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UploadTimes(HashMap<String, SystemTime>);
+
+impl UploadTimes {
+    /// Reads them from the project's `/pypi/{project}/json` document.
+    /// Files with a malformed upload time are left out.
+    pub fn parse(json: &[u8]) -> Result<Self, String> {
+        let project = serde_json::from_slice::<Project>(json)
+            .map_err(|e| format!("invalid project metadata: {e}"))?;
+        Ok(Self(
+            project
+                .releases
+                .into_values()
+                .flatten()
+                .filter_map(|file| {
+                    let upload_time = humantime::parse_rfc3339(&file.upload_time_iso_8601?).ok()?;
+                    Some((file.filename, upload_time))
+                })
+                .collect(),
+        ))
+    }
+
+    pub fn get(&self, filename: &str) -> Option<SystemTime> {
+        self.0.get(filename).copied()
+    }
+
+    /// How many files were uploaded by `time`.
+    pub fn count_before(&self, time: SystemTime) -> usize {
+        self.0
+            .values()
+            .filter(|upload_time| **upload_time <= time)
+            .count()
+    }
+}
+
+/// What PyPI's JSON API says about how established a project is.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Reputation {
+    /// When its first file was uploaded, if any of them say.
+    pub first_upload: Option<SystemTime>,
+    /// How many versions it has files of.
+    pub releases: usize,
+    /// The source repository it links to, if it does.
+    pub repository: Option<String>,
+}
+
+impl Reputation {
+    pub fn parse(json: &[u8]) -> Result<Self, String> {
+        let project = serde_json::from_slice::<Project>(json)
+            .map_err(|e| format!("invalid project metadata: {e}"))?;
+
+        let first_upload = project
+            .releases
+            .values()
+            .flatten()
+            .filter_map(|file| humantime::parse_rfc3339(file.upload_time_iso_8601.as_ref()?).ok())
+            .min();
+        let releases = project
+            .releases
+            .values()
+            .filter(|files| !files.is_empty())
+            .count();
+
+        let repository = project
+            .info
+            .project_urls
+            .unwrap_or_default()
+            .into_iter()
+            .chain(project.info.home_page.map(|url| (String::new(), url)))
+            .find(|(label, url)| {
+                let host = Url::parse(url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+                REPOSITORY_LABELS.contains(&label.to_ascii_lowercase().as_str())
+                    || host.is_some_and(|host| {
+                        FORGES
+                            .iter()
+                            .any(|forge| host == *forge || host.ends_with(&format!(".{forge}")))
+                    })
+            })
+            .map(|(_, url)| url);
+        Ok(Self {
+            first_upload,
+            releases,
+            repository,
+        })
+    }
+}
+
+/// The licenses a project is under, going by its latest release in the JSON API:
+/// the identifiers in its license expression, or else its license when that's a single line,
+/// along with the licenses its trove classifiers name, e.g. `MIT License`.
+pub fn licenses(json: &[u8]) -> Result<Vec<String>, String> {
+    let info = serde_json::from_slice::<Project>(json)
+        .map_err(|e| format!("invalid project metadata: {e}"))?
+        .info;
+
+    let mut licenses = vec![];
+    match (&info.license_expression, &info.license) {
+        (Some(expression), _) => {
+            for identifier in expression
+                .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .filter(|identifier| !["", "AND", "OR", "WITH"].contains(identifier))
+            {
+                licenses.push(identifier.to_owned());
+            }
+        }
+        (None, Some(license)) => {
+            let license = license.trim();
+            // setuptools writes `UNKNOWN` when there's none
+            if !license.is_empty() && !license.contains('\n') && license != "UNKNOWN" {
+                licenses.push(license.to_owned());
+            }
+        }
+        (None, None) => {}
+    }
+    for classifier in info.classifiers.iter() {
+        if let Some(license) = classifier.strip_prefix("License :: ") {
+            let license = license.rsplit(" :: ").next().unwrap_or(license);
+            if license != "OSI Approved" && !licenses.iter().any(|known| known == license) {
+                licenses.push(license.to_owned());
+            }
+        }
+    }
+    Ok(licenses)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json = br#"{
+            "info": {"name": "six"},
+            "releases": {
+                "1.16.0": [
+                    {"filename": "six-1.16.0.tar.gz", "upload_time_iso_8601": "2021-05-05T14:18:18.379898Z"},
+                    {"filename": "six-1.16.0-py2.py3-none-any.whl", "upload_time_iso_8601": "2021-05-05T14:18:17Z"}
+                ],
+                "1.15.0": [
+                    {"filename": "six-1.15.0.tar.gz", "upload_time_iso_8601": "yesterday"}
+                ]
+            }
+        }"#;
+        let upload_times = UploadTimes::parse(json).unwrap();
+
+        let uploaded = SystemTime::UNIX_EPOCH + Duration::from_secs(1620224297);
+        assert_eq!(
+            upload_times.get("six-1.16.0-py2.py3-none-any.whl"),
+            Some(uploaded)
+        );
+        assert_eq!(upload_times.get("six-1.15.0.tar.gz"), None);
+        assert_eq!(upload_times.count_before(uploaded), 1);
+        assert_eq!(
+            upload_times.count_before(uploaded + Duration::from_secs(2)),
+            2
+        );
+
+        assert!(UploadTimes::parse(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_reputation() {
+        let json = br#"{
+            "info": {"home_page": "https://example.com", "project_urls": {"Homepage": "https://example.com", "Tracker": "https://GitHub.com/acme/thing/issues"}},
+            "releases": {
+                "0.1": [{"filename": "thing-0.1.tar.gz", "upload_time_iso_8601": "2024-01-02T00:00:00Z"}],
+                "0.2": [
+                    {"filename": "thing-0.2.tar.gz", "upload_time_iso_8601": "2024-01-01T00:00:00Z"},
+                    {"filename": "thing-0.2-py3-none-any.whl", "upload_time_iso_8601": null}
+                ],
+                "0.3": []
+            }
+        }"#;
+        assert_eq!(
+            Reputation::parse(json).unwrap(),
+            Reputation {
+                first_upload: humantime::parse_rfc3339("2024-01-01T00:00:00Z").ok(),
+                releases: 2,
+                repository: Some("https://GitHub.com/acme/thing/issues".to_owned()),
+            }
+        );
+
+        let json = br#"{"info": {"project_urls": {"Source": "https://git.example.com/thing"}}}"#;
+        assert_eq!(
+            Reputation::parse(json).unwrap().repository,
+            Some("https://git.example.com/thing".to_owned())
+        );
+        let json = br#"{"info": {"home_page": "https://example.com", "project_urls": null}, "releases": {}}"#;
+        assert_eq!(Reputation::parse(json).unwrap(), Reputation::default());
+    }
+
+    #[test]
+    fn test_licenses() {
+        let json = br#"{
+            "info": {
+                "license_expression": "(MIT OR Apache-2.0) AND GPL-2.0-only WITH Classpath-exception-2.0",
+                "license": "Dual licensed",
+                "classifiers": ["License :: OSI Approved :: MIT License", "License :: OSI Approved", "Programming Language :: Python"]
+            },
+            "releases": {}
+        }"#;
+        assert_eq!(
+            licenses(json).unwrap(),
+            vec![
+                "MIT",
+                "Apache-2.0",
+                "GPL-2.0-only",
+                "Classpath-exception-2.0",
+                "MIT License"
+            ],
+        );
+
+        let json = br#"{"info": {"license": "Copyright (c) 2024\n\nPermission is hereby granted...", "classifiers": []}}"#;
+        assert_eq!(licenses(json).unwrap(), Vec::<String>::new());
+        let json = br#"{"info": {"license": " AGPL-3.0 ", "classifiers": []}}"#;
+        assert_eq!(licenses(json).unwrap(), vec!["AGPL-3.0"]);
+        let json = br#"{"info": {"license": "UNKNOWN"}}"#;
+        assert_eq!(licenses(json).unwrap(), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,134 @@
+// reference: https://pip.pypa.io/en/stable/reference/requirements-file-format/
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{pep_440::SpecifierSet, pep_503};
+
+/// A requirement on a package from the index, as listed in a requirements or constraints file.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Requirement {
+    pub name: String,
+    pub specifier_set: SpecifierSet,
+}
+
+/// Parses the requirements in a requirements (or constraints) file.
+///
+/// Options (e.g. `-r other.txt` or `--index-url ...`), environment markers,
+/// and anything which isn't installed from an index (local paths, URLs, VCS links)
+/// are skipped, since there's nothing for us to fetch for them.
+pub fn parse(contents: &str) -> Result<Vec<Requirement>, String> {
+    lazy_static! {
+        static ref REQUIREMENT_RE: Regex =
+            Regex::new(r"^(?P<name>[A-Za-z0-9][A-Za-z0-9._-]*)\s*(\[[^\]]*\])?\s*(?P<rest>.*)$")
+                .unwrap();
+        static ref COMMENT_RE: Regex = Regex::new(r"(^|\s)#.*$").unwrap();
+        // per-requirement options, e.g. pip-compile's --hash=sha256:...
+        static ref OPTION_RE: Regex = Regex::new(r"\s--.*$").unwrap();
+    }
+
+    let mut requirements = vec![];
+    for (number, line) in contents.replace("\\\n", "").lines().enumerate() {
+        let line = COMMENT_RE.replace(line, "");
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('-') || line.contains("://") {
+            continue;
+        }
+
+        // environment markers describe where a requirement applies,
+        // but we serve every environment
+        let line = line.split(';').next().unwrap_or_default().trim();
+        let line = OPTION_RE.replace(line, "");
+        let line = line.trim();
+        let captures = if let Some(captures) = REQUIREMENT_RE.captures(line) {
+            captures
+        } else {
+            // e.g. ./some/local/package
+            continue;
+        };
+        let name = &captures["name"];
+        let rest = captures["rest"].trim();
+        if rest.starts_with('@') {
+            // a direct reference, which bypasses the index
+            continue;
+        }
+        if !pep_503::is_valid_project_name(name) {
+            return Err(format!(
+                "line {}: invalid project name `{name}`",
+                number + 1
+            ));
+        }
+
+        let specifiers = rest
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split_whitespace()
+            .collect::<String>();
+        requirements.push(Requirement {
+            name: name.to_owned(),
+            specifier_set: SpecifierSet::parse_strict(&specifiers)
+                .map_err(|e| format!("line {}: {e}", number + 1))?,
+        });
+    }
+    Ok(requirements)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn make_requirement(name: &str, specifiers: &str) -> Requirement {
+        Requirement {
+            name: name.to_string(),
+            specifier_set: SpecifierSet::from_str(specifiers).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let requirements = parse(
+            r#"# pinned for CI
+-r base.txt
+--index-url https://pypi.example.com/simple/
+numpy==1.26.0  # the one with wheels for 3.12
+requests[socks] >= 2.31, < 3
+protobuf (<5)
+pywin32>=306; sys_platform == "win32"
+scipy \
+    >=1.11
+attrs==23.2.0 \
+    --hash=sha256:abc123 \
+    --hash=sha256:def456
+acme @ https://example.com/acme-1.0.tar.gz
+./vendored/package
+git+https://github.com/crockeo/pyproxide
+six
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            requirements,
+            vec![
+                make_requirement("numpy", "==1.26.0"),
+                make_requirement("requests", ">=2.31,<3"),
+                make_requirement("protobuf", "<5"),
+                make_requirement("pywin32", ">=306"),
+                make_requirement("scipy", ">=1.11"),
+                make_requirement("attrs", "==23.2.0"),
+                make_requirement("six", ""),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(
+            parse("numpy\nscipy bar\n"),
+            Err("line 2: could not match version str: `bar`".to_string()),
+        );
+    }
+}
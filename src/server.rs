@@ -0,0 +1,340 @@
+use std::{
+    convert::Infallible,
+    fs::File,
+    io::BufReader,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use hyper::{
+    server::conn::Http,
+    service::{service_fn, Service},
+    Body, HeaderMap, Request, Response,
+};
+use ipnet::IpNet;
+use log::{debug, warn};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    time::Instant,
+};
+use tokio_rustls::{rustls, TlsAcceptor};
+
+use crate::forwarded;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+    /// Serves HTTPS instead of plain HTTP when set.
+    pub tls: Option<TlsConfig>,
+    /// Whether HTTP/1.1 connections are kept open between requests.
+    pub keep_alive: bool,
+    /// How long a connection may sit without a request in flight before it's closed.
+    #[serde(with = "humantime_serde")]
+    pub idle_timeout: Duration,
+    /// Whether HTTP/2 is offered, through ALPN with TLS or with prior knowledge without it.
+    pub http2: bool,
+    pub max_concurrent_streams: u32,
+    /// Canonical URL the proxy is reached at, e.g. `https://pypi.internal.example.com`.
+    /// When set, every link and redirect is generated against it,
+    /// no matter how the request arrived.
+    pub external_url: Option<String>,
+    /// Frontends (addresses or CIDR ranges) whose `Forwarded`/`X-Forwarded-*` headers are honored
+    /// when generating links and redirects.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Bearer token the `/admin/` API, `/-/warm` and `/-/freeze` have to be called with.
+    /// They're all disabled without one.
+    pub admin_token: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: ([127, 0, 0, 1], 8080).into(),
+            tls: None,
+            keep_alive: true,
+            idle_timeout: Duration::from_secs(60),
+            http2: true,
+            max_concurrent_streams: 100,
+            external_url: None,
+            trusted_proxies: vec![],
+            admin_token: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The base that links and redirects served in response to a request are generated against.
+    /// Empty when they should stay relative to the host the client used.
+    pub fn external_base(&self, remote: Option<SocketAddr>, headers: &HeaderMap) -> String {
+        if let Some(external_url) = &self.external_url {
+            return external_url.trim_end_matches('/').to_owned();
+        }
+
+        match remote {
+            Some(remote) if forwarded::is_trusted(&self.trusted_proxies, remote.ip()) => {
+                forwarded::external_origin(headers).unwrap_or_default()
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Who a request came from: the client's address,
+    /// or the one a trusted frontend says it forwarded the request for.
+    pub fn client(&self, remote: Option<SocketAddr>, headers: &HeaderMap) -> Option<String> {
+        let remote = remote?;
+        if forwarded::is_trusted(&self.trusted_proxies, remote.ip()) {
+            if let Some(client) = forwarded::client(headers) {
+                return Some(client);
+            }
+        }
+        Some(remote.ip().to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// PEM file with the private key.
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    fn acceptor(&self, http2: bool) -> Result<TlsAcceptor, String> {
+        let read_pem = |path: &PathBuf| -> Result<Vec<rustls_pemfile::Item>, String> {
+            let file =
+                File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+            rustls_pemfile::read_all(&mut BufReader::new(file))
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))
+        };
+
+        let certs = read_pem(&self.cert)?
+            .into_iter()
+            .filter_map(|item| match item {
+                rustls_pemfile::Item::X509Certificate(cert) => Some(rustls::Certificate(cert)),
+                _ => None,
+            })
+            .collect::<Vec<rustls::Certificate>>();
+        let key = read_pem(&self.key)?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| format!("no private key in {}", self.key.display()))?;
+
+        let mut config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("invalid TLS certificate: {e}"))?;
+        config.alpn_protocols = if http2 {
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        } else {
+            vec![b"http/1.1".to_vec()]
+        };
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// The address of the client a request came from, as a request extension.
+/// warp can't know it, since it doesn't own the listener.
+#[derive(Clone, Copy, Debug)]
+pub struct RemoteAddr(pub SocketAddr);
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// Tracks whether a connection is in use, so that idle ones can be closed.
+struct Activity {
+    in_flight: AtomicUsize,
+    last_active: Mutex<Instant>,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            in_flight: AtomicUsize::new(0),
+            last_active: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn start(self: &Arc<Self>) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.clone())
+    }
+
+    /// When the connection will have been idle for `idle_timeout`, unless something happens first.
+    fn idle_deadline(&self, idle_timeout: Duration) -> Instant {
+        if self.in_flight.load(Ordering::SeqCst) > 0 {
+            Instant::now() + idle_timeout
+        } else {
+            *self.last_active.lock().unwrap() + idle_timeout
+        }
+    }
+}
+
+struct InFlight(Arc<Activity>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        *self.0.last_active.lock().unwrap() = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub async fn serve<S>(config: &ServerConfig, service: S) -> Result<(), String>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let listener = TcpListener::bind(config.listen)
+        .await
+        .map_err(|e| format!("failed to listen on {}: {e}", config.listen))?;
+    serve_listener(config, listener, service).await
+}
+
+async fn serve_listener<S>(
+    config: &ServerConfig,
+    listener: TcpListener,
+    service: S,
+) -> Result<(), String>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let acceptor = match &config.tls {
+        Some(tls) => Some(tls.acceptor(config.http2)?),
+        None => None,
+    };
+
+    let mut http = Http::new();
+    http.http1_keep_alive(config.keep_alive)
+        .http2_max_concurrent_streams(config.max_concurrent_streams);
+    if !config.http2 {
+        http.http1_only(true);
+    }
+
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+
+        let http = http.clone();
+        let acceptor = acceptor.clone();
+        let service = service.clone();
+        let idle_timeout = config.idle_timeout;
+        tokio::spawn(async move {
+            let io: Box<dyn Io> = match acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => Box::new(stream),
+                    Err(e) => {
+                        debug!("TLS handshake with {remote} failed: {e}");
+                        return;
+                    }
+                },
+                None => Box::new(stream),
+            };
+
+            let activity = Arc::new(Activity::new());
+            let service = {
+                let activity = activity.clone();
+                service_fn(move |mut req: Request<Body>| {
+                    req.extensions_mut().insert(RemoteAddr(remote));
+                    let in_flight = activity.start();
+                    let mut service = service.clone();
+                    async move {
+                        let res = service.call(req).await;
+                        drop(in_flight);
+                        res
+                    }
+                })
+            };
+
+            let conn = http.serve_connection(io, service);
+            tokio::pin!(conn);
+            let mut closing = false;
+            loop {
+                let idle_deadline = activity.idle_deadline(idle_timeout);
+                tokio::select! {
+                    result = conn.as_mut() => {
+                        if let Err(e) = result {
+                            debug!("connection from {remote} failed: {e}");
+                        }
+                        return;
+                    }
+                    _ = tokio::time::sleep_until(idle_deadline), if !closing => {
+                        if activity.idle_deadline(idle_timeout) <= Instant::now() {
+                            // lets anything still being written finish first
+                            conn.as_mut().graceful_shutdown();
+                            closing = true;
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_idle_connections_are_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ServerConfig {
+            idle_timeout: Duration::from_millis(200),
+            ..ServerConfig::default()
+        };
+        let service = service_fn(|req: Request<Body>| async move {
+            let remote = req.extensions().get::<RemoteAddr>().unwrap().0;
+            Ok::<_, Infallible>(Response::new(Body::from(remote.ip().to_string())))
+        });
+        tokio::spawn(async move { serve_listener(&config, listener, service).await });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        let response = String::from_utf8_lossy(&buf[..n]);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("127.0.0.1"));
+
+        // kept alive for a bit, then closed once it's been idle for too long
+        let read = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf)).await;
+        assert_eq!(read.unwrap().unwrap(), 0);
+    }
+}
@@ -0,0 +1,112 @@
+use std::{collections::HashMap, future::Future, hash::Hash, sync::Mutex};
+
+use tokio::sync::broadcast;
+
+/// Coalesces concurrent calls for the same key,
+/// so that only the first one does the work and everyone else shares its result.
+///
+/// If the call doing the work is cancelled, its followers each fall back to doing it themselves.
+pub struct SingleFlight<K, V> {
+    in_flight: Mutex<HashMap<K, broadcast::Sender<V>>>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn run<F: Future<Output = V>>(&self, key: K, f: F) -> V {
+        let receiver = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(sender) => Some(sender.subscribe()),
+                None => {
+                    in_flight.insert(key.clone(), broadcast::channel(1).0);
+                    None
+                }
+            }
+        };
+        if let Some(mut receiver) = receiver {
+            return match receiver.recv().await {
+                Ok(value) => value,
+                Err(_) => f.await,
+            };
+        }
+
+        let flight = Flight {
+            in_flight: &self.in_flight,
+            key,
+        };
+        let value = f.await;
+        let sender = flight.in_flight.lock().unwrap().remove(&flight.key);
+        if let Some(sender) = sender {
+            // nobody listening is fine
+            let _ = sender.send(value.clone());
+        }
+        value
+    }
+}
+
+/// Makes sure a leader which is dropped mid-flight doesn't leave its key behind,
+/// which would make every later call for it wait forever.
+struct Flight<'a, K: Eq + Hash, V> {
+    in_flight: &'a Mutex<HashMap<K, broadcast::Sender<V>>>,
+    key: K,
+}
+
+impl<K: Eq + Hash, V> Drop for Flight<'_, K, V> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use futures_util::future::join_all;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_result() {
+        let single_flight = SingleFlight::new();
+        let calls = AtomicU32::new(0);
+
+        let results = join_all((0..10).map(|_| {
+            single_flight.run("numpy/", async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                calls.fetch_add(1, Ordering::SeqCst) + 1
+            })
+        }))
+        .await;
+
+        assert_eq!(results, vec![1; 10]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // finished flights aren't reused
+        let result = single_flight.run("numpy/", async { 2 }).await;
+        assert_eq!(result, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_leader() {
+        let single_flight = SingleFlight::new();
+
+        let leader = tokio::time::timeout(
+            Duration::from_millis(10),
+            single_flight.run("numpy/", std::future::pending()),
+        );
+        let follower = single_flight.run("numpy/", async { 1 });
+
+        let (leader, follower) = tokio::join!(leader, follower);
+        assert!(leader.is_err());
+        assert_eq!(follower, 1);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use hyper::{body::Bytes, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, Syntax},
+    fetch_from_indexes, filtered_package_index, package_config,
+    pep_503::{self, Release},
+    upstream::Upstream,
+    ParsedPackageIndexes,
+};
+
+/// The index as it was served at some point, kept under a name,
+/// e.g. so that an LTS branch always resolves the same way.
+///
+/// Packages captured in it are served exactly as they were, for as long as they're served at all,
+/// and every other package as of `taken_at`, like with `X-PyProxide-Snapshot`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot {
+    #[serde(with = "humantime_serde")]
+    pub taken_at: SystemTime,
+    /// The releases which were served for each package captured in it, by normalized name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub packages: BTreeMap<String, Vec<Release>>,
+}
+
+/// Whether `name` can name a snapshot: the same characters as a project name,
+/// since it ends up in both file names and URLs.
+pub fn is_valid_name(name: &str) -> bool {
+    pep_503::is_valid_project_name(name)
+}
+
+fn path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.json"))
+}
+
+/// The snapshot called `name` in `dir`, if there is one.
+pub fn load(dir: &Path, name: &str) -> Result<Option<Snapshot>, String> {
+    let path = path(dir, name);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => config::parse(&path, &contents, Syntax::Json).map(Some),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("failed to read {}: {e}", path.display())),
+    }
+}
+
+/// Every snapshot in `dir`, by name.
+pub fn list(dir: &Path) -> Result<BTreeMap<String, Snapshot>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(format!("failed to read {}: {e}", dir.display())),
+    };
+
+    let mut snapshots = BTreeMap::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("failed to read {}: {e}", dir.display()))?;
+        let file_name = entry.file_name();
+        let name = match file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".json"))
+        {
+            Some(name) if is_valid_name(name) => name.to_owned(),
+            _ => continue,
+        };
+        if let Some(snapshot) = load(dir, &name)? {
+            snapshots.insert(name, snapshot);
+        }
+    }
+    Ok(snapshots)
+}
+
+/// Saves `snapshot` as `name` in `dir`, returning whether it was,
+/// since snapshots are never replaced: one can't change out from under whatever resolves against it.
+pub fn save(dir: &Path, name: &str, snapshot: &Snapshot) -> Result<bool, String> {
+    let path = path(dir, name);
+    if path.exists() {
+        return Ok(false);
+    }
+    std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    package_config::write(&path, &config::render(snapshot, Syntax::Json)?)?;
+    Ok(true)
+}
+
+/// Removes the snapshot called `name` from `dir`, returning whether there was one.
+pub fn remove(dir: &Path, name: &str) -> Result<bool, String> {
+    let path = path(dir, name);
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(format!("failed to remove {}: {e}", path.display())),
+    }
+}
+
+/// The releases of `package` which are served right now, to keep in a snapshot.
+pub async fn capture(
+    upstream: &Upstream,
+    parsed: &ParsedPackageIndexes,
+    package: &str,
+) -> Result<Vec<Release>, String> {
+    let mut package_config = upstream.package_config(package).await;
+    if let Some(snapshot) = upstream.config().snapshot {
+        package_config = Some(package_config.unwrap_or_default().at(snapshot));
+    }
+    if let Some(reason) = package_config
+        .as_ref()
+        .filter(|config| !config.is_audit())
+        .and_then(|config| config.blocked.as_ref())
+    {
+        return Err(format!("blocked: {reason}"));
+    }
+
+    let indexes = upstream.indexes_for(package);
+    let path = format!("{package}/");
+    let (res, pages) =
+        fetch_from_indexes(upstream, &indexes, &path, HeaderMap::new(), Bytes::new()).await;
+    if pages.is_empty() {
+        return Err(format!("index page returned {}", res.status()));
+    }
+    if package_config
+        .as_ref()
+        .is_some_and(|config| config.pass_through)
+        || !pages.iter().any(|page| page.html().is_some())
+    {
+        return Err("passed through as is, so there are no releases to keep".to_owned());
+    }
+
+    let package_index =
+        filtered_package_index(upstream, parsed, package, &pages, package_config, false).await;
+    Ok(package_index.releases.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_save() {
+        let dir = std::env::temp_dir().join(format!("pyproxide-snapshots-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let snapshot = Snapshot {
+            taken_at: humantime::parse_rfc3339("2024-06-01T00:00:00Z").unwrap(),
+            packages: BTreeMap::from([(
+                "six".to_owned(),
+                vec![Release {
+                    name: "six-1.16.0.tar.gz".to_owned(),
+                    uri: "https://files.example.com/six-1.16.0.tar.gz#sha256=abc123".to_owned(),
+                    has_gpg: false,
+                    requires_python: Some(">=2.7".to_owned()),
+                    yanked: None,
+                    upload_time: None,
+                    vulnerabilities: vec![],
+                    licenses: vec![],
+                    filtered: None,
+                }],
+            )]),
+        };
+        assert_eq!(load(&dir, "lts-1").unwrap(), None);
+        assert!(save(&dir, "lts-1", &snapshot).unwrap());
+        assert_eq!(load(&dir, "lts-1").unwrap(), Some(snapshot.clone()));
+        let replacement = Snapshot {
+            packages: BTreeMap::new(),
+            ..snapshot.clone()
+        };
+        assert!(!save(&dir, "lts-1", &replacement).unwrap());
+        assert_eq!(load(&dir, "lts-1").unwrap(), Some(snapshot.clone()));
+        assert_eq!(
+            list(&dir).unwrap(),
+            BTreeMap::from([("lts-1".to_owned(), snapshot)])
+        );
+
+        assert!(remove(&dir, "lts-1").unwrap());
+        assert!(!remove(&dir, "lts-1").unwrap());
+        assert_eq!(list(&dir).unwrap(), BTreeMap::new());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use crate::pep_503;
+
+/// How many edits it takes to turn `a` into `b`: characters inserted, removed, replaced,
+/// or swapped with the one next to them, as in `reqeusts`.
+pub fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    // rows[i][j] is the distance between the first i characters of a and the first j of b
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replace = rows[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut best = replace.min(rows[i - 1][j] + 1).min(rows[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The name in `known` which `package` is most likely a typosquat of:
+/// the nearest one within `max_distance` edits, if `package` isn't one of them itself.
+/// Names are compared normalized, so `Foo_Bar` is no typosquat of `foo-bar`.
+pub fn target<'a>(
+    package: &str,
+    known: impl IntoIterator<Item = &'a str>,
+    max_distance: usize,
+) -> Option<&'a str> {
+    let package = pep_503::normalize(package);
+    let mut nearest = None;
+    for name in known {
+        let distance = distance(&package, &pep_503::normalize(name));
+        if distance == 0 {
+            return None;
+        }
+        if distance <= max_distance && nearest.is_none_or(|(_, nearest)| distance < nearest) {
+            nearest = Some((name, distance));
+        }
+    }
+    nearest.map(|(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("requests", "requests"), 0);
+        assert_eq!(distance("reqeusts", "requests"), 1);
+        assert_eq!(distance("request", "requests"), 1);
+        assert_eq!(distance("requestss", "requests"), 1);
+        assert_eq!(distance("rewuests", "requests"), 1);
+        assert_eq!(distance("numpy", "numpi"), 1);
+        assert_eq!(distance("", "six"), 3);
+        assert_eq!(distance("django", "flask"), 5);
+    }
+
+    #[test]
+    fn test_target() {
+        let known = ["requests", "numpy", "python-dateutil"];
+        assert_eq!(target("reqeusts", known, 1), Some("requests"));
+        assert_eq!(
+            target("python_dateutils", known, 1),
+            Some("python-dateutil")
+        );
+        assert_eq!(target("Requests", known, 1), None);
+        assert_eq!(target("numpyy", known, 0), None);
+        assert_eq!(target("flask", known, 2), None);
+        // nothing is a typosquat of a name it is itself
+        assert_eq!(target("numpy", ["numpy", "numpi"], 1), None);
+    }
+}
@@ -0,0 +1,181 @@
+use futures_util::{stream, StreamExt};
+use hyper::{
+    body::{Bytes, HttpBody},
+    HeaderMap, Method,
+};
+use serde::Serialize;
+
+use crate::{
+    fetch_from_indexes, filtered_package_index, pep_440::Version, requirements::Requirement,
+    upstream::Upstream, ParsedPackageIndexes,
+};
+
+/// How many packages are warmed at once.
+const CONCURRENCY: usize = 8;
+
+#[derive(Debug, Default, Serialize)]
+pub struct WarmReport {
+    pub warmed: Vec<Warmed>,
+    pub failed: Vec<Failed>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Warmed {
+    pub package: String,
+    /// The version whose files were fetched, if any matched.
+    pub version: Option<String>,
+    pub files: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Failed {
+    pub package: String,
+    pub error: String,
+}
+
+/// Fetches the index page for each requirement into the cache,
+/// along with every file of the newest version which satisfies it,
+/// which is the version pip would pick.
+///
+/// Files are only fetched when there's an artifact cache to put them in,
+/// and when they're downloaded through the proxy (i.e. they're on the files host).
+pub async fn warm(
+    upstream: &Upstream,
+    parsed: &ParsedPackageIndexes,
+    requirements: Vec<Requirement>,
+) -> WarmReport {
+    let results = stream::iter(requirements)
+        .map(|requirement| warm_package(upstream, parsed, requirement))
+        .buffer_unordered(CONCURRENCY)
+        .collect::<Vec<Result<Warmed, Failed>>>()
+        .await;
+
+    let mut report = WarmReport::default();
+    for result in results {
+        match result {
+            Ok(warmed) => report.warmed.push(warmed),
+            Err(failed) => report.failed.push(failed),
+        }
+    }
+    report
+}
+
+async fn warm_package(
+    upstream: &Upstream,
+    parsed: &ParsedPackageIndexes,
+    requirement: Requirement,
+) -> Result<Warmed, Failed> {
+    let package = requirement.name;
+    let failed = |error: String| Failed {
+        package: package.clone(),
+        error,
+    };
+
+    let mut package_config = upstream.package_config(&package).await;
+    if let Some(snapshot) = upstream.config().snapshot {
+        package_config = Some(package_config.unwrap_or_default().at(snapshot));
+    }
+    if let Some(reason) = package_config
+        .as_ref()
+        .filter(|config| !config.is_audit())
+        .and_then(|config| config.blocked.as_ref())
+    {
+        return Err(failed(format!("blocked: {reason}")));
+    }
+
+    let indexes = upstream.indexes_for(&package);
+    let path = format!("{package}/");
+    let (res, pages) =
+        fetch_from_indexes(upstream, &indexes, &path, HeaderMap::new(), Bytes::new()).await;
+    if pages.is_empty() {
+        return Err(failed(format!("index page returned {}", res.status())));
+    }
+
+    if package_config
+        .as_ref()
+        .is_some_and(|config| config.pass_through)
+        || !pages.iter().any(|page| page.html().is_some())
+    {
+        // nothing we can read files out of
+        return Ok(Warmed {
+            package,
+            version: None,
+            files: 0,
+        });
+    }
+    let package_index =
+        filtered_package_index(upstream, parsed, &package, &pages, package_config, false).await;
+
+    let mut newest: Option<Version> = None;
+    for release in package_index.releases.iter() {
+        // pip passes over yanked files, short of being pinned to exactly them
+        if release.yanked.is_some() {
+            continue;
+        }
+        let version = if let Some(version) = release.version() {
+            version
+        } else {
+            continue;
+        };
+        if !requirement.specifier_set.contains(&version) {
+            continue;
+        }
+        let is_newer = match &newest {
+            None => true,
+            Some(newest) => &version > newest,
+        };
+        if is_newer {
+            newest = Some(version);
+        }
+    }
+    let newest = if let Some(newest) = newest {
+        newest
+    } else {
+        return Ok(Warmed {
+            package,
+            version: None,
+            files: 0,
+        });
+    };
+
+    let mut files = 0;
+    if upstream.caches_artifacts() {
+        let files_url = format!("{}/packages/", upstream.config().files_url);
+        for release in package_index.releases.iter() {
+            if release.version().as_ref() != Some(&newest) {
+                continue;
+            }
+            let path = if let Some(path) = release.uri.strip_prefix(&files_url) {
+                // the hash fragment is only for pip
+                path.split('#').next().unwrap_or_default()
+            } else {
+                continue;
+            };
+
+            let res = upstream
+                .forward_artifact(path, Method::GET, HeaderMap::new(), Bytes::new())
+                .await;
+            if !res.status().is_success() {
+                return Err(failed(format!(
+                    "{} returned {}",
+                    release.name,
+                    res.status()
+                )));
+            }
+            // the artifact cache picks the file up as it's read
+            let mut body = res.into_body();
+            while let Some(chunk) = body.data().await {
+                if let Err(e) = chunk {
+                    return Err(failed(format!("failed to download {}: {e}", release.name)));
+                }
+            }
+            files += 1;
+        }
+    }
+
+    Ok(Warmed {
+        package,
+        version: Some(newest.to_string()),
+        files,
+    })
+}